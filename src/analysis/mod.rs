@@ -0,0 +1,11 @@
+//! Quantitative measures of a tree skeleton, for comparing generated trees.
+//!
+//! [`TreeStats`] gathers sizes, branching angles and stream orders of a
+//! [`NodeGraph`](crate::skeleton::node::NodeGraph) in one serializable report. The stream
+//! orders are also available on their own through [`strahler_orders`] and [`horton_orders`].
+
+pub mod orders;
+pub mod stats;
+
+pub use orders::{horton_orders, strahler_orders};
+pub use stats::{AngleHistogram, TreeStats};
@@ -0,0 +1,104 @@
+use crate::skeleton::node::NodeGraph;
+
+/// Returns the Strahler order of every node, indexed like `graph.nodes`.
+///
+/// Tips have order 1. A node whose children share the highest order `k` has order `k + 1`;
+/// otherwise it takes the highest order of its children. A chain of single children keeps
+/// one order, so only forks can raise it.
+pub fn strahler_orders(graph: &NodeGraph) -> Vec<u32> {
+    let mut orders = vec![1; graph.nodes.len()];
+    // Children come after their parents, so walking backwards visits every child first.
+    for (index, node) in graph.nodes.iter().enumerate().rev() {
+        let mut highest = 0;
+        let mut highest_count = 0;
+        for &child in &node.children_indices {
+            match orders[child].cmp(&highest) {
+                std::cmp::Ordering::Greater => {
+                    highest = orders[child];
+                    highest_count = 1;
+                }
+                std::cmp::Ordering::Equal => highest_count += 1,
+                std::cmp::Ordering::Less => {}
+            }
+        }
+        if highest_count > 1 {
+            orders[index] = highest + 1;
+        } else if highest_count == 1 {
+            orders[index] = highest;
+        }
+    }
+    orders
+}
+
+/// Returns the Horton order of every node given their Strahler orders, indexed like
+/// `graph.nodes`.
+///
+/// Horton orders follow each stream from its base out to a tip instead of stopping at the
+/// fork where its Strahler order drops: at every fork the main stream continues into the
+/// child of the highest Strahler order, ties going to the child with the longest path to a
+/// tip, and keeps its order all the way out.
+pub fn horton_orders(graph: &NodeGraph, strahler: &[u32]) -> Vec<u32> {
+    let nodes = &graph.nodes;
+    let mut reach = vec![0.0; nodes.len()];
+    for (index, node) in nodes.iter().enumerate().rev() {
+        reach[index] = node.length
+            + node
+                .children_indices
+                .iter()
+                .map(|&child| reach[child])
+                .fold(0.0, f64::max);
+    }
+
+    let mut orders = strahler.to_vec();
+    for (index, node) in nodes.iter().enumerate() {
+        let main = node.children_indices.iter().copied().max_by(|&a, &b| {
+            strahler[a]
+                .cmp(&strahler[b])
+                .then(reach[a].total_cmp(&reach[b]))
+                .then(b.cmp(&a))
+        });
+        if let Some(main) = main {
+            orders[main] = orders[index];
+        }
+    }
+    orders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::tree::Tree;
+
+    /// A root forking into a tip and a branch that forks into two tips, the first longer.
+    fn fork_of_forks() -> Tree {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 0.0, 4.0);
+        let branch = tree.add_node(Some(root), 8.0, 0.5, 3.0);
+        tree.add_node(Some(root), 8.0, -0.8, 2.0);
+        tree.add_node(Some(branch), 6.0, 0.9, 1.0);
+        tree.add_node(Some(branch), 4.0, -0.2, 1.0);
+        tree
+    }
+
+    #[test]
+    fn strahler_orders_rise_at_forks_of_equal_orders() {
+        assert_eq!(strahler_orders(&fork_of_forks().nodes), vec![2, 2, 1, 1, 1]);
+    }
+
+    #[test]
+    fn chains_keep_their_order() {
+        let mut tree = Tree::new();
+        let mut parent = tree.add_node(None, 10.0, 0.0, 4.0);
+        for _ in 0..3 {
+            parent = tree.add_node(Some(parent), 5.0, 0.0, 2.0);
+        }
+        assert_eq!(strahler_orders(&tree.nodes), vec![1; 4]);
+    }
+
+    #[test]
+    fn horton_orders_follow_the_longest_main_stream() {
+        let graph = fork_of_forks().nodes;
+        let strahler = strahler_orders(&graph);
+        assert_eq!(horton_orders(&graph, &strahler), vec![2, 2, 1, 2, 1]);
+    }
+}
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    skeleton::node::NodeGraph,
+    utils::{geometry::angle_between, quadtree::BoundingBox},
+};
+
+use super::orders::{horton_orders, strahler_orders};
+
+/// Width of the bins of [`TreeStats::branching_angles`] in degrees.
+const BRANCHING_ANGLE_BIN_DEG: f64 = 10.0;
+
+/// Counts of angles between 0 and 180 degrees in bins of equal width.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AngleHistogram {
+    pub bin_width_deg: f64,
+    /// `counts[i]` is the number of angles from `i * bin_width_deg` up to the next bin.
+    pub counts: Vec<usize>,
+}
+
+impl AngleHistogram {
+    pub fn new(bin_width_deg: f64) -> Self {
+        Self {
+            bin_width_deg,
+            counts: vec![0; (180.0 / bin_width_deg).ceil() as usize],
+        }
+    }
+
+    /// Counts `angle_deg`, clamped to the range of the histogram.
+    pub fn add(&mut self, angle_deg: f64) {
+        let bin = (angle_deg / self.bin_width_deg).floor().max(0.0) as usize;
+        let last = self.counts.len() - 1;
+        self.counts[bin.min(last)] += 1;
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+}
+
+/// Size, shape and branching structure of a tree skeleton.
+///
+/// Lengths are in world units. Stream orders are indexed from order 1, so
+/// `stream_counts[0]` is the number of order 1 streams.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeStats {
+    pub node_count: usize,
+    pub tip_count: usize,
+    /// Largest number of ancestors of any node.
+    pub max_depth: usize,
+    /// Vertical distance from the root up to the highest segment end.
+    pub height: f64,
+    pub bounds: BoundingBox,
+    /// Horizontal extent of everything above the first fork of the trunk.
+    pub crown_width: f64,
+    /// Angles between every child of a fork and the segment it forks from.
+    pub branching_angles: AngleHistogram,
+    pub mean_branching_angle_deg: f64,
+    pub max_strahler_order: u32,
+    /// Number of Strahler streams of every order: chains of nodes that share an order.
+    pub stream_counts: Vec<usize>,
+    /// `bifurcation_ratios[k]` is the number of streams of order `k + 1` over the number of
+    /// order `k + 2`.
+    pub bifurcation_ratios: Vec<f64>,
+    pub mean_bifurcation_ratio: f64,
+    /// Summed length of all segments.
+    pub total_length: f64,
+    /// Summed `length * thickness` of all segments, the area the wood covers in a drawing
+    /// when overlaps are ignored.
+    pub wood_area: f64,
+    pub strahler_orders: Vec<u32>,
+    pub horton_orders: Vec<u32>,
+}
+
+impl TreeStats {
+    pub fn new(graph: &NodeGraph) -> Self {
+        let nodes = &graph.nodes;
+        let root = graph.root_position();
+
+        let height = nodes
+            .iter()
+            .flat_map(|node| [node.point.y, node.next_point().y])
+            .map(|y| root.y - y)
+            .fold(0.0, f64::max);
+
+        let mut branching_angles = AngleHistogram::new(BRANCHING_ANGLE_BIN_DEG);
+        let mut angle_sum = 0.0;
+        for node in nodes.iter().filter(|node| node.children_indices.len() > 1) {
+            for &child in &node.children_indices {
+                let angle = angle_between(node.angle, nodes[child].angle).to_degrees();
+                branching_angles.add(angle);
+                angle_sum += angle;
+            }
+        }
+        let angle_count = branching_angles.total();
+
+        let strahler = strahler_orders(graph);
+        let horton = horton_orders(graph, &strahler);
+        let max_strahler_order = strahler.iter().copied().max().unwrap_or(0);
+        let mut stream_counts = vec![0; max_strahler_order as usize];
+        for (index, node) in nodes.iter().enumerate() {
+            let starts_stream = node
+                .parent_index
+                .is_none_or(|parent| strahler[parent] != strahler[index]);
+            if starts_stream {
+                stream_counts[strahler[index] as usize - 1] += 1;
+            }
+        }
+        let bifurcation_ratios: Vec<f64> = stream_counts
+            .windows(2)
+            .map(|pair| pair[0] as f64 / pair[1] as f64)
+            .collect();
+        let mean_bifurcation_ratio =
+            mean(bifurcation_ratios.iter().sum(), bifurcation_ratios.len());
+
+        Self {
+            node_count: nodes.len(),
+            tip_count: nodes
+                .iter()
+                .filter(|node| node.children_indices.is_empty())
+                .count(),
+            max_depth: graph.depths().into_iter().max().unwrap_or(0),
+            height,
+            bounds: graph.bounds(),
+            crown_width: crown_width(graph),
+            branching_angles,
+            mean_branching_angle_deg: mean(angle_sum, angle_count),
+            max_strahler_order,
+            mean_bifurcation_ratio,
+            stream_counts,
+            bifurcation_ratios,
+            total_length: nodes.iter().map(|node| node.length).sum(),
+            wood_area: nodes.iter().map(|node| node.length * node.thickness).sum(),
+            strahler_orders: strahler,
+            horton_orders: horton,
+        }
+    }
+}
+
+/// Returns the horizontal extent of every segment except the unbranched base of the trunk.
+fn crown_width(graph: &NodeGraph) -> f64 {
+    let nodes = &graph.nodes;
+    let mut in_trunk = vec![false; nodes.len()];
+    let mut current = nodes.iter().position(|node| node.parent_index.is_none());
+    while let Some(index) = current {
+        in_trunk[index] = true;
+        current = match nodes[index].children_indices.as_slice() {
+            [only_child] => Some(*only_child),
+            _ => None,
+        };
+    }
+
+    let (min, max) = nodes
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| !in_trunk[index])
+        .flat_map(|(_, node)| [node.point.x, node.next_point().x])
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| {
+            (min.min(x), max.max(x))
+        });
+    (max - min).max(0.0)
+}
+
+fn mean(sum: f64, count: usize) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::tree::Tree;
+
+    #[test]
+    fn stats_of_a_fork_of_forks() {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 0.0, 4.0);
+        let branch = tree.add_node(Some(root), 8.0, 35f64.to_radians(), 3.0);
+        tree.add_node(Some(root), 8.0, (-47f64).to_radians(), 2.0);
+        tree.add_node(Some(branch), 6.0, 57f64.to_radians(), 1.0);
+        tree.add_node(Some(branch), 4.0, (-8f64).to_radians(), 1.0);
+        let stats = TreeStats::new(&tree.nodes);
+
+        assert_eq!(stats.node_count, 5);
+        assert_eq!(stats.tip_count, 3);
+        assert_eq!(stats.max_depth, 2);
+        assert_eq!(stats.max_strahler_order, 2);
+        assert_eq!(stats.stream_counts, vec![3, 1]);
+        assert_eq!(stats.bifurcation_ratios, vec![3.0]);
+        assert_eq!(stats.mean_bifurcation_ratio, 3.0);
+        assert_eq!(stats.branching_angles.counts[..5], [0, 0, 1, 1, 2]);
+        assert!((stats.mean_branching_angle_deg - 36.75).abs() < 1e-9);
+        assert_eq!(stats.total_length, 36.0);
+        assert_eq!(stats.wood_area, 40.0 + 24.0 + 16.0 + 6.0 + 4.0);
+        assert!(stats.height > 10.0 && stats.height < 10.0 + 8.0 + 6.0);
+    }
+
+    #[test]
+    fn angle_histograms_clamp_to_their_range() {
+        let mut histogram = AngleHistogram::new(10.0);
+        for angle in [-5.0, 0.0, 95.0, 180.0, 400.0] {
+            histogram.add(angle);
+        }
+        assert_eq!(histogram.counts.len(), 18);
+        assert_eq!(histogram.counts[0], 2);
+        assert_eq!(histogram.counts[9], 1);
+        assert_eq!(histogram.counts[17], 2);
+        assert_eq!(histogram.total(), 5);
+    }
+}
@@ -1,24 +1,32 @@
 use std::{cell::RefCell, rc::Rc};
 
 use druid::{
-    widget::{Button, Checkbox, Flex, Split},
-    AppLauncher, UnitPoint, Widget, WidgetExt, WindowDesc,
+    widget::{Button, Checkbox, Flex, Label, Maybe, RadioGroup, Scroll, Split},
+    AppLauncher, Lens, Size, UnitPoint, Widget, WidgetExt, WindowDesc,
 };
 use image::{DynamicImage, RgbImage, Rgb};
+use palette::{Srgb, Srgba};
 use treegen::{
     generator::{
         branch_layer::{BranchLayer, BranchParams},
-        layer::Layer,
+        foliage_layer::{FoliageLayer, FoliageParams, Season},
+        param_curve::{CurveInput, ParamCurve},
+        pipeline::Pipeline,
+        presets::Preset,
+        tropism_layer::{TropismLayer, TropismParams},
         trunk_layer::{TrunkLayer, TrunkParams},
     },
-    gui::{AppData, DynamicImageWidget, UPDATE_IMAGE},
-    rendering::{PolygonRenderer, Renderer},
-    skeleton::{
-        node::{HEIGHT, WIDTH},
-        tree::Tree,
+    gui::{curve_editor::CurveEditor, AppData, DynamicImageWidget, UPDATE_IMAGE},
+    rendering::{
+        AntialiasedCanvas, Canvas, PolygonRenderer, Renderer, TaperedRenderer, Viewport,
     },
+    skeleton::tree::Tree,
+    utils::curve::{Curve, Interpolation},
 };
 
+const IMAGE_WIDTH: u32 = 400;
+const IMAGE_HEIGHT: u32 = 400;
+
 macro_rules! create_sliders {
     ($param_struct:ident, $param_field:ident, $label:expr, $($field:ident{$lo:expr, $hi:expr}),+ $(,)? ) => {{
         use druid::LensExt;
@@ -63,33 +71,210 @@ fn make_branch_sliders() -> impl Widget<AppData> {
         "Branch Params",
         spread{0.0,1.0},
         branch{0.0,1.0},
-        variability{0.0,1.0}
+        variability{0.0,1.0},
+        base_angle_mean_deg{0.0,90.0},
+        base_angle_std_dev_deg{0.0,30.0},
+        initial_length{1.0,50.0},
+        minimum_size{0.0,1.0},
+    )
+}
+
+fn make_tropism_sliders() -> impl Widget<AppData> {
+    create_sliders!(
+        TropismParams,
+        tropism_params,
+        "Tropism Params",
+        direction_deg{0.0,360.0},
+        strength{-20.0,20.0},
+        stiffness{0.0,4.0},
+        distance_exponent{0.0,4.0},
     )
 }
 
+fn make_foliage_sliders() -> impl Widget<AppData> {
+    use druid::LensExt;
+    let seasons = Season::ALL
+        .into_iter()
+        .map(|season| (format!("{season:?}"), season))
+        .collect::<Vec<_>>();
+    let season_radio =
+        RadioGroup::row(seasons).lens(AppData::foliage_params.then(FoliageParams::season));
+
+    create_sliders!(
+        FoliageParams,
+        foliage_params,
+        "Foliage Params",
+        density{0.0,10.0},
+        tip_depth{0.0,6.0},
+        leaf_length{1.0,20.0},
+        leaf_width{0.05,1.0},
+        orientation_deg{0.0,90.0},
+        color_variation{0.0,1.0},
+    )
+    .with_child(season_radio)
+}
+
+/// A checkbox that turns the curve behind `lens` on or off, and an editor for it while on.
+fn make_curve_row<L>(label: &'static str, lens: L) -> impl Widget<AppData>
+where
+    L: Lens<AppData, Option<ParamCurve>> + Clone + 'static,
+{
+    use druid::LensExt;
+    let enabled = Checkbox::new(label).lens(lens.clone().map(
+        |curve: &Option<ParamCurve>| curve.is_some(),
+        |curve: &mut Option<ParamCurve>, enabled: bool| {
+            if enabled != curve.is_some() {
+                *curve = enabled.then(ParamCurve::default);
+            }
+        },
+    ));
+    let editor = Maybe::or_empty(|| {
+        let inputs = RadioGroup::column([
+            ("depth", CurveInput::Depth),
+            ("height", CurveInput::Height),
+            ("distance", CurveInput::Distance),
+        ])
+        .lens(ParamCurve::input);
+        let interpolations = RadioGroup::column([
+            ("linear", Interpolation::Linear),
+            ("smooth", Interpolation::CatmullRom),
+        ])
+        .lens(ParamCurve::curve.then(druid::lens!(Curve, interpolation)));
+        Flex::row()
+            .with_child(CurveEditor::new(Size::new(160.0, 60.0)).lens(ParamCurve::curve))
+            .with_spacer(5.0)
+            .with_child(inputs)
+            .with_child(interpolations)
+    })
+    .lens(lens);
+
+    Flex::column()
+        .with_child(enabled.align_left())
+        .with_child(editor.align_left())
+}
+
+fn make_curve_editors() -> impl Widget<AppData> {
+    use druid::LensExt;
+    let col = Flex::column()
+        .with_child(Label::new("Curves").align_left())
+        .with_child(make_curve_row(
+            "trunk length",
+            AppData::trunk_params.then(TrunkParams::length_curve),
+        ))
+        .with_child(make_curve_row(
+            "trunk spread",
+            AppData::trunk_params.then(TrunkParams::spread_curve),
+        ))
+        .with_child(make_curve_row(
+            "trunk split",
+            AppData::trunk_params.then(TrunkParams::split_curve),
+        ))
+        .with_child(make_curve_row(
+            "trunk branch",
+            AppData::trunk_params.then(TrunkParams::branch_curve),
+        ))
+        .with_child(make_curve_row(
+            "trunk thickness",
+            AppData::trunk_params.then(TrunkParams::thickness_curve),
+        ))
+        .with_child(make_curve_row(
+            "branch length",
+            AppData::branch_params.then(BranchParams::length_curve),
+        ))
+        .with_child(make_curve_row(
+            "branch angle",
+            AppData::branch_params.then(BranchParams::angle_curve),
+        ));
+    Scroll::new(col).vertical()
+}
+
+fn render_tree<C: Canvas<Srgb<u8>>>(canvas: &mut C, tree: &Tree, viewport: Viewport, tapered: bool) {
+    if tapered {
+        TaperedRenderer::new(viewport).render_tree(canvas, tree);
+    } else {
+        PolygonRenderer::new(viewport).render_tree(canvas, tree);
+    }
+}
+
 fn make_image_button() -> impl Widget<AppData> {
     Button::new("Generate Image").on_click(|ctx, data: &mut AppData, _env| {
-        let mut renderer = PolygonRenderer;
-        let mut image = RgbImage::new(WIDTH as u32, HEIGHT as u32);
-
-        for pixel in image.pixels_mut() {
-            *pixel = Rgb([255u8, 255u8, 255u8]);
-        }
-        let mut tree = data.tree.borrow_mut().to_owned();
-        tree = TrunkLayer::generate(tree, &data.trunk_params);
-        tree = BranchLayer::generate(tree, &data.branch_params);
-        renderer.render_tree(&mut image, &tree);
+        let pipeline = Pipeline::new()
+            .with_layer(TrunkLayer::new(data.trunk_params))
+            .with_stage(BranchLayer::new(data.branch_params), data.branches_enabled)
+            .with_layer(TropismLayer::new(data.tropism_params))
+            .with_stage(FoliageLayer::new(data.foliage_params), data.foliage_enabled);
+        let (tree, report) = pipeline.run_with_report(data.tree.borrow().to_owned(), data.seed);
+        tracing::info!("generated tree\n{report}");
+        data.report = report.to_string();
+        let viewport = Viewport::fit(
+            tree.bounds(),
+            IMAGE_WIDTH as f64,
+            IMAGE_HEIGHT as f64,
+            10.0,
+        );
+        let image = if data.antialiased {
+            let mut canvas = AntialiasedCanvas::new(IMAGE_WIDTH, IMAGE_HEIGHT)
+                .with_background(Srgba::new(255, 255, 255, 255));
+            render_tree(&mut canvas, &tree, viewport, data.tapered);
+            canvas.to_rgb_image()
+        } else {
+            let mut image = RgbImage::from_pixel(IMAGE_WIDTH, IMAGE_HEIGHT, Rgb([255u8, 255u8, 255u8]));
+            render_tree(&mut image, &tree, viewport, data.tapered);
+            image
+        };
         data.image = Rc::new(RefCell::new(DynamicImage::ImageRgb8(image)));
         ctx.submit_command(UPDATE_IMAGE);
     })
 }
 
+fn make_seed_row() -> impl Widget<AppData> {
+    let label = Label::new(|data: &AppData, _env: &_| format!("seed: {}", data.seed));
+    let button = Button::new("New Seed").on_click(|_ctx, data: &mut AppData, _env| {
+        data.seed = rand::random();
+    });
+    Flex::row()
+        .with_child(label)
+        .with_spacer(5.0)
+        .with_child(button)
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("Tapered").lens(AppData::tapered))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("Anti-aliased").lens(AppData::antialiased))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("Branches").lens(AppData::branches_enabled))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("Leaves").lens(AppData::foliage_enabled))
+}
+
+fn make_preset_row() -> impl Widget<AppData> {
+    let mut row = Flex::row().with_child(Label::new("Preset:"));
+    for name in Preset::builtin_names() {
+        let button = Button::new(name).on_click(move |_ctx, data: &mut AppData, _env| {
+            if let Some(preset) = Preset::builtin(name) {
+                data.trunk_params = preset.params.trunk;
+                data.branch_params = preset.params.branch;
+                data.tropism_params = preset.params.tropism;
+                data.foliage_params = preset.params.foliage;
+            }
+        });
+        row.add_spacer(5.0);
+        row.add_child(button);
+    }
+    row
+}
+
 fn make_layout() -> impl Widget<AppData> {
     let sliders = Flex::column()
+        .with_child(make_preset_row())
         .with_flex_child(make_trunk_sliders(), 2.0)
         .with_flex_spacer(0.2)
         .with_flex_child(make_branch_sliders(), 2.0)
+        .with_flex_child(make_tropism_sliders(), 1.0)
+        .with_flex_child(make_foliage_sliders(), 2.0)
+        .with_flex_child(make_curve_editors(), 2.0)
+        .with_child(make_seed_row())
         .with_flex_child(make_image_button(), 0.2)
+        .with_child(Label::new(|data: &AppData, _env: &_| data.report.clone()))
         .align_left()
         .align_vertical(UnitPoint::TOP)
         .align_horizontal(UnitPoint::LEFT)
@@ -112,13 +297,21 @@ fn main() {
     let data = AppData {
         trunk_params: TrunkParams::new(1.0, 1.0, 1.0, 0.5),
         branch_params: BranchParams::new(1.0, 1.0, 0.5),
+        tropism_params: TropismParams::default(),
+        foliage_params: FoliageParams::default(),
         tree: Rc::new(RefCell::new(Tree::new())),
         image: Rc::new(RefCell::new(DynamicImage::ImageRgb8(RgbImage::new(
-            WIDTH as u32,
-            HEIGHT as u32,
+            IMAGE_WIDTH,
+            IMAGE_HEIGHT,
         )))),
         image_updated: false,
         auto_generate: false,
+        seed: 0,
+        tapered: false,
+        antialiased: true,
+        branches_enabled: true,
+        foliage_enabled: true,
+        report: String::new(),
     };
 
     AppLauncher::with_window(main_window)
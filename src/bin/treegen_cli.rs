@@ -0,0 +1,1329 @@
+use std::{
+    error::Error,
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
+use clap::{Args, Parser, ValueEnum};
+use image::{DynamicImage, Rgb, RgbImage, RgbaImage};
+use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
+use palette::{Srgb, Srgba};
+use rayon::prelude::*;
+use serde::Serialize;
+use treegen::{
+    analysis::TreeStats,
+    generator::{
+        branch_layer::BranchParams,
+        collision::{CollisionMode, CollisionParams},
+        foliage_layer::{FoliageParams, Season},
+        growth::GrowthParams,
+        lsystem_layer::LSystemParams,
+        pipe_model_layer::PipeModelParams,
+        pipeline::{LayerConfig, Pipeline, PipelineConfig, PipelineReport, StageConfig},
+        presets::Preset,
+        tree_params::TreeParams,
+        tropism_layer::TropismParams,
+        trunk_layer::TrunkParams,
+    },
+    rendering::{
+        animation::{Easing, GrowthAnimation, FRAME_RATES, MAX_FRAMES, MAX_HOLD},
+        AntialiasedCanvas, Canvas, JointStyle, PolygonRenderer, Renderer, Supersampling,
+        SvgCanvas, TaperedRenderer, Viewport,
+    },
+    lsystem::LSystem,
+    sampler::DistributionKind,
+    skeleton::tree::Tree,
+    utils::quadtree::BoundingBox,
+};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Most trees a single batch generates. The manifest lists every one of them, so it is built
+/// in memory.
+const MAX_BATCH_SIZE: u64 = 100_000;
+
+/// Declares an argument group with one optional flag per parameter field and an `apply`
+/// method that writes the flags that were given into a params struct.
+macro_rules! param_args {
+    ($args:ident, $params:ty, $heading:literal, $($(#[doc = $doc:literal])* $flag:ident => $field:ident),+ $(,)?) => {
+        #[derive(Debug, Clone, Args)]
+        #[command(next_help_heading = $heading)]
+        struct $args {
+            $(
+                $(#[doc = $doc])*
+                #[arg(long, value_name = "VALUE")]
+                $flag: Option<f64>,
+            )+
+        }
+
+        impl $args {
+            fn apply(&self, params: &mut $params) {
+                $(
+                    if let Some(value) = self.$flag {
+                        params.$field = value;
+                    }
+                )+
+            }
+        }
+    };
+}
+
+param_args!(
+    TrunkArgs,
+    TrunkParams,
+    "Trunk parameters",
+    /// Scale of the angle between split branches
+    trunk_spread => spread,
+    /// Peak probability of splitting a tip into two branches
+    trunk_split => split,
+    /// Probability of growing a single new branch from a tip
+    trunk_branch => branch,
+    /// Randomness of heights and angles
+    trunk_variability => variability,
+    /// Length of every trunk segment
+    trunk_default_branch_length => default_branch_length,
+    /// Thickness scale of trunk segments
+    trunk_default_branch_size => default_branch_size,
+    /// How quickly thickness falls off with each iteration
+    trunk_branch_size_falloff => branch_size_falloff,
+    /// Mean number of growth iterations
+    trunk_default_height_mean => default_height_mean,
+    /// Iteration at which splitting is most likely
+    trunk_split_falloff_peak => split_falloff_peak,
+    /// Angle in degrees every branch leans toward
+    trunk_lean_bias => lean_bias,
+    /// Scale applied to the variability of angles
+    trunk_variability_modifier => variability_modifier,
+    /// Angle in degrees of the right-hand branch of a split
+    trunk_angle_spread_positive => angle_spread_positive,
+    /// Angle in degrees of the left-hand branch of a split
+    trunk_angle_spread_negative => angle_spread_negative,
+    /// Maximum number of children per node
+    trunk_max_children => max_children,
+);
+
+param_args!(
+    BranchArgs,
+    BranchParams,
+    "Branch parameters",
+    /// Scale of the angle between a sub-branch and its parent
+    branch_spread => spread,
+    /// Probability of growing a sub-branch off a node
+    branch_branch => branch,
+    /// Randomness of sub-branch angles
+    branch_variability => variability,
+    /// Size lost with every node of a sub-branch
+    branch_base_size_reduction => base_size_reduction,
+    /// Size at which a sub-branch stops growing
+    branch_minimum_size => minimum_size,
+    /// Size of the first node of a sub-branch, relative to its parent
+    branch_initial_branch_size => initial_branch_size,
+    /// Length of a sub-branch node of size 1
+    branch_initial_length => initial_length,
+    /// Mean angle in degrees between a sub-branch and its parent
+    branch_base_angle_mean_deg => base_angle_mean_deg,
+    /// Standard deviation in degrees of the sub-branch angle
+    branch_base_angle_std_dev_deg => base_angle_std_dev_deg,
+);
+
+param_args!(
+    FoliageArgs,
+    FoliageParams,
+    "Foliage parameters",
+    /// Mean number of leaves per leafy node
+    foliage_density => density,
+    /// How many steps away from a tip nodes still get leaves
+    foliage_tip_depth => tip_depth,
+    /// Length of a leaf
+    foliage_leaf_length => leaf_length,
+    /// Width of a leaf relative to its length
+    foliage_leaf_width => leaf_width,
+    /// Relative standard deviation of the leaf size
+    foliage_size_variability => size_variability,
+    /// Mean angle in degrees between a leaf and its branch
+    foliage_orientation_deg => orientation_deg,
+    /// Standard deviation in degrees of the leaf angle
+    foliage_orientation_std_dev_deg => orientation_std_dev_deg,
+    /// How far leaf colors stray from the season's palette, from 0 to 1
+    foliage_color_variation => color_variation,
+);
+
+param_args!(
+    TropismArgs,
+    TropismParams,
+    "Tropism parameters",
+    /// Direction branches bend toward in degrees: 0 is up, 90 is right, 180 is down
+    tropism_direction_deg => direction_deg,
+    /// Largest turn of a single node in degrees; negative values bend away
+    tropism_strength => strength,
+    /// How strongly thickness resists bending
+    tropism_stiffness => stiffness,
+    /// How quickly bending grows with the distance from the root
+    tropism_distance_exponent => distance_exponent,
+);
+
+param_args!(
+    PipeModelArgs,
+    PipeModelParams,
+    "Pipe model",
+    /// Exponent n of r^n = sum(r_child^n): 2 is the pipe model, 3 Murray's law
+    pipe_model_exponent => exponent,
+    /// Thickness of every tip
+    pipe_model_tip_thickness => tip_thickness,
+);
+
+param_args!(
+    GrowthArgs,
+    GrowthParams,
+    "Growth",
+    /// Length of a new segment relative to its full length
+    growth_bud_length => bud_length,
+    /// Share of its missing length a segment grows every year
+    growth_elongation => elongation,
+    /// Thickness of a new segment
+    growth_bud_thickness => bud_thickness,
+    /// Thickness every segment gains every year
+    growth_thickening => thickening,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CollisionArg {
+    /// Segments may overlap
+    Off,
+    /// Drop segments that come too close to others
+    Reject,
+    /// Turn segments away from others, dropping them if no turn helps
+    Steer,
+}
+
+impl From<CollisionArg> for CollisionMode {
+    fn from(mode: CollisionArg) -> Self {
+        match mode {
+            CollisionArg::Off => CollisionMode::Off,
+            CollisionArg::Reject => CollisionMode::Reject,
+            CollisionArg::Steer => CollisionMode::Steer,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+#[command(next_help_heading = "Collision")]
+struct CollisionArgs {
+    /// How trunk and branch segments avoid each other
+    #[arg(long = "collision", value_enum, value_name = "MODE")]
+    mode: Option<CollisionArg>,
+    /// Smallest gap allowed between two segments
+    #[arg(long = "collision-clearance", value_name = "VALUE")]
+    clearance: Option<f64>,
+    /// Largest turn in degrees tried when steering
+    #[arg(long = "collision-max-steer-deg", value_name = "VALUE")]
+    max_steer_deg: Option<f64>,
+}
+
+impl CollisionArgs {
+    fn apply(&self, params: &mut CollisionParams) {
+        if let Some(mode) = self.mode {
+            params.mode = mode.into();
+        }
+        if let Some(clearance) = self.clearance {
+            params.clearance = clearance;
+        }
+        if let Some(max_steer_deg) = self.max_steer_deg {
+            params.max_steer_deg = max_steer_deg;
+        }
+    }
+}
+
+/// Distribution flags take normal, truncated-normal:BOUND, uniform, log-normal,
+/// beta:ALPHA,BETA or von-mises.
+#[derive(Debug, Clone, Args)]
+#[command(next_help_heading = "Distributions")]
+struct DistributionArgs {
+    /// Distribution of the number of trunk growth iterations
+    #[arg(long, value_name = "KIND", value_parser = parse_distribution)]
+    trunk_height_distribution: Option<DistributionKind>,
+    /// Distribution of the angles of split trunk branches
+    #[arg(long, value_name = "KIND", value_parser = parse_distribution)]
+    trunk_split_angle_distribution: Option<DistributionKind>,
+    /// Distribution of the angle of single new trunk branches
+    #[arg(long, value_name = "KIND", value_parser = parse_distribution)]
+    trunk_branch_angle_distribution: Option<DistributionKind>,
+    /// Distribution of the angle between a sub-branch and its parent
+    #[arg(long, value_name = "KIND", value_parser = parse_distribution)]
+    branch_base_angle_distribution: Option<DistributionKind>,
+    /// Distribution of the turn between the nodes of a sub-branch
+    #[arg(long, value_name = "KIND", value_parser = parse_distribution)]
+    branch_wobble_distribution: Option<DistributionKind>,
+}
+
+impl DistributionArgs {
+    fn apply_trunk(&self, params: &mut TrunkParams) {
+        if let Some(kind) = self.trunk_height_distribution {
+            params.height_distribution = kind;
+        }
+        if let Some(kind) = self.trunk_split_angle_distribution {
+            params.split_angle_distribution = kind;
+        }
+        if let Some(kind) = self.trunk_branch_angle_distribution {
+            params.branch_angle_distribution = kind;
+        }
+    }
+
+    fn apply_branch(&self, params: &mut BranchParams) {
+        if let Some(kind) = self.branch_base_angle_distribution {
+            params.base_angle_distribution = kind;
+        }
+        if let Some(kind) = self.branch_wobble_distribution {
+            params.wobble_distribution = kind;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SeasonArg {
+    Spring,
+    Summer,
+    Autumn,
+    /// No leaves
+    Bare,
+}
+
+impl From<SeasonArg> for Season {
+    fn from(season: SeasonArg) -> Self {
+        match season {
+            SeasonArg::Spring => Season::Spring,
+            SeasonArg::Summer => Season::Summer,
+            SeasonArg::Autumn => Season::Autumn,
+            SeasonArg::Bare => Season::Bare,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Svg,
+    /// A looping GIF; a still image unless --animate is given
+    Gif,
+    /// A looping animated PNG; a still PNG unless --animate is given
+    Apng,
+    /// The skeleton as JSON
+    Json,
+    /// The skeleton in the compact binary format
+    Bin,
+}
+
+impl OutputFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "bmp" => Some(OutputFormat::Bmp),
+            "svg" => Some(OutputFormat::Svg),
+            "gif" => Some(OutputFormat::Gif),
+            "apng" => Some(OutputFormat::Apng),
+            "json" => Some(OutputFormat::Json),
+            "bin" | "tree" => Some(OutputFormat::Bin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Style {
+    /// Constant-width segments
+    Polygon,
+    /// Segments tapering from the parent's thickness
+    Tapered,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Joint {
+    Round,
+    Miter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum EasingArg {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl From<EasingArg> for Easing {
+    fn from(easing: EasingArg) -> Self {
+        match easing {
+            EasingArg::Linear => Easing::Linear,
+            EasingArg::EaseIn => Easing::EaseIn,
+            EasingArg::EaseOut => Easing::EaseOut,
+            EasingArg::EaseInOut => Easing::EaseInOut,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Antialias {
+    None,
+    X1,
+    X2,
+    X4,
+    X8,
+}
+
+#[derive(Debug, Clone, Args)]
+#[command(next_help_heading = "Rendering")]
+struct RenderArgs {
+    /// Output width in pixels
+    #[arg(long, default_value_t = 400)]
+    width: u32,
+    /// Output height in pixels
+    #[arg(long, default_value_t = 400)]
+    height: u32,
+    /// Space in pixels kept free around the tree
+    #[arg(long, default_value_t = 10.0)]
+    margin: f64,
+    /// Background color as #rrggbb or #rrggbbaa
+    #[arg(long, default_value = "#ffffff", value_parser = parse_color)]
+    background: Srgba<u8>,
+    #[arg(long, value_enum, default_value_t = Style::Polygon)]
+    style: Style,
+    /// Joint style of the tapered style
+    #[arg(long, value_enum, default_value_t = Joint::Round)]
+    joint: Joint,
+    /// Draw every branch of the tapered style as one outline
+    #[arg(long)]
+    merge_chains: bool,
+    /// Anti-aliasing samples per pixel row of raster output
+    #[arg(long, value_enum, default_value_t = Antialias::X4)]
+    antialias: Antialias,
+}
+
+impl RenderArgs {
+    fn viewport(&self, tree: &Tree) -> Viewport {
+        Viewport::fit(
+            tree.bounds(),
+            self.width as f64,
+            self.height as f64,
+            self.margin,
+        )
+    }
+
+    fn render_to<C: Canvas<Srgb<u8>>>(&self, canvas: &mut C, tree: &Tree, viewport: Viewport) {
+        match self.style {
+            Style::Polygon => PolygonRenderer::new(viewport).render_tree(canvas, tree),
+            Style::Tapered => {
+                let joint = match self.joint {
+                    Joint::Round => JointStyle::Round,
+                    Joint::Miter => JointStyle::Miter,
+                };
+                TaperedRenderer::new(viewport)
+                    .with_joint(joint)
+                    .with_merged_chains(self.merge_chains)
+                    .render_tree(canvas, tree)
+            }
+        }
+    }
+
+    fn render_image(&self, tree: &Tree, viewport: Viewport) -> DynamicImage {
+        let supersampling = match self.antialias {
+            Antialias::None => {
+                let background = self.background;
+                let mut image = RgbImage::from_pixel(
+                    self.width,
+                    self.height,
+                    Rgb([background.red, background.green, background.blue]),
+                );
+                self.render_to(&mut image, tree, viewport);
+                return DynamicImage::ImageRgb8(image);
+            }
+            Antialias::X1 => Supersampling::X1,
+            Antialias::X2 => Supersampling::X2,
+            Antialias::X4 => Supersampling::X4,
+            Antialias::X8 => Supersampling::X8,
+        };
+
+        let mut canvas = AntialiasedCanvas::new(self.width, self.height)
+            .with_supersampling(supersampling)
+            .with_background(self.background);
+        self.render_to(&mut canvas, tree, viewport);
+        if self.background.alpha == u8::MAX {
+            DynamicImage::ImageRgb8(canvas.to_rgb_image())
+        } else {
+            DynamicImage::ImageRgba8(canvas.to_rgba_image())
+        }
+    }
+
+    fn render_svg(&self, tree: &Tree, viewport: Viewport) -> SvgCanvas {
+        let mut canvas = SvgCanvas::new(self.width as f64, self.height as f64);
+        if self.background.alpha > 0 {
+            canvas = canvas.with_background(self.background.color);
+        }
+        self.render_to(&mut canvas, tree, viewport);
+        canvas
+    }
+
+    fn write(&self, tree: &Tree, format: OutputFormat, path: &Path) -> Result<(), BoxError> {
+        self.write_with_viewport(tree, self.viewport(tree), format, path)
+    }
+
+    /// Writes `tree` framed by `viewport` instead of fitting the view to the tree.
+    fn write_with_viewport(
+        &self,
+        tree: &Tree,
+        viewport: Viewport,
+        format: OutputFormat,
+        path: &Path,
+    ) -> Result<(), BoxError> {
+        match format {
+            OutputFormat::Svg => self.render_svg(tree, viewport).save(path)?,
+            OutputFormat::Json => tree.save_json(path)?,
+            OutputFormat::Bin => tree.save_binary(path)?,
+            OutputFormat::Jpeg => {
+                let image = DynamicImage::ImageRgb8(self.render_image(tree, viewport).into_rgb8());
+                image.save_with_format(path, image::ImageFormat::Jpeg)?
+            }
+            OutputFormat::Png | OutputFormat::Apng => self
+                .render_image(tree, viewport)
+                .save_with_format(path, image::ImageFormat::Png)?,
+            OutputFormat::Gif => self
+                .render_image(tree, viewport)
+                .save_with_format(path, image::ImageFormat::Gif)?,
+            OutputFormat::Bmp => self
+                .render_image(tree, viewport)
+                .save_with_format(path, image::ImageFormat::Bmp)?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+#[command(next_help_heading = "Animation")]
+struct AnimationArgs {
+    /// Grow the tree in over this many frames. GIF and APNG outputs become one animated
+    /// file, other formats a numbered file per frame
+    #[arg(long, value_name = "FRAMES", conflicts_with_all = ["count", "seeds"])]
+    animate: Option<usize>,
+    /// Frames per second
+    #[arg(long, default_value_t = 24.0)]
+    fps: f64,
+    /// How the growth speeds up and slows down
+    #[arg(long, value_enum, default_value_t = EasingArg::Linear)]
+    easing: EasingArg,
+    /// Seconds the finished tree is shown before the animation loops
+    #[arg(long, value_name = "SECONDS", default_value_t = 1.0)]
+    hold: f64,
+}
+
+impl AnimationArgs {
+    /// Returns the animation that was asked for, or `None` when --animate was not given.
+    fn animation(&self) -> Result<Option<GrowthAnimation>, BoxError> {
+        let Some(frames) = self.animate else {
+            return Ok(None);
+        };
+        if frames == 0 {
+            return Err("--animate needs at least one frame".into());
+        }
+        if frames > MAX_FRAMES {
+            return Err(format!("--animate must be at most {MAX_FRAMES}, got {frames}").into());
+        }
+        if !(self.fps.is_finite() && self.fps > 0.0) {
+            return Err(format!("--fps must be finite and positive, got {}", self.fps).into());
+        }
+        if !FRAME_RATES.contains(&self.fps) {
+            return Err(format!(
+                "--fps must be between {} and {}, got {}",
+                FRAME_RATES.start(),
+                FRAME_RATES.end(),
+                self.fps
+            )
+            .into());
+        }
+        let hold = Duration::try_from_secs_f64(self.hold)
+            .map_err(|_| format!("invalid hold time `{}`", self.hold))?;
+        if hold > MAX_HOLD {
+            return Err(format!(
+                "--hold must be at most {} seconds, got {}",
+                MAX_HOLD.as_secs(),
+                self.hold
+            )
+            .into());
+        }
+        Ok(Some(
+            GrowthAnimation::new(frames, self.fps)
+                .with_easing(self.easing.into())
+                .with_hold(hold),
+        ))
+    }
+}
+
+/// Generates a tree and writes it as an image, an SVG or a skeleton file.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// Start from a named preset or a preset file; see --list-presets
+    #[arg(short, long, value_name = "NAME|FILE", help_heading = "Presets")]
+    preset: Option<String>,
+    /// Directory searched for presets in addition to the built-in ones
+    #[arg(long, value_name = "DIR", help_heading = "Presets")]
+    preset_dir: Option<PathBuf>,
+    /// List the available presets and exit
+    #[arg(long, help_heading = "Presets")]
+    list_presets: bool,
+    /// Print the parameters that differ between two presets and exit
+    #[arg(long, num_args = 2, value_names = ["A", "B"], help_heading = "Presets")]
+    diff_presets: Option<Vec<String>>,
+    /// Write the effective parameters as a preset to this file
+    #[arg(long, value_name = "FILE", help_heading = "Presets")]
+    save_preset: Option<PathBuf>,
+    /// Grow the tree from an L-system instead of the trunk and branch layers: the name of an
+    /// example (fractal_plant, bush, weed, twig, stochastic_weed, parametric_tree) or a
+    /// grammar file
+    #[arg(long, value_name = "NAME|FILE", help_heading = "L-systems")]
+    lsystem: Option<String>,
+    /// Derivation depth of the L-system [default: set by the grammar]
+    #[arg(long, value_name = "N", help_heading = "L-systems")]
+    lsystem_iterations: Option<usize>,
+    /// JSON parameter file. Its values override the preset, and flags given on the command
+    /// line override both
+    #[arg(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Seed for all random choices; the same seed and parameters give the same tree
+    #[arg(short, long, default_value_t = 0)]
+    seed: u64,
+    /// Output file
+    #[arg(short, long, default_value = "tree.png")]
+    output: PathBuf,
+    /// Output format [default: inferred from the output extension]
+    #[arg(short, long, value_enum)]
+    format: Option<OutputFormat>,
+    /// JSON pipeline file listing the layers to run, used instead of presets and --config.
+    /// Parameter flags apply to every layer of the matching kind
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["preset", "config", "save_preset", "lsystem"])]
+    pipeline: Option<PathBuf>,
+    /// Write the effective parameters (or pipeline) as JSON to this file
+    #[arg(long, value_name = "FILE")]
+    save_params: Option<PathBuf>,
+    /// Do not print statistics
+    #[arg(short, long)]
+    quiet: bool,
+    /// Write the morphology statistics of the tree as JSON to this file, or to stdout for
+    /// `-`, which moves the summary to stderr. A batch writes a list with one entry per seed
+    #[arg(long, value_name = "FILE")]
+    stats: Option<PathBuf>,
+    #[command(flatten)]
+    render: RenderArgs,
+    #[command(flatten)]
+    animation: AnimationArgs,
+    #[command(flatten)]
+    trunk: TrunkArgs,
+    #[command(flatten)]
+    branch: BranchArgs,
+    /// Color of sub-branches as #rrggbb
+    #[arg(long, value_name = "COLOR", value_parser = parse_rgb, help_heading = "Branch parameters")]
+    branch_color: Option<Srgb<u8>>,
+    #[command(flatten)]
+    collision: CollisionArgs,
+    #[command(flatten)]
+    distributions: DistributionArgs,
+    /// Grow the trunk year by year for this many years instead of in one pass. The trunk
+    /// parameters set the sprouting rates and full segment lengths
+    #[arg(long, value_name = "YEARS", conflicts_with = "lsystem", help_heading = "Growth")]
+    growth_years: Option<usize>,
+    #[command(flatten)]
+    growth: GrowthArgs,
+    /// Recompute thicknesses from the tips down after branching, so every parent is as thick
+    /// as the children it carries
+    #[arg(long, help_heading = "Pipe model")]
+    pipe_model: bool,
+    #[command(flatten)]
+    pipe_model_args: PipeModelArgs,
+    #[command(flatten)]
+    tropism: TropismArgs,
+    #[command(flatten)]
+    foliage: FoliageArgs,
+    /// Palette of the leaves
+    #[arg(long, value_enum, value_name = "SEASON", help_heading = "Foliage parameters")]
+    season: Option<SeasonArg>,
+    /// Generate this many trees with consecutive seeds starting at --seed
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..=MAX_BATCH_SIZE),
+        conflicts_with = "seeds",
+        help_heading = "Batch"
+    )]
+    count: Option<u64>,
+    /// Generate one tree per seed in START..END or START..=END
+    #[arg(long, value_name = "RANGE", value_parser = parse_seed_range, help_heading = "Batch")]
+    seeds: Option<RangeInclusive<u64>>,
+    /// Manifest written by a batch [default: manifest.json next to the outputs]
+    #[arg(long, value_name = "FILE", help_heading = "Batch")]
+    manifest: Option<PathBuf>,
+}
+
+/// The statistics of one tree of a batch.
+#[derive(Debug, Serialize)]
+struct SeedStats {
+    seed: u64,
+    #[serde(flatten)]
+    stats: TreeStats,
+}
+
+/// One line of the batch manifest.
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    index: usize,
+    file: PathBuf,
+    seed: u64,
+    pipeline: PipelineConfig,
+    node_count: usize,
+    tip_count: usize,
+    leaf_count: usize,
+    bounds: BoundingBox,
+}
+
+impl Cli {
+    /// Presets found in --preset-dir, or none when it was not given.
+    fn user_presets(&self) -> Result<Vec<Preset>, BoxError> {
+        match &self.preset_dir {
+            Some(dir) => Ok(Preset::load_dir(dir)
+                .map_err(|err| format!("could not load presets from {}: {err}", dir.display()))?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Finds a preset by name, looking in --preset-dir before the built-in presets, or loads
+    /// it from a file when no preset has that name.
+    fn find_preset(&self, name: &str) -> Result<Preset, BoxError> {
+        let user_presets = self.user_presets()?;
+        if let Some(preset) = user_presets.into_iter().find(|preset| preset.name == name) {
+            return Ok(preset);
+        }
+        if let Some(preset) = Preset::builtin(name) {
+            return Ok(preset);
+        }
+        let path = Path::new(name);
+        if path.is_file() {
+            return Ok(Preset::load(path)
+                .map_err(|err| format!("could not load {}: {err}", path.display()))?);
+        }
+        Err(format!("unknown preset `{name}`, run with --list-presets to see them").into())
+    }
+
+    fn params(&self) -> Result<TreeParams, BoxError> {
+        let mut params = match &self.preset {
+            Some(name) => self.find_preset(name)?.params,
+            None => TreeParams::default(),
+        };
+        if let Some(path) = &self.config {
+            params = fs::read_to_string(path)
+                .map_err(Into::into)
+                .and_then(|json| params.with_json_overrides(&json))
+                .map_err(|err| format!("could not load {}: {err}", path.display()))?;
+        }
+        self.apply_trunk(&mut params.trunk);
+        self.apply_branch(&mut params.branch);
+        if self.pipe_model {
+            params.pipe_model.get_or_insert_with(PipeModelParams::default);
+        }
+        if let Some(pipe_model) = &mut params.pipe_model {
+            self.pipe_model_args.apply(pipe_model);
+        }
+        self.tropism.apply(&mut params.tropism);
+        self.apply_foliage(&mut params.foliage);
+        Ok(params)
+    }
+
+    fn apply_foliage(&self, params: &mut FoliageParams) {
+        self.foliage.apply(params);
+        if let Some(season) = self.season {
+            params.season = season.into();
+        }
+    }
+
+    fn apply_trunk(&self, params: &mut TrunkParams) {
+        self.trunk.apply(params);
+        self.collision.apply(&mut params.collision);
+        self.distributions.apply_trunk(params);
+    }
+
+    fn apply_branch(&self, params: &mut BranchParams) {
+        self.branch.apply(params);
+        self.collision.apply(&mut params.collision);
+        self.distributions.apply_branch(params);
+        if let Some(color) = self.branch_color {
+            params.color = [color.red, color.green, color.blue];
+        }
+    }
+
+    fn apply_growth(&self, params: &mut GrowthParams) {
+        self.growth.apply(params);
+        self.apply_trunk(&mut params.trunk);
+        if let Some(years) = self.growth_years {
+            params.years = years;
+        }
+    }
+
+    fn apply_lsystem(&self, params: &mut LSystemParams) {
+        if let Some(iterations) = self.lsystem_iterations {
+            params.iterations = Some(iterations);
+        }
+    }
+
+    /// Loads the grammar given with --lsystem, or returns `None` when there is none.
+    fn lsystem(&self) -> Result<Option<LSystemParams>, BoxError> {
+        let Some(name) = &self.lsystem else {
+            return Ok(None);
+        };
+        let grammar = match LSystem::example(name) {
+            Some(grammar) => grammar,
+            None => {
+                let source = fs::read_to_string(name)
+                    .map_err(|err| format!("could not load L-system `{name}`: {err}"))?;
+                LSystem::parse(&source).map_err(|err| format!("{name}: {err}"))?
+            }
+        };
+        let mut params = LSystemParams::new(grammar);
+        self.apply_lsystem(&mut params);
+        params
+            .validate()
+            .map_err(|err| format!("invalid parameters: lsystem.{err}"))?;
+        Ok(Some(params))
+    }
+
+    fn load_pipeline(&self, path: &Path) -> Result<PipelineConfig, BoxError> {
+        let mut config = PipelineConfig::load(path)
+            .map_err(|err| format!("could not load {}: {err}", path.display()))?;
+        for stage in &mut config.stages {
+            match &mut stage.layer {
+                LayerConfig::Trunk(params) => self.apply_trunk(params),
+                LayerConfig::Branch(params) => self.apply_branch(params),
+                LayerConfig::Foliage(params) => self.apply_foliage(params),
+                LayerConfig::LSystem(params) => self.apply_lsystem(params),
+                LayerConfig::Tropism(params) => self.tropism.apply(params),
+                LayerConfig::Growth(params) => self.apply_growth(params),
+                LayerConfig::PipeModel(params) => self.pipe_model_args.apply(params),
+                LayerConfig::SpaceColonization(_) => {}
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns the seeds of a batch, or `None` when a single tree was requested.
+    fn batch_seeds(&self) -> Option<Vec<u64>> {
+        match (&self.seeds, self.count) {
+            (Some(range), _) => Some(range.clone().collect()),
+            (None, Some(count)) => Some((0..count).map(|i| self.seed.wrapping_add(i)).collect()),
+            (None, None) => None,
+        }
+    }
+
+    fn format(&self) -> Result<OutputFormat, String> {
+        self.format
+            .or_else(|| OutputFormat::from_path(&self.output))
+            .ok_or_else(|| {
+                format!(
+                    "cannot infer the output format of {}, pass --format",
+                    self.output.display()
+                )
+            })
+    }
+}
+
+fn parse_color(value: &str) -> Result<Srgba<u8>, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !hex.is_ascii() {
+        return Err(format!("invalid color `{value}`"));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("invalid color `{value}`"))
+    };
+    match hex.len() {
+        6 => Ok(Srgba::new(channel(0)?, channel(2)?, channel(4)?, u8::MAX)),
+        8 => Ok(Srgba::new(channel(0)?, channel(2)?, channel(4)?, channel(6)?)),
+        _ => Err(format!("invalid color `{value}`, expected #rrggbb or #rrggbbaa")),
+    }
+}
+
+fn parse_rgb(value: &str) -> Result<Srgb<u8>, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 {
+        return Err(format!("invalid color `{value}`, expected #rrggbb"));
+    }
+    parse_color(value).map(|color| color.color)
+}
+
+fn parse_distribution(value: &str) -> Result<DistributionKind, String> {
+    let invalid = || {
+        format!(
+            "invalid distribution `{value}`, expected normal, truncated-normal:BOUND, uniform, \
+             log-normal, beta:ALPHA,BETA or von-mises"
+        )
+    };
+    let number = |text: &str| text.trim().parse::<f64>().map_err(|_| invalid());
+    let (name, arguments) = match value.split_once(':') {
+        Some((name, arguments)) => (name, Some(arguments)),
+        None => (value, None),
+    };
+    let kind = match (name.trim(), arguments) {
+        ("normal", None) => Ok(DistributionKind::Normal),
+        ("truncated-normal", Some(bound)) => Ok(DistributionKind::TruncatedNormal {
+            bound: number(bound)?,
+        }),
+        ("uniform", None) => Ok(DistributionKind::Uniform),
+        ("log-normal", None) => Ok(DistributionKind::LogNormal),
+        ("beta", Some(shapes)) => {
+            let (alpha, beta) = shapes.split_once(',').ok_or_else(invalid)?;
+            Ok(DistributionKind::Beta {
+                alpha: number(alpha)?,
+                beta: number(beta)?,
+            })
+        }
+        ("von-mises", None) => Ok(DistributionKind::VonMises),
+        _ => Err(invalid()),
+    }?;
+    kind.validate()?;
+    Ok(kind)
+}
+
+fn parse_seed_range(value: &str) -> Result<RangeInclusive<u64>, String> {
+    let invalid = || format!("invalid seed range `{value}`, expected START..END or START..=END");
+    let empty = || format!("seed range `{value}` is empty");
+    let parse = |bound: &str| bound.trim().parse::<u64>().map_err(|_| invalid());
+    let range = if let Some((start, end)) = value.split_once("..=") {
+        parse(start)?..=parse(end)?
+    } else if let Some((start, end)) = value.split_once("..") {
+        let start = parse(start)?;
+        parse(end)?.checked_sub(1).map(|end| start..=end).ok_or_else(empty)?
+    } else {
+        return Err(invalid());
+    };
+    if range.is_empty() {
+        return Err(empty());
+    }
+    if range.end() - range.start() >= MAX_BATCH_SIZE {
+        return Err(format!("seed range `{value}` holds more than {MAX_BATCH_SIZE} seeds"));
+    }
+    Ok(range)
+}
+
+/// Inserts `number` into `template`: `{}` in the file name is replaced by it, otherwise it is
+/// appended to the file stem, so `tree.png` becomes `tree_0007.png`.
+fn numbered_path(template: &Path, number: usize, digits: usize) -> PathBuf {
+    let number = format!("{number:0digits$}");
+    let file_name = template
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = if file_name.contains("{}") {
+        file_name.replace("{}", &number)
+    } else {
+        match file_name.rsplit_once('.') {
+            Some((stem, extension)) => format!("{stem}_{number}.{extension}"),
+            None => format!("{file_name}_{number}"),
+        }
+    };
+    template.with_file_name(file_name)
+}
+
+/// Describes `tree` and the pipeline `report` for people rather than scripts.
+fn summarize(tree: &Tree, seed: u64, report: &PipelineReport) -> String {
+    let bounds = tree.bounds();
+    let max_depth = tree.nodes.depths().into_iter().max().unwrap_or(0);
+    format!(
+        "seed:      {seed}\n\
+         nodes:     {}\n\
+         tips:      {}\n\
+         leaves:    {}\n\
+         max depth: {max_depth}\n\
+         bounds:    {:.1} x {:.1}\n\
+         \n\
+         {report}",
+        tree.nodes.nodes.len(),
+        tree.get_tip_nodes().len(),
+        tree.leaves.len(),
+        bounds.width,
+        bounds.height,
+    )
+}
+
+/// Prints `summary` unless `--quiet` was given. It goes to stderr when the statistics are
+/// written to stdout, so that stdout stays valid JSON.
+fn print_summary(cli: &Cli, summary: &str) {
+    if cli.quiet {
+        return;
+    }
+    if cli.stats.as_deref() == Some(Path::new("-")) {
+        eprintln!("{summary}");
+    } else {
+        println!("{summary}");
+    }
+}
+
+fn run_batch(
+    cli: &Cli,
+    config: &PipelineConfig,
+    pipeline: &Pipeline,
+    format: OutputFormat,
+    seeds: Vec<u64>,
+) -> Result<(), BoxError> {
+    if let Some(directory) = cli.output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(directory)?;
+    }
+
+    let digits = seeds.len().saturating_sub(1).to_string().len().max(4);
+    let progress = if cli.quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(seeds.len() as u64).with_style(
+            ProgressStyle::with_template(
+                "{bar:40.green/white} {pos}/{len} trees [{elapsed_precise} < {eta_precise}]",
+            )
+            .unwrap(),
+        )
+    };
+
+    let entries = seeds
+        .par_iter()
+        .enumerate()
+        .progress_with(progress.clone())
+        .map(|(index, &seed)| -> Result<(ManifestEntry, Option<SeedStats>), BoxError> {
+            let tree = pipeline.run(Tree::new(), seed);
+            let file = numbered_path(&cli.output, index, digits);
+            cli.render
+                .write(&tree, format, &file)
+                .map_err(|err| format!("could not write {}: {err}", file.display()))?;
+            let stats = cli.stats.is_some().then(|| SeedStats {
+                seed,
+                stats: TreeStats::new(&tree.nodes),
+            });
+            let entry = ManifestEntry {
+                index,
+                file,
+                seed,
+                pipeline: config.clone(),
+                node_count: tree.nodes.nodes.len(),
+                tip_count: tree.get_tip_nodes().len(),
+                leaf_count: tree.leaves.len(),
+                bounds: tree.bounds(),
+            };
+            Ok((entry, stats))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    progress.finish();
+    let (entries, stats): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+    if let Some(path) = &cli.stats {
+        write_stats(path, &stats.into_iter().flatten().collect::<Vec<_>>())?;
+    }
+
+    let manifest = cli
+        .manifest
+        .clone()
+        .unwrap_or_else(|| cli.output.with_file_name("manifest.json"));
+    fs::write(&manifest, serde_json::to_string_pretty(&entries)?)?;
+
+    let node_count: usize = entries.iter().map(|entry| entry.node_count).sum();
+    print_summary(
+        cli,
+        &format!(
+            "trees:     {}\nnodes:     {node_count}\nwrote {}",
+            entries.len(),
+            manifest.display()
+        ),
+    );
+    Ok(())
+}
+
+/// Writes `stats` as JSON to `path`, or to stdout when `path` is `-`.
+fn write_stats<T: Serialize>(path: &Path, stats: &T) -> Result<(), BoxError> {
+    let json = serde_json::to_string_pretty(stats)?;
+    if path == Path::new("-") {
+        println!("{json}");
+    } else {
+        fs::write(path, json)
+            .map_err(|err| format!("could not write {}: {err}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Writes `animation` of `tree` growing in, framed so that the finished tree fits.
+fn write_animation(
+    cli: &Cli,
+    tree: &Tree,
+    animation: &GrowthAnimation,
+    format: OutputFormat,
+) -> Result<(), BoxError> {
+    let viewport = cli.render.viewport(tree);
+    match format {
+        OutputFormat::Gif | OutputFormat::Apng => {
+            // Frames are rendered a batch at a time and handed to the encoder as the batch
+            // finishes, so only one batch is held in memory.
+            let batch = rayon::current_num_threads().max(1);
+            let frames = (0..animation.frames).step_by(batch).flat_map(|start| {
+                (start..(start + batch).min(animation.frames))
+                    .into_par_iter()
+                    .map(|frame| {
+                        cli.render
+                            .render_image(&animation.frame(tree, frame), viewport)
+                            .into_rgba8()
+                    })
+                    .collect::<Vec<RgbaImage>>()
+            });
+            if format == OutputFormat::Gif {
+                animation.save_gif(frames, &cli.output)?;
+            } else {
+                animation.save_apng(frames, &cli.output)?;
+            }
+        }
+        _ => {
+            // A frame sequence has no frame delays, so the hold repeats the last frame.
+            let frame_count = animation.sequence_len();
+            if frame_count > MAX_FRAMES {
+                return Err(format!(
+                    "a frame sequence of {frame_count} files is too long; at most {MAX_FRAMES} \
+                     frames including the hold are written, so shorten --hold or lower --fps"
+                )
+                .into());
+            }
+            let digits = frame_count.saturating_sub(1).to_string().len().max(4);
+            (0..frame_count).into_par_iter().try_for_each(|frame| {
+                let file = numbered_path(&cli.output, frame, digits);
+                let frame_tree = animation.frame(tree, frame.min(animation.frames - 1));
+                cli.render
+                    .write_with_viewport(&frame_tree, viewport, format, &file)
+                    .map_err(|err| format!("could not write {}: {err}", file.display()))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn list_presets(cli: &Cli) -> Result<(), BoxError> {
+    let presets = Preset::builtins().into_iter().chain(cli.user_presets()?);
+    for preset in presets {
+        println!("{:<12} {}", preset.name, preset.description);
+    }
+    Ok(())
+}
+
+fn diff_presets(cli: &Cli, left: &str, right: &str) -> Result<(), BoxError> {
+    let diffs = cli.find_preset(left)?.diff(&cli.find_preset(right)?);
+    if diffs.is_empty() {
+        println!("{left} and {right} have the same parameters");
+    }
+    for diff in diffs {
+        println!("{diff}");
+    }
+    Ok(())
+}
+
+fn run(cli: &Cli) -> Result<(), BoxError> {
+    if cli.list_presets {
+        return list_presets(cli);
+    }
+    if let Some([left, right]) = cli.diff_presets.as_deref() {
+        return diff_presets(cli, left, right);
+    }
+
+    let format = cli.format()?;
+    let animation = cli.animation.animation()?;
+    let config = match &cli.pipeline {
+        Some(path) => {
+            let config = cli.load_pipeline(path)?;
+            config
+                .validate()
+                .map_err(|err| format!("invalid pipeline: {err}"))?;
+            if let Some(path) = &cli.save_params {
+                config.save(path)?;
+            }
+            config
+        }
+        None => {
+            let params = cli.params()?;
+            params
+                .validate()
+                .map_err(|err| format!("invalid parameters: {err}"))?;
+            if let Some(path) = &cli.save_params {
+                params.save(path)?;
+            }
+            if let Some(path) = &cli.save_preset {
+                let name = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                Preset::new(name, "", params).save(path)?;
+            }
+            let mut config = PipelineConfig::from(params);
+            if let Some(lsystem) = cli.lsystem()? {
+                config.stages.retain(|stage| {
+                    !matches!(stage.layer, LayerConfig::Trunk(_) | LayerConfig::Branch(_))
+                });
+                config.stages.insert(
+                    0,
+                    StageConfig {
+                        enabled: true,
+                        layer: LayerConfig::LSystem(lsystem),
+                    },
+                );
+            }
+            if cli.growth_years.is_some() {
+                let mut growth = GrowthParams::new(params.trunk, 0);
+                cli.apply_growth(&mut growth);
+                growth
+                    .validate()
+                    .map_err(|err| format!("invalid parameters: growth.{err}"))?;
+                for stage in &mut config.stages {
+                    if matches!(stage.layer, LayerConfig::Trunk(_)) {
+                        stage.layer = LayerConfig::Growth(growth);
+                    }
+                }
+            }
+            config
+        }
+    };
+    let pipeline = config.build();
+
+    if let Some(seeds) = cli.batch_seeds() {
+        return run_batch(cli, &config, &pipeline, format, seeds);
+    }
+
+    let (tree, report) = pipeline.run_with_report(Tree::new(), cli.seed);
+    match animation {
+        Some(animation) => write_animation(cli, &tree, &animation, format)?,
+        None => cli.render.write(&tree, format, &cli.output)?,
+    }
+    if let Some(path) = &cli.stats {
+        write_stats(path, &TreeStats::new(&tree.nodes))?;
+    }
+
+    print_summary(
+        cli,
+        &format!(
+            "{}\nwrote {}",
+            summarize(&tree, cli.seed, &report),
+            cli.output.display()
+        ),
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Runs the CLI with `args` and returns its error message.
+    fn run_err(args: &[&str]) -> String {
+        let output = std::env::temp_dir().join("treegen_cli_test.png");
+        let output = output.to_str().unwrap();
+        match Cli::try_parse_from(["treegen_cli", "-q", "-o", output].iter().chain(args)) {
+            Ok(cli) => run(&cli).unwrap_err().to_string(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn invalid_flags_are_rejected() {
+        let err = run_err(&["--branch-base-size-reduction", "0"]);
+        assert!(err.contains("branch.base_size_reduction"), "{err}");
+        let err = run_err(&["--trunk-variability=-1"]);
+        assert!(err.contains("trunk.variability"), "{err}");
+        let err = run_err(&["--collision-clearance=-1"]);
+        assert!(err.contains("trunk.collision.clearance"), "{err}");
+        let err = run_err(&["--growth-years", "5", "--growth-elongation=-1"]);
+        assert!(err.contains("growth.elongation"), "{err}");
+        let err = run_err(&["--animate", "3", "--fps", "inf"]);
+        assert!(err.contains("--fps must be finite"), "{err}");
+        let err = run_err(&["--animate", "1000000000"]);
+        assert!(err.contains("--animate must be at most"), "{err}");
+        let err = run_err(&["--animate", "3", "--hold", "1e12"]);
+        assert!(err.contains("--hold must be at most"), "{err}");
+        let err = run_err(&["--animate", "3", "--fps", "1000", "--hold", "3600"]);
+        assert!(err.contains("frame sequence of 3600003 files"), "{err}");
+        let err = run_err(&["--lsystem", "weed", "--lsystem-iterations", "100"]);
+        assert!(err.contains("lsystem.iterations"), "{err}");
+        let err = run_err(&["--branch-color", "#11223344"]);
+        assert!(err.contains("expected #rrggbb"), "{err}");
+    }
+
+    #[test]
+    fn invalid_animations_are_rejected_before_anything_is_written() {
+        let path = std::env::temp_dir().join("treegen_cli_test_animation_params.json");
+        let _ = fs::remove_file(&path);
+        let save = ["--save-params", path.to_str().unwrap()];
+        let err = run_err(&[&["--animate", "3", "--fps", "0"][..], &save].concat());
+        assert!(err.contains("--fps"), "{err}");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn invalid_config_files_are_rejected() {
+        let path = std::env::temp_dir().join("treegen_cli_test_config.json");
+        fs::write(&path, r#"{"branch": {"base_size_reduction": 1e-12}}"#).unwrap();
+        let err = run_err(&["--config", path.to_str().unwrap()]);
+        assert!(err.contains("branch.base_size_reduction 0.000000000001 is too small"), "{err}");
+        let distribution = r#"{"kind": "beta", "alpha": 0.0, "beta": 1.0}"#;
+        fs::write(&path, format!(r#"{{"branch": {{"wobble_distribution": {distribution}}}}}"#))
+            .unwrap();
+        let err = run_err(&["--config", path.to_str().unwrap()]);
+        assert!(err.contains("branch.wobble_distribution: beta shapes"), "{err}");
+    }
+
+    #[test]
+    fn invalid_pipeline_files_are_rejected() {
+        let path = std::env::temp_dir().join("treegen_cli_test_pipeline.json");
+        let pipeline = r#"{"stages": [{"layer": "trunk"}, {"layer": "branch", "variability": -1}]}"#;
+        fs::write(&path, pipeline).unwrap();
+        let err = run_err(&["--pipeline", path.to_str().unwrap()]);
+        assert!(err.contains("invalid pipeline: stages[1].variability"), "{err}");
+    }
+
+    #[test]
+    fn seed_ranges_are_parsed() {
+        assert_eq!(parse_seed_range("3..6"), Ok(3..=5));
+        assert_eq!(parse_seed_range(" 3 ..= 6"), Ok(3..=6));
+        assert_eq!(parse_seed_range("5..=5"), Ok(5..=5));
+        assert!(parse_seed_range("3-6").unwrap_err().starts_with("invalid seed range"));
+    }
+
+    #[test]
+    fn batches_are_bounded() {
+        let last = MAX_BATCH_SIZE - 1;
+        assert_eq!(parse_seed_range(&format!("0..{MAX_BATCH_SIZE}")), Ok(0..=last));
+        for range in [format!("0..={MAX_BATCH_SIZE}"), format!("5..={}", u64::MAX)] {
+            let err = parse_seed_range(&range).unwrap_err();
+            assert!(err.contains("holds more than"), "{err}");
+        }
+        let count = (MAX_BATCH_SIZE + 1).to_string();
+        assert!(Cli::try_parse_from(["treegen_cli", "--count", &count]).is_err());
+    }
+
+    #[test]
+    fn empty_seed_ranges_are_rejected() {
+        for range in ["0..0", "5..5", "9..3", "9..=3"] {
+            assert_eq!(
+                parse_seed_range(range),
+                Err(format!("seed range `{range}` is empty"))
+            );
+        }
+    }
+}
@@ -1,17 +1,44 @@
 use druid::{Data, Lens};
 use palette::Srgb;
 use rand::Rng;
-use rand_distr::{Distribution, Normal};
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
 
-use crate::skeleton::tree::Tree;
+use crate::{
+    sampler::{DistributionKind, Moments, Sampler},
+    skeleton::tree::Tree,
+    utils::rng::{derive_seed, stream_rng},
+};
 
-use super::layer::Layer;
+use super::{
+    collision::CollisionParams,
+    layer::Layer,
+    param_curve::{CurvePosition, ParamCurve},
+    validation::{distribution, finite, nested, non_negative, non_negative_curve, positive},
+};
 
-#[derive(Debug)]
-pub struct BranchLayer;
+const BRANCH_SEED_KEY: u64 = 0x4252_4e43;
 
-#[derive(Debug, Copy, Clone, Data, Lens)]
+/// Most nodes a single sub-branch chain grows. Sizes that shrink by a tiny amount, or not at
+/// all, would otherwise make chains practically endless.
+pub const MAX_CHAIN_NODES: usize = 1000;
+
+/// Sizes this close to `minimum_size` count as having reached it, so that rounding in the
+/// repeated subtraction of `base_size_reduction` does not add a node.
+const SIZE_TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BranchLayer {
+    pub params: BranchParams,
+}
+
+impl BranchLayer {
+    pub fn new(params: BranchParams) -> Self {
+        Self { params }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Data, Lens, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct BranchParams {
     pub spread: f64,
     pub branch: f64,
@@ -23,6 +50,18 @@ pub struct BranchParams {
     pub base_angle_mean_deg: f64,
     pub base_angle_std_dev_deg: f64,
     pub color: [u8; 3],
+    /// Distribution of the angle between a sub-branch and its parent.
+    pub base_angle_distribution: DistributionKind,
+    /// Distribution of the turn between consecutive nodes of a sub-branch.
+    pub wobble_distribution: DistributionKind,
+    /// How new segments avoid the ones already grown.
+    pub collision: CollisionParams,
+    /// Scales `initial_length` by where on the tree a sub-branch starts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length_curve: Option<ParamCurve>,
+    /// Scales `base_angle_mean_deg` by where on the tree a sub-branch starts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub angle_curve: Option<ParamCurve>,
 }
 
 impl BranchParams {
@@ -42,66 +81,202 @@ impl BranchParams {
             base_angle_mean_deg: 20.0,
             base_angle_std_dev_deg: 5.0,
             color: [0, 255, 0],
+            base_angle_distribution: DistributionKind::Normal,
+            wobble_distribution: DistributionKind::Normal,
+            collision: CollisionParams::default(),
+            length_curve: None,
+            angle_curve: None,
+        }
+    }
+
+    /// Number of nodes in every sub-branch chain, or `None` if a chain would grow more than
+    /// [`MAX_CHAIN_NODES`] before its size drops to `minimum_size`.
+    pub fn chain_length(&self) -> Option<usize> {
+        let mut size = self.initial_branch_size;
+        for length in 0..=MAX_CHAIN_NODES {
+            if size <= self.minimum_size + SIZE_TOLERANCE || size.is_nan() {
+                return Some(length);
+            }
+            size -= self.base_size_reduction;
+        }
+        None
+    }
+
+    /// Checks that every value is one sub-branches can grow with. In particular chains have
+    /// to shrink by a positive amount and end within [`MAX_CHAIN_NODES`] nodes.
+    pub fn validate(&self) -> Result<(), String> {
+        finite("spread", self.spread)?;
+        finite("branch", self.branch)?;
+        non_negative("variability", self.variability)?;
+        positive("base_size_reduction", self.base_size_reduction)?;
+        non_negative("minimum_size", self.minimum_size)?;
+        non_negative("initial_branch_size", self.initial_branch_size)?;
+        non_negative("initial_length", self.initial_length)?;
+        finite("base_angle_mean_deg", self.base_angle_mean_deg)?;
+        non_negative("base_angle_std_dev_deg", self.base_angle_std_dev_deg)?;
+        distribution("base_angle_distribution", &self.base_angle_distribution)?;
+        distribution("wobble_distribution", &self.wobble_distribution)?;
+        nested("collision", self.collision.validate())?;
+        non_negative_curve("length_curve", &self.length_curve)?;
+        if self.chain_length().is_none() {
+            return Err(format!(
+                "base_size_reduction {} is too small, chains from {} down to {} would grow more \
+                 than {MAX_CHAIN_NODES} nodes",
+                self.base_size_reduction, self.initial_branch_size, self.minimum_size
+            ));
         }
+        Ok(())
     }
 }
 
-impl Layer<BranchParams> for BranchLayer {
-    fn generate(mut tree: Tree, params: &BranchParams) -> Tree {
-        return tree;
-        let tip_nodes = tree.get_tip_nodes();
-
-        let mut processed_nodes = HashSet::new();
-
-        fn generate_branch(
-            node: usize,
-            tree: &mut Tree,
-            size: f64,
-            branch: f64,
-            processed_nodes: &mut HashSet<usize>,
-            params: &BranchParams,
-        ) {
-            if size <= params.minimum_size || processed_nodes.contains(&node) {
-                return;
+impl Default for BranchParams {
+    fn default() -> Self {
+        Self::new(1.0, 1.0, 0.5)
+    }
+}
+
+impl Layer for BranchLayer {
+    fn name(&self) -> &str {
+        "branch"
+    }
+
+    /// Grows a sub-branch off each existing node with probability `params.branch`.
+    ///
+    /// A sub-branch leaves its parent at `base_angle_mean_deg` (scaled by `spread`) to a
+    /// random side and continues as a chain of nodes, each one `base_size_reduction`
+    /// smaller than the last, until its size drops to `minimum_size`. Sizes are fractions of
+    /// the parent's thickness and of `initial_length`. Chains that would not end stop after
+    /// [`MAX_CHAIN_NODES`] nodes, and a chain that can't be placed without colliding stops
+    /// growing. The curves are measured against the extent of the tree the layer starts from.
+    fn generate(&self, mut tree: Tree, seed: u64) -> Tree {
+        let params = &self.params;
+        let seed = derive_seed(seed, BRANCH_SEED_KEY);
+        let color = Srgb::from(params.color);
+        let parent_count = tree.nodes.nodes.len();
+        let chain_length = params.chain_length().unwrap_or(MAX_CHAIN_NODES);
+
+        let positions = (params.length_curve.is_some() || params.angle_curve.is_some())
+            .then(|| CurvePosition::of_nodes(&tree.nodes, &CurvePosition::extent_of(&tree.nodes)));
+        // Scales `value` by `curve` at the end of node `j`, if both are set.
+        let scaled = |curve: Option<ParamCurve>, value: f64, j: usize| {
+            curve
+                .zip(positions.as_ref())
+                .map(|(curve, positions)| value * curve.value(&positions[j]))
+        };
+        let base_angle_with_mean = |mean_deg: f64| {
+            params.base_angle_distribution.sampler(Moments::angle(
+                params.spread * mean_deg,
+                params.variability * params.base_angle_std_dev_deg,
+                360.0,
+            ))
+        };
+        let base_angle = base_angle_with_mean(params.base_angle_mean_deg);
+        let wobble = params.wobble_distribution.sampler(Moments::angle(
+            0.0,
+            params.variability * params.base_angle_std_dev_deg,
+            360.0,
+        ));
+
+        for parent_index in 0..parent_count {
+            let mut local_rng = stream_rng(seed, parent_index as u64);
+            if local_rng.gen::<f64>() >= params.branch {
+                continue;
             }
-            let mut local_rng = rand::thread_rng();
 
-            processed_nodes.insert(node);
-            let should_branch = local_rng.gen::<f64>() < branch;
+            let parent = &tree.nodes.nodes[parent_index];
+            let parent_thickness = parent.thickness;
+            let side = if local_rng.gen::<bool>() { 1.0 } else { -1.0 };
+            let base_angle = scaled(params.angle_curve, params.base_angle_mean_deg, parent_index)
+                .map_or(base_angle, base_angle_with_mean);
+            let mut angle = parent.angle
+                + side * base_angle.sample(&mut local_rng).to_radians();
+            let initial_length = scaled(params.length_curve, params.initial_length, parent_index)
+                .unwrap_or(params.initial_length);
 
-            if !should_branch {
-                return;
+            let mut current = parent_index;
+            let mut size = params.initial_branch_size;
+            for _ in 0..chain_length {
+                let length = initial_length * size;
+                let thickness = parent_thickness * size;
+                let Some(placed) = params.collision.place(&tree, current, length, angle, thickness)
+                else {
+                    break;
+                };
+                angle = placed;
+                current = tree.add_node(Some(current), length, angle, thickness);
+                tree.nodes.nodes[current].set_color(color);
+
+                angle += wobble.sample(&mut local_rng).to_radians();
+                size -= params.base_size_reduction;
             }
-            let base_angle_normal =
-                Normal::new(params.base_angle_mean_deg, params.base_angle_std_dev_deg).unwrap();
-            let base_angle = base_angle_normal.sample(&mut local_rng).to_radians();
-
-            // Logic for creating sub-branches
-            let new_node = tree.add_node(Some(node), params.initial_length, base_angle, size);
-            tree.nodes.nodes[new_node].set_color(Srgb::from(params.color));
-
-            // Recursive call for child node
-            generate_branch(
-                new_node,
-                tree,
-                size - params.base_size_reduction,
-                branch,
-                processed_nodes,
-                params
-            );
         }
 
-        tip_nodes.iter().for_each(|&node| {
-            generate_branch(
-                node,
-                &mut tree,
-                params.initial_branch_size,
-                params.branch,
-                &mut processed_nodes,
-                params
-            );
-        });
-
         tree
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_end_where_the_size_drops_to_the_minimum() {
+        // 0.9 - 3 * 0.1 comes out a little above 0.6 in floating point, but that is still
+        // the minimum.
+        assert_eq!(BranchParams::new(1.0, 1.0, 0.5).chain_length(), Some(3));
+
+        let exact = BranchParams {
+            initial_branch_size: 1.0,
+            base_size_reduction: 0.125,
+            minimum_size: 0.5,
+            ..BranchParams::new(1.0, 1.0, 0.5)
+        };
+        assert_eq!(exact.chain_length(), Some(4));
+        let below = BranchParams {
+            minimum_size: 0.4,
+            ..exact
+        };
+        assert_eq!(below.chain_length(), Some(5));
+
+        let mut params = BranchParams::new(1.0, 1.0, 0.5);
+        params.minimum_size = 1.0;
+        assert_eq!(params.chain_length(), Some(0));
+    }
+
+    #[test]
+    fn negative_sizes_are_rejected() {
+        let params = BranchParams {
+            minimum_size: -0.5,
+            ..BranchParams::new(1.0, 1.0, 0.5)
+        };
+        let err = params.validate().unwrap_err();
+        assert!(err.contains("minimum_size must be finite and not negative"), "{err}");
+        let params = BranchParams {
+            initial_branch_size: -0.1,
+            ..BranchParams::new(1.0, 1.0, 0.5)
+        };
+        let err = params.validate().unwrap_err();
+        assert!(err.contains("initial_branch_size must be finite and not negative"), "{err}");
+    }
+
+    #[test]
+    fn endless_chains_have_no_length() {
+        let mut params = BranchParams::new(1.0, 1.0, 0.5);
+        params.base_size_reduction = 1e-12;
+        assert_eq!(params.chain_length(), None);
+
+        params.initial_branch_size = 1e20;
+        params.base_size_reduction = 1.0;
+        assert_eq!(params.chain_length(), None);
+    }
+
+    #[test]
+    fn endless_chains_stop_at_the_node_cap() {
+        let mut tree = Tree::new();
+        tree.add_node(None, 10.0, 0.0, 5.0);
+        let mut params = BranchParams::new(1.0, 1.0, 0.5);
+        params.base_size_reduction = 0.0;
+        let tree = BranchLayer::new(params).generate(tree, 0);
+        assert_eq!(tree.nodes.nodes.len(), 1 + MAX_CHAIN_NODES);
+    }
+}
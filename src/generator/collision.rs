@@ -0,0 +1,230 @@
+use druid::{Data, Lens};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    skeleton::{node::Point, tree::Tree},
+    utils::geometry::point_segment_distance,
+};
+
+use super::validation::non_negative;
+
+/// What a layer does with a new segment that comes too close to existing ones.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Data, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionMode {
+    /// Segments may overlap freely.
+    #[default]
+    Off,
+    /// The segment is not added.
+    Reject,
+    /// The segment is turned away in growing steps until it is clear, and rejected if no
+    /// turn within `max_steer_deg` is.
+    Steer,
+}
+
+#[derive(Debug, Copy, Clone, Data, Lens, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollisionParams {
+    pub mode: CollisionMode,
+    /// Smallest gap allowed between the surfaces of two segments.
+    pub clearance: f64,
+    /// Largest turn in degrees tried when steering.
+    pub max_steer_deg: f64,
+    /// Number of turns tried on each side when steering.
+    pub steer_attempts: usize,
+}
+
+impl CollisionParams {
+    pub fn new(mode: CollisionMode, clearance: f64) -> Self {
+        Self {
+            mode,
+            clearance,
+            max_steer_deg: 60.0,
+            steer_attempts: 6,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        non_negative("clearance", self.clearance)?;
+        non_negative("max_steer_deg", self.max_steer_deg)
+    }
+
+    /// Returns the angle a new child of `parent` should grow at, or `None` if it can't be
+    /// placed. The parent, and its ancestors and siblings where they reach the joint the new
+    /// segment grows from, never count as collisions. The parent's other children count if
+    /// the center lines of either comes within `clearance` of the other's end.
+    pub fn place(
+        &self,
+        tree: &Tree,
+        parent: usize,
+        length: f64,
+        angle: f64,
+        thickness: f64,
+    ) -> Option<f64> {
+        if self.mode == CollisionMode::Off {
+            return Some(angle);
+        }
+        if self.is_clear(tree, parent, length, angle, thickness) {
+            return Some(angle);
+        }
+        if self.mode == CollisionMode::Reject {
+            return None;
+        }
+
+        let attempts = self.steer_attempts.max(1);
+        let step = self.max_steer_deg.to_radians() / attempts as f64;
+        (1..=attempts)
+            .flat_map(|attempt| [angle + attempt as f64 * step, angle - attempt as f64 * step])
+            .find(|&steered| self.is_clear(tree, parent, length, steered, thickness))
+    }
+
+    fn is_clear(
+        &self,
+        tree: &Tree,
+        parent: usize,
+        length: f64,
+        angle: f64,
+        thickness: f64,
+    ) -> bool {
+        let graph = &tree.nodes;
+        let parent_node = &graph.nodes[parent];
+        let start = parent_node.next_point();
+        let end = Point::new(start.x + length * angle.sin(), start.y - length * angle.cos());
+        let reach = thickness / 2.0 + self.clearance;
+        // Parents come before their children, so the walk can stop below `index`.
+        let is_ancestor = |index: usize| {
+            let mut current = parent_node.parent_index;
+            while let Some(ancestor) = current.filter(|&ancestor| ancestor >= index) {
+                if ancestor == index {
+                    return true;
+                }
+                current = graph.nodes[ancestor].parent_index;
+            }
+            false
+        };
+
+        graph
+            .segments_near(start, end, thickness, self.clearance)
+            .into_iter()
+            .all(|index| {
+                let node = &graph.nodes[index];
+                if index == parent {
+                    return true;
+                }
+                if node.parent_index == Some(parent) {
+                    // Siblings fan out from the same joint, where their bodies always merge,
+                    // so only their center lines have to keep clear of each other.
+                    let sibling_end = node.next_point();
+                    return point_segment_distance(end, node.point, sibling_end) > self.clearance
+                        && point_segment_distance(sibling_end, start, end) > self.clearance;
+                }
+                // The thick ancestors below the joint and the siblings of the parent overlap
+                // the new segment there whichever way it grows. Any other segment collides.
+                let is_parent_sibling =
+                    node.parent_index.is_some() && node.parent_index == parent_node.parent_index;
+                let gap = point_segment_distance(start, node.point, node.next_point())
+                    - node.thickness / 2.0;
+                (is_parent_sibling || is_ancestor(index)) && gap <= reach
+            })
+    }
+}
+
+impl Default for CollisionParams {
+    fn default() -> Self {
+        Self::new(CollisionMode::Off, 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::presets::Preset;
+
+    fn reject(clearance: f64) -> CollisionParams {
+        CollisionParams::new(CollisionMode::Reject, clearance)
+    }
+
+    /// A thick root with one child leaning `angle_deg` to the right.
+    fn fork(angle_deg: f64) -> Tree {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 20.0, 0.0, 20.0);
+        tree.add_node(Some(root), 30.0, angle_deg.to_radians(), 16.0);
+        tree
+    }
+
+    #[test]
+    fn siblings_may_merge_at_their_joint() {
+        let tree = fork(20.0);
+        let angle = (-20f64).to_radians();
+        assert_eq!(reject(0.0).place(&tree, 0, 30.0, angle, 16.0), Some(angle));
+    }
+
+    #[test]
+    fn coincident_siblings_collide() {
+        let tree = fork(20.0);
+        assert_eq!(reject(2.0).place(&tree, 0, 30.0, 21f64.to_radians(), 16.0), None);
+    }
+
+    #[test]
+    fn children_of_a_fork_are_placed() {
+        let mut tree = fork(20.0);
+        tree.add_node(Some(0), 30.0, (-20f64).to_radians(), 16.0);
+        for sibling in [1, 2] {
+            let angle = tree.nodes.nodes[sibling].angle;
+            assert_eq!(reject(0.0).place(&tree, sibling, 30.0, angle, 12.0), Some(angle));
+        }
+    }
+
+    #[test]
+    fn crossing_branches_collide() {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 60.0, 0.0, 10.0);
+        let right = tree.add_node(Some(root), 40.0, 90f64.to_radians(), 4.0);
+        tree.add_node(Some(root), 50.0, 0.0, 8.0);
+        let up = tree.add_node(Some(right), 30.0, 0.0, 4.0);
+        // Grows left from above `right`, straight through the trunk.
+        assert_eq!(reject(0.0).place(&tree, up, 60.0, (-90f64).to_radians(), 4.0), None);
+        assert!(reject(0.0).place(&tree, up, 20.0, (-90f64).to_radians(), 4.0).is_some());
+    }
+
+    /// A fork whose right arm bends back to end where the left arm ends, unless `bent` is
+    /// false. Returns the tree and the left arm.
+    fn bent_fork(bent: bool) -> (Tree, usize) {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 20.0, 0.0, 10.0);
+        let left = tree.add_node(Some(root), 40.0, (-30f64).to_radians(), 4.0);
+        let right = tree.add_node(Some(root), 40.0, 30f64.to_radians(), 4.0);
+        if bent {
+            tree.add_node(Some(right), 40.0, (-90f64).to_radians(), 4.0);
+        }
+        (tree, left)
+    }
+
+    #[test]
+    fn unrelated_branches_at_the_joint_collide() {
+        let (tree, left) = bent_fork(false);
+        assert_eq!(reject(0.0).place(&tree, left, 20.0, 0.0, 4.0), Some(0.0));
+        let (tree, left) = bent_fork(true);
+        assert_eq!(reject(0.0).place(&tree, left, 20.0, 0.0, 4.0), None);
+    }
+
+    #[test]
+    fn presets_keep_their_size_when_rejecting_without_clearance() {
+        for name in Preset::builtin_names() {
+            let free = Preset::builtin(name).unwrap().params;
+            let mut rejecting = free;
+            rejecting.trunk.collision = reject(0.0);
+            rejecting.branch.collision = reject(0.0);
+
+            let free = free.generate(Tree::new(), 3).nodes;
+            let placed = rejecting.generate(Tree::new(), 3).nodes;
+            assert!(placed.nodes.len() >= 50, "{name} kept {} nodes", placed.nodes.len());
+            assert!(
+                placed.bounds().height >= 0.75 * free.bounds().height,
+                "{name} shrank from {} to {}",
+                free.bounds().height,
+                placed.bounds().height
+            );
+        }
+    }
+}
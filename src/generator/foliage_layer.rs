@@ -0,0 +1,277 @@
+use druid::{Data, Lens};
+use palette::{FromColor, Hsl, Srgb};
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    skeleton::{leaf::Leaf, tree::Tree},
+    utils::rng::{derive_seed, stream_rng, TreeRng},
+};
+
+use super::{
+    layer::Layer,
+    validation::{finite, non_negative},
+};
+
+const FOLIAGE_SEED_KEY: u64 = 0x4c45_4146;
+
+/// Most leaves a single node gets, which bounds [`FoliageParams::density`]. Denser foliage
+/// would hide the tree and take ages to draw.
+pub const MAX_LEAVES_PER_NODE: usize = 100;
+
+/// The palette leaves are colored from.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Data, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Season {
+    Spring,
+    #[default]
+    Summer,
+    Autumn,
+    /// No leaves at all.
+    Bare,
+}
+
+impl Season {
+    pub const ALL: [Season; 4] = [Season::Spring, Season::Summer, Season::Autumn, Season::Bare];
+
+    /// Base colors leaves are picked from before color variation is applied.
+    pub fn palette(&self) -> &'static [Srgb<u8>] {
+        const SPRING: [Srgb<u8>; 3] = [
+            Srgb::new(150, 200, 80),
+            Srgb::new(170, 215, 100),
+            Srgb::new(125, 185, 70),
+        ];
+        const SUMMER: [Srgb<u8>; 3] = [
+            Srgb::new(60, 120, 40),
+            Srgb::new(40, 100, 35),
+            Srgb::new(80, 140, 50),
+        ];
+        const AUTUMN: [Srgb<u8>; 4] = [
+            Srgb::new(200, 80, 30),
+            Srgb::new(220, 150, 40),
+            Srgb::new(170, 50, 30),
+            Srgb::new(190, 120, 40),
+        ];
+        match self {
+            Season::Spring => &SPRING,
+            Season::Summer => &SUMMER,
+            Season::Autumn => &AUTUMN,
+            Season::Bare => &[],
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FoliageLayer {
+    pub params: FoliageParams,
+}
+
+impl FoliageLayer {
+    pub fn new(params: FoliageParams) -> Self {
+        Self { params }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Data, Lens, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FoliageParams {
+    /// Mean number of leaves on every node that gets leaves.
+    pub density: f64,
+    /// Nodes at most this many steps away from a tip get leaves; 0 only decorates tips.
+    pub tip_depth: f64,
+    pub leaf_length: f64,
+    /// Width of a leaf relative to its length.
+    pub leaf_width: f64,
+    /// Relative standard deviation of the leaf size.
+    pub size_variability: f64,
+    /// Mean angle in degrees between a leaf and its branch.
+    pub orientation_deg: f64,
+    pub orientation_std_dev_deg: f64,
+    /// How far leaf colors stray from the season's palette, from 0 to 1.
+    pub color_variation: f64,
+    pub season: Season,
+}
+
+impl FoliageParams {
+    pub fn new(density: f64, leaf_length: f64, season: Season) -> Self {
+        Self {
+            density,
+            tip_depth: 2.0,
+            leaf_length,
+            leaf_width: 0.45,
+            size_variability: 0.2,
+            orientation_deg: 40.0,
+            orientation_std_dev_deg: 15.0,
+            color_variation: 0.3,
+            season,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        non_negative("density", self.density)?;
+        if self.density > MAX_LEAVES_PER_NODE as f64 {
+            return Err(format!(
+                "density must be at most {MAX_LEAVES_PER_NODE}, got {}",
+                self.density
+            ));
+        }
+        non_negative("tip_depth", self.tip_depth)?;
+        non_negative("leaf_length", self.leaf_length)?;
+        non_negative("leaf_width", self.leaf_width)?;
+        non_negative("size_variability", self.size_variability)?;
+        finite("orientation_deg", self.orientation_deg)?;
+        non_negative("orientation_std_dev_deg", self.orientation_std_dev_deg)?;
+        non_negative("color_variation", self.color_variation)
+    }
+}
+
+impl Default for FoliageParams {
+    fn default() -> Self {
+        Self::new(3.0, 8.0, Season::Summer)
+    }
+}
+
+impl Layer for FoliageLayer {
+    fn name(&self) -> &str {
+        "foliage"
+    }
+
+    /// Attaches leaves to every node within `tip_depth` steps of a tip.
+    ///
+    /// Each leaf sits at a random point along the upper part of its node's segment and
+    /// points `orientation_deg` away from it to a random side. The number of leaves per node
+    /// averages `density`, up to [`MAX_LEAVES_PER_NODE`].
+    fn generate(&self, mut tree: Tree, seed: u64) -> Tree {
+        let params = &self.params;
+        let palette = params.season.palette();
+        if palette.is_empty() || params.density.is_nan() || params.density <= 0.0 {
+            return tree;
+        }
+        let density = params.density.min(MAX_LEAVES_PER_NODE as f64);
+
+        let seed = derive_seed(seed, FOLIAGE_SEED_KEY);
+        let orientation_normal = Normal::new(
+            params.orientation_deg,
+            params.orientation_std_dev_deg.max(0.0),
+        )
+        .unwrap();
+        let size_normal = Normal::new(1.0, params.size_variability.max(0.0)).unwrap();
+        let tip_distances = tree.nodes.tip_distances();
+        let graph = &tree.nodes;
+
+        let leaves: Vec<Leaf> = (0..graph.nodes.len())
+            .into_par_iter()
+            .filter(|&index| tip_distances[index] as f64 <= params.tip_depth)
+            .flat_map_iter(|index| {
+                let mut local_rng = stream_rng(seed, index as u64);
+                let node = &graph.nodes[index];
+                let count = density.floor() as usize
+                    + usize::from(local_rng.gen::<f64>() < density.fract());
+
+                (0..count)
+                    .map(|_| {
+                        let along = local_rng.gen_range(0.3..=1.0);
+                        let side = if local_rng.gen::<bool>() { 1.0 } else { -1.0 };
+                        let angle = node.angle
+                            + side * orientation_normal.sample(&mut local_rng).to_radians();
+                        let length =
+                            params.leaf_length * size_normal.sample(&mut local_rng).max(0.2);
+                        let base = palette[local_rng.gen_range(0..palette.len())];
+                        Leaf {
+                            node_index: index,
+                            position: node.point + (node.next_point() - node.point) * along,
+                            angle,
+                            length,
+                            width: length * params.leaf_width,
+                            color: vary_color(base, params.color_variation, &mut local_rng),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        tree.leaves.extend(leaves);
+        tree
+    }
+}
+
+/// Shifts the hue, saturation and lightness of `color` by up to `variation` times a fixed
+/// maximum in either direction.
+fn vary_color(color: Srgb<u8>, variation: f64, rng: &mut TreeRng) -> Srgb<u8> {
+    let variation = variation.clamp(0.0, 1.0) as f32;
+    let mut hsl = Hsl::from_color(color.into_format::<f32>());
+    hsl.hue += variation * rng.gen_range(-15.0..=15.0);
+    hsl.saturation = (hsl.saturation + variation * rng.gen_range(-0.15..=0.15)).clamp(0.0, 1.0);
+    hsl.lightness = (hsl.lightness + variation * rng.gen_range(-0.1..=0.1)).clamp(0.0, 1.0);
+    Srgb::from_color(hsl).into_format()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{branch_layer::BranchLayer, trunk_layer::TrunkLayer};
+
+    fn bare_tree() -> Tree {
+        let tree = TrunkLayer::default().generate(Tree::new(), 3);
+        BranchLayer::default().generate(tree, 3)
+    }
+
+    #[test]
+    fn leaves_grow_near_tips_on_their_segments() {
+        let params = FoliageParams {
+            density: 2.5,
+            tip_depth: 1.0,
+            ..FoliageParams::default()
+        };
+        let tree = FoliageLayer::new(params).generate(bare_tree(), 5);
+        let tip_distances = tree.nodes.tip_distances();
+        assert!(!tree.leaves.is_empty());
+
+        let mut counts = vec![0; tree.nodes.nodes.len()];
+        for leaf in &tree.leaves {
+            assert!(tip_distances[leaf.node_index] <= 1);
+            counts[leaf.node_index] += 1;
+
+            let node = &tree.nodes.nodes[leaf.node_index];
+            let segment = node.next_point() - node.point;
+            let along = (leaf.position - node.point).dot(&segment) / segment.norm_squared();
+            assert!((0.3 - 1e-9..=1.0 + 1e-9).contains(&along), "{along}");
+        }
+        for (index, &count) in counts.iter().enumerate() {
+            if tip_distances[index] <= 1 {
+                assert!(count == 2 || count == 3, "node {index} has {count} leaves");
+            }
+        }
+    }
+
+    #[test]
+    fn bare_trees_have_no_leaves() {
+        let params = FoliageParams::new(5.0, 8.0, Season::Bare);
+        assert!(FoliageLayer::new(params).generate(bare_tree(), 5).leaves.is_empty());
+    }
+
+    #[test]
+    fn densities_are_bounded() {
+        for density in [f64::NAN, f64::INFINITY, MAX_LEAVES_PER_NODE as f64 + 0.5] {
+            let params = FoliageParams {
+                density,
+                ..FoliageParams::default()
+            };
+            assert!(params.validate().unwrap_err().starts_with("density"), "{density}");
+        }
+
+        // Unchecked densities still give at most the cap on every node.
+        let params = FoliageParams {
+            density: f64::INFINITY,
+            ..FoliageParams::default()
+        };
+        let tree = FoliageLayer::new(params).generate(bare_tree(), 5);
+        let mut counts = vec![0; tree.nodes.nodes.len()];
+        for leaf in &tree.leaves {
+            counts[leaf.node_index] += 1;
+        }
+        assert_eq!(counts.into_iter().max(), Some(MAX_LEAVES_PER_NODE));
+    }
+}
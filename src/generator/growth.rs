@@ -0,0 +1,306 @@
+use druid::{Data, Lens};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    skeleton::tree::Tree,
+    utils::rng::{derive_seed, rng_from_seed, TreeRng},
+};
+
+use super::{
+    layer::Layer,
+    trunk_layer::{TrunkParams, TRUNK_SEED_KEY},
+    validation::{nested, non_negative},
+};
+
+/// Upper bound on [`GrowthParams::years`]. Trees stop sprouting long before.
+pub const MAX_YEARS: usize = 10_000;
+
+#[derive(Debug, Copy, Clone, Data, Lens, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GrowthParams {
+    /// Sprouting rates, angles and full segment lengths, as for a one-shot trunk.
+    pub trunk: TrunkParams,
+    /// Number of steps [`GrowthLayer`] simulates.
+    pub years: usize,
+    /// Length of a new segment relative to its full length.
+    pub bud_length: f64,
+    /// Share of the length still missing that a segment grows every step.
+    pub elongation: f64,
+    /// Thickness of a new segment.
+    pub bud_thickness: f64,
+    /// Thickness a segment carrying a single tip gains every step. Segments carrying `n`
+    /// tips gain `sqrt(n)` times as much, so their cross sections keep up with the tips.
+    pub thickening: f64,
+}
+
+impl GrowthParams {
+    pub fn new(trunk: TrunkParams, years: usize) -> Self {
+        Self {
+            trunk,
+            years,
+            bud_length: 0.2,
+            elongation: 0.5,
+            bud_thickness: 1.0,
+            thickening: 0.2,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        nested("trunk", self.trunk.validate())?;
+        if self.years > MAX_YEARS {
+            return Err(format!("years must be at most {MAX_YEARS}, got {}", self.years));
+        }
+        non_negative("bud_length", self.bud_length)?;
+        non_negative("elongation", self.elongation)?;
+        non_negative("bud_thickness", self.bud_thickness)?;
+        non_negative("thickening", self.thickening)
+    }
+}
+
+impl Default for GrowthParams {
+    fn default() -> Self {
+        Self::new(TrunkParams::default(), 20)
+    }
+}
+
+/// Grows a tree step by step from a single bud, so it can be looked at at any age.
+///
+/// Every step, all segments grow older, thicker and closer to their full length, and then
+/// buds sprout at the split and branch rates of [`TrunkParams`] for that step. Sprouting
+/// stops once the trunk reaches the height a one-shot trunk would have; after that the tree
+/// only matures. Sprouting draws from the same random streams as the
+/// [`TrunkLayer`](super::trunk_layer::TrunkLayer), so with collisions off a simulation of
+/// that many steps branches exactly like the one-shot trunk grown from the same seed.
+#[derive(Debug, Clone)]
+pub struct GrowthSimulator {
+    pub params: GrowthParams,
+    tree: Tree,
+    rng: TreeRng,
+    seed: u64,
+    height: f64,
+    /// Nodes buds may sprout from: the tips the simulation started from and every node it
+    /// grew since.
+    sprouting: Vec<usize>,
+    /// Length every node grows toward, indexed like the tree's nodes.
+    full_lengths: Vec<f64>,
+    step: usize,
+}
+
+impl GrowthSimulator {
+    pub fn new(params: GrowthParams, seed: u64) -> Self {
+        Self::with_tree(params, Tree::new(), seed)
+    }
+
+    /// Grows on top of `tree`. An empty tree gets a new root; otherwise buds sprout from the
+    /// tips already in the tree. Nodes already in the tree age and thicken with the
+    /// simulation but keep their length.
+    pub fn with_tree(params: GrowthParams, mut tree: Tree, seed: u64) -> Self {
+        let seed = derive_seed(seed, TRUNK_SEED_KEY);
+        let mut rng = rng_from_seed(seed);
+        let height = params.trunk.sample_height(&mut rng);
+
+        let mut full_lengths: Vec<f64> =
+            tree.nodes.nodes.iter().map(|node| node.length).collect();
+        let sprouting = if tree.nodes.nodes.is_empty() {
+            let root = tree.add_node(
+                None,
+                params.bud_length * 2.0 * height,
+                0.0,
+                params.bud_thickness,
+            );
+            full_lengths.push(2.0 * height);
+            vec![root]
+        } else {
+            tree.get_tip_nodes()
+        };
+
+        Self {
+            params,
+            tree,
+            rng,
+            seed,
+            height,
+            sprouting,
+            full_lengths,
+            step: 0,
+        }
+    }
+
+    /// Number of steps simulated so far.
+    pub fn step_count(&self) -> usize {
+        self.step
+    }
+
+    /// Whether buds still sprout in the next step.
+    pub fn is_sprouting(&self) -> bool {
+        (self.step as f64) < self.height.floor()
+    }
+
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    /// Returns a copy of the tree as it is after the steps simulated so far.
+    pub fn snapshot(&self) -> Tree {
+        self.tree.clone()
+    }
+
+    pub fn into_tree(self) -> Tree {
+        self.tree
+    }
+
+    /// Advances the simulation by one step.
+    pub fn step(&mut self) {
+        let params = self.params;
+        let tip_counts = tip_counts(&self.tree);
+        for ((node, &full_length), &tips) in self
+            .tree
+            .nodes
+            .nodes
+            .iter_mut()
+            .zip(&self.full_lengths)
+            .zip(&tip_counts)
+        {
+            node.age += 1;
+            node.length += (full_length - node.length) * params.elongation;
+            node.thickness += params.thickening * (tips as f64).sqrt();
+        }
+        self.tree.nodes.update_points();
+
+        if self.is_sprouting() {
+            let buds = params.trunk.sprout(
+                &self.tree,
+                &self.sprouting,
+                self.step,
+                &mut self.rng,
+                self.seed,
+            );
+            for bud in buds {
+                let length = params.bud_length * bud.length;
+                let Some(angle) = params.trunk.collision.place(
+                    &self.tree,
+                    bud.parent_index,
+                    length,
+                    bud.angle,
+                    params.bud_thickness,
+                ) else {
+                    continue;
+                };
+                let thickness = params.bud_thickness;
+                let index = self.tree.add_node(Some(bud.parent_index), length, angle, thickness);
+                self.tree.nodes.nodes[index].set_color(bud.color);
+                self.full_lengths.push(bud.length);
+                self.sprouting.push(index);
+            }
+        }
+        self.step += 1;
+    }
+
+    /// Advances the simulation by `steps` steps.
+    pub fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+}
+
+/// Returns the number of tips above every node, indexed like the tree's nodes.
+fn tip_counts(tree: &Tree) -> Vec<usize> {
+    let nodes = &tree.nodes.nodes;
+    let mut counts = vec![0; nodes.len()];
+    for (index, node) in nodes.iter().enumerate().rev() {
+        counts[index] = if node.children_indices.is_empty() {
+            1
+        } else {
+            node.children_indices
+                .iter()
+                .map(|&child| counts[child])
+                .sum()
+        };
+    }
+    counts
+}
+
+/// Grows a trunk with a [`GrowthSimulator`] for `years` steps, as a one-shot layer.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct GrowthLayer {
+    pub params: GrowthParams,
+}
+
+impl GrowthLayer {
+    pub fn new(params: GrowthParams) -> Self {
+        Self { params }
+    }
+}
+
+impl Layer for GrowthLayer {
+    fn name(&self) -> &str {
+        "growth"
+    }
+
+    fn generate(&self, tree: Tree, seed: u64) -> Tree {
+        let mut simulator = GrowthSimulator::with_tree(self.params, tree, seed);
+        simulator.run(self.params.years);
+        simulator.into_tree()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::trunk_layer::TrunkLayer;
+
+    #[test]
+    fn growth_branches_like_the_one_shot_trunk() {
+        let trunk = TrunkParams::default();
+        for seed in [0, 5, 11] {
+            let one_shot = TrunkLayer::new(trunk).generate(Tree::new(), seed);
+            let grown = GrowthLayer::new(GrowthParams::new(trunk, 40)).generate(Tree::new(), seed);
+
+            let shape = |tree: &Tree| {
+                tree.nodes
+                    .nodes
+                    .iter()
+                    .map(|node| (node.parent_index, node.angle, node.color))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(shape(&grown), shape(&one_shot), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn segments_age_lengthen_and_thicken() {
+        let params = GrowthParams::default();
+        let mut simulator = GrowthSimulator::new(params, 3);
+        simulator.run(3);
+        let before = simulator.snapshot();
+        simulator.step();
+        for (old, new) in before.nodes.nodes.iter().zip(&simulator.tree().nodes.nodes) {
+            assert_eq!(new.age, old.age + 1);
+            assert!(new.length > old.length);
+            assert!(new.thickness > old.thickness);
+        }
+    }
+
+    #[test]
+    fn growth_on_an_existing_tree_sprouts_from_its_tips_and_round_trips() {
+        let trunk = TrunkParams::default();
+        let base = TrunkLayer::new(trunk).generate(Tree::new(), 2);
+        let base_tips = base.get_tip_nodes();
+        let grown = GrowthLayer::new(GrowthParams::new(trunk, 3)).generate(base.clone(), 2);
+        assert!(grown.nodes.nodes.len() > base.nodes.nodes.len());
+
+        let roots = grown.nodes.nodes.iter().filter(|node| node.parent_index.is_none());
+        assert_eq!(roots.count(), 1);
+        for node in &grown.nodes.nodes[base.nodes.nodes.len()..] {
+            let parent = node.parent_index.unwrap();
+            assert!(parent >= base.nodes.nodes.len() || base_tips.contains(&parent));
+        }
+
+        let shape = |tree: &Tree| format!("{:?}", tree.nodes.nodes);
+        let loaded = Tree::from_json(&grown.to_json().unwrap()).unwrap();
+        assert_eq!(shape(&loaded), shape(&grown));
+        let loaded = Tree::from_binary(&grown.to_binary().unwrap()).unwrap();
+        assert_eq!(shape(&loaded), shape(&grown));
+    }
+}
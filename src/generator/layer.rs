@@ -1,8 +1,50 @@
+use std::fmt::Debug;
+
 use crate::skeleton::tree::Tree;
 
-pub trait Layer<Params> where Params : Copy {
-    fn generate(
-        tree: Tree,
-        params: &Params
-    ) -> Tree;
+/// A generation pass over a [`Tree`].
+///
+/// Layers own their parameters, so differently configured layers can be stored side by side
+/// as `Box<dyn Layer>` and chained in a [`Pipeline`](super::pipeline::Pipeline).
+///
+/// All randomness is derived from `seed`: running a layer on the same tree with the same
+/// params and seed always produces an identical `NodeGraph`, regardless of how many threads
+/// rayon uses.
+pub trait Layer: Debug + Send + Sync {
+    /// A short name used in reports.
+    fn name(&self) -> &str;
+
+    fn generate(&self, tree: Tree, seed: u64) -> Tree;
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::ThreadPoolBuilder;
+
+    use super::*;
+    use crate::generator::tree_params::TreeParams;
+
+    /// Runs the default pipeline on a pool of `threads` threads. Trees are compared through
+    /// their debug output, which prints every float exactly.
+    fn generate_on(threads: usize, seed: u64) -> String {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let tree = TreeParams::default().generate(Tree::new(), seed);
+            format!("{:?} {:?}", tree.nodes.nodes, tree.leaves)
+        })
+    }
+
+    #[test]
+    fn seeds_give_the_same_tree_on_any_number_of_threads() {
+        for seed in [0, 7, 42] {
+            let single = generate_on(1, seed);
+            for threads in [2, 3, 8] {
+                let threaded = generate_on(threads, seed);
+                assert_eq!(threaded, single, "seed {seed}, {threads} threads");
+            }
+        }
+    }
 }
@@ -0,0 +1,152 @@
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lsystem::{grammar::MAX_ITERATIONS, LSystem, Turtle},
+    skeleton::tree::Tree,
+    utils::rng::{derive_seed, rng_from_seed},
+};
+
+use super::{
+    layer::Layer,
+    validation::{finite, non_negative, positive},
+};
+
+const LSYSTEM_SEED_KEY: u64 = 0x4c53_5953;
+
+/// Grows a tree by deriving an L-system and drawing the result with a [`Turtle`]. On an
+/// empty tree the drawing starts a new root; otherwise it continues from the end of the
+/// root segment.
+#[derive(Debug, Default, Clone)]
+pub struct LSystemLayer {
+    pub params: LSystemParams,
+}
+
+impl LSystemLayer {
+    pub fn new(params: LSystemParams) -> Self {
+        Self { params }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LSystemParams {
+    /// The grammar, either as text or as the name of an
+    /// [example](crate::lsystem::examples::NAMES).
+    pub grammar: LSystem,
+    /// Derivation depth. Defaults to the grammar's `iterations:` line.
+    pub iterations: Option<usize>,
+    /// Turning angle in degrees. Defaults to the grammar's `angle:` line.
+    pub angle_deg: Option<f64>,
+    pub step_length: f64,
+    pub thickness: f64,
+    pub thickness_ratio: f64,
+    pub color: [u8; 3],
+}
+
+impl LSystemParams {
+    pub fn new(grammar: LSystem) -> Self {
+        Self {
+            grammar,
+            iterations: None,
+            angle_deg: None,
+            step_length: 5.0,
+            thickness: 6.0,
+            thickness_ratio: 0.8,
+            color: [90, 60, 40],
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(iterations) = self.iterations {
+            if iterations > MAX_ITERATIONS {
+                return Err(format!(
+                    "iterations must be at most {MAX_ITERATIONS}, got {iterations}"
+                ));
+            }
+        }
+        if let Some(angle_deg) = self.angle_deg {
+            finite("angle_deg", angle_deg)?;
+        }
+        positive("step_length", self.step_length)?;
+        non_negative("thickness", self.thickness)?;
+        non_negative("thickness_ratio", self.thickness_ratio)
+    }
+}
+
+impl Default for LSystemParams {
+    fn default() -> Self {
+        Self::new(LSystem::example("fractal_plant").unwrap())
+    }
+}
+
+impl Layer for LSystemLayer {
+    fn name(&self) -> &str {
+        "lsystem"
+    }
+
+    fn generate(&self, mut tree: Tree, seed: u64) -> Tree {
+        let params = &self.params;
+        let grammar = &params.grammar;
+        let mut rng = rng_from_seed(derive_seed(seed, LSYSTEM_SEED_KEY));
+        let modules = grammar.derive(params.iterations.unwrap_or(grammar.iterations), &mut rng);
+
+        let turtle = Turtle {
+            angle_deg: params.angle_deg.unwrap_or(grammar.angle_deg),
+            step_length: params.step_length,
+            thickness: params.thickness,
+            thickness_ratio: params.thickness_ratio,
+            color: Srgb::from(params.color),
+        };
+        let base = (!tree.nodes.nodes.is_empty()).then_some(0);
+        turtle.interpret(&modules, &mut tree, base);
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generator::trunk_layer::TrunkLayer, lsystem::examples};
+
+    fn shape(tree: &Tree) -> String {
+        format!("{:?}", tree.nodes.nodes)
+    }
+
+    #[test]
+    fn examples_grow_nodes() {
+        for name in examples::NAMES {
+            let mut params = LSystemParams::new(LSystem::example(name).unwrap());
+            params.iterations = Some(3);
+            let tree = LSystemLayer::new(params).generate(Tree::new(), 0);
+            assert!(!tree.nodes.nodes.is_empty(), "{name}");
+            let roots = tree.nodes.nodes.iter().filter(|node| node.parent_index.is_none());
+            assert_eq!(roots.count(), 1, "{name}");
+        }
+    }
+
+    #[test]
+    fn stochastic_grammars_are_deterministic_per_seed() {
+        let layer = LSystemLayer::new(LSystemParams::new(
+            LSystem::example("stochastic_weed").unwrap(),
+        ));
+        let trees: Vec<String> = (0..4)
+            .map(|seed| shape(&layer.generate(Tree::new(), seed)))
+            .collect();
+        for (seed, tree) in trees.iter().enumerate() {
+            assert_eq!(&shape(&layer.generate(Tree::new(), seed as u64)), tree);
+        }
+        assert!(trees.iter().any(|tree| tree != &trees[0]));
+    }
+
+    #[test]
+    fn drawing_after_another_layer_keeps_a_single_root() {
+        let trunk = TrunkLayer::default().generate(Tree::new(), 1);
+        let tree = LSystemLayer::default().generate(trunk.clone(), 1);
+        assert!(tree.nodes.nodes.len() > trunk.nodes.nodes.len());
+        let roots = tree.nodes.nodes.iter().filter(|node| node.parent_index.is_none());
+        assert_eq!(roots.count(), 1);
+        let loaded = Tree::from_json(&tree.to_json().unwrap()).unwrap();
+        assert_eq!(shape(&loaded), shape(&tree));
+    }
+}
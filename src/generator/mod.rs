@@ -1,3 +1,15 @@
 pub mod layer;
+pub mod collision;
 pub mod trunk_layer;
 pub mod branch_layer;
+pub mod foliage_layer;
+pub mod growth;
+pub mod space_colonization_layer;
+pub mod lsystem_layer;
+pub mod tropism_layer;
+pub mod pipe_model_layer;
+pub mod param_curve;
+pub mod pipeline;
+pub mod tree_params;
+pub mod presets;
+mod validation;
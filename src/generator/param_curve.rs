@@ -0,0 +1,173 @@
+use druid::{Data, Lens};
+use serde::{Deserialize, Serialize};
+
+use crate::{skeleton::node::NodeGraph, utils::curve::Curve};
+
+/// What a [`ParamCurve`] is a function of. Every input runs from 0 at the root to 1 at an
+/// extent that depends on the layer the curve is used in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Data, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveInput {
+    /// Number of segments between the root and the end of a node.
+    #[default]
+    Depth,
+    /// Height of the end of a node above the root.
+    Height,
+    /// Length of the path from the root to the end of a node.
+    Distance,
+}
+
+/// A curve that scales a parameter along the tree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Data, Lens, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParamCurve {
+    pub input: CurveInput,
+    pub curve: Curve,
+}
+
+impl ParamCurve {
+    pub fn new(input: CurveInput, curve: Curve) -> Self {
+        Self { input, curve }
+    }
+
+    /// Returns the value of the curve at `position`.
+    pub fn value(&self, position: &CurvePosition) -> f64 {
+        self.curve.evaluate(match self.input {
+            CurveInput::Depth => position.depth,
+            CurveInput::Height => position.height,
+            CurveInput::Distance => position.distance,
+        })
+    }
+}
+
+/// Where the end of a node lies along the tree, for every [`CurveInput`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CurvePosition {
+    pub depth: f64,
+    pub height: f64,
+    pub distance: f64,
+}
+
+impl CurvePosition {
+    /// Returns the position of the end of every node of `graph`, indexed like its nodes,
+    /// with every input divided by the matching value of `extent`.
+    pub fn of_nodes(graph: &NodeGraph, extent: &CurvePosition) -> Vec<CurvePosition> {
+        let root = graph.root_position();
+        let mut raw: Vec<CurvePosition> = Vec::with_capacity(graph.nodes.len());
+        for node in &graph.nodes {
+            let parent = node
+                .parent_index
+                .map_or_else(CurvePosition::default, |parent| raw[parent]);
+            raw.push(CurvePosition {
+                depth: parent.depth + 1.0,
+                height: root.y - node.next_point().y,
+                distance: parent.distance + node.length,
+            });
+        }
+
+        let divide = |value: f64, extent: f64| if extent > 0.0 { value / extent } else { 0.0 };
+        raw.into_iter()
+            .map(|position| CurvePosition {
+                depth: divide(position.depth, extent.depth),
+                height: divide(position.height, extent.height),
+                distance: divide(position.distance, extent.distance),
+            })
+            .collect()
+    }
+
+    /// Returns the largest value of every input over the ends of the nodes of `graph`, so
+    /// that positions measured against it run from 0 to 1 over the whole tree.
+    pub fn extent_of(graph: &NodeGraph) -> CurvePosition {
+        let unit = CurvePosition {
+            depth: 1.0,
+            height: 1.0,
+            distance: 1.0,
+        };
+        Self::of_nodes(graph, &unit).into_iter().fold(
+            CurvePosition::default(),
+            |extent, position| CurvePosition {
+                depth: extent.depth.max(position.depth),
+                height: extent.height.max(position.height),
+                distance: extent.distance.max(position.distance),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{skeleton::node::Point, utils::curve::Interpolation};
+
+    /// A root at (10, 100) grown 10 straight up, then 10 sideways, then 20 straight up.
+    fn bent_graph() -> NodeGraph {
+        let mut graph = NodeGraph::with_root(Point::new(10.0, 100.0));
+        let root = graph.add_node(None, 10.0, 0.0, 1.0);
+        let side = graph.add_node(Some(root), 10.0, std::f64::consts::FRAC_PI_2, 1.0);
+        graph.add_node(Some(side), 20.0, 0.0, 1.0);
+        graph
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+        }
+    }
+
+    #[test]
+    fn positions_are_divided_by_the_extent() {
+        let graph = bent_graph();
+        let extent = CurvePosition {
+            depth: 4.0,
+            height: 60.0,
+            distance: 80.0,
+        };
+        let positions = CurvePosition::of_nodes(&graph, &extent);
+        let inputs = |input: fn(&CurvePosition) -> f64| -> Vec<f64> {
+            positions.iter().map(input).collect()
+        };
+        assert_close(&inputs(|p| p.depth), &[0.25, 0.5, 0.75]);
+        assert_close(&inputs(|p| p.height), &[10.0 / 60.0, 10.0 / 60.0, 0.5]);
+        assert_close(&inputs(|p| p.distance), &[0.125, 0.25, 0.5]);
+    }
+
+    #[test]
+    fn the_extent_of_a_graph_spans_it_from_zero_to_one() {
+        let graph = bent_graph();
+        let extent = CurvePosition::extent_of(&graph);
+        assert_eq!(
+            extent,
+            CurvePosition {
+                depth: 3.0,
+                height: 30.0,
+                distance: 40.0,
+            }
+        );
+        let last = CurvePosition::of_nodes(&graph, &extent)[2];
+        assert_eq!((last.depth, last.height, last.distance), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn zero_extents_map_everything_to_zero() {
+        let positions = CurvePosition::of_nodes(&bent_graph(), &CurvePosition::default());
+        assert!(positions.iter().all(|position| *position == CurvePosition::default()));
+    }
+
+    #[test]
+    fn curves_read_the_input_they_are_set_to() {
+        let position = CurvePosition {
+            depth: 0.2,
+            height: 0.5,
+            distance: 0.8,
+        };
+        let identity = Curve::new(Interpolation::Linear, &[[0.0, 0.0], [1.0, 1.0]]).unwrap();
+        for (input, expected) in [
+            (CurveInput::Depth, 0.2),
+            (CurveInput::Height, 0.5),
+            (CurveInput::Distance, 0.8),
+        ] {
+            assert_eq!(ParamCurve::new(input, identity).value(&position), expected);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use druid::{Data, Lens};
+use serde::{Deserialize, Serialize};
+
+use crate::skeleton::{node::NodeGraph, tree::Tree};
+
+use super::{
+    layer::Layer,
+    validation::{non_negative, positive},
+};
+
+/// Recomputes every thickness from the tips down with the pipe model, so that branches taper
+/// the same way whichever layers grew them.
+///
+/// Tips get `tip_thickness`, and every other node gets the thickness `t` that satisfies
+/// `t^exponent = sum(t_child^exponent)`. An exponent of 2 keeps the cross section constant
+/// across a fork (the pipe model, Leonardo's rule); 3 is Murray's law.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PipeModelLayer {
+    pub params: PipeModelParams,
+}
+
+impl PipeModelLayer {
+    pub fn new(params: PipeModelParams) -> Self {
+        Self { params }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Data, Lens, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipeModelParams {
+    pub exponent: f64,
+    pub tip_thickness: f64,
+}
+
+impl PipeModelParams {
+    pub fn new(exponent: f64, tip_thickness: f64) -> Self {
+        Self {
+            exponent,
+            tip_thickness,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        positive("exponent", self.exponent)?;
+        non_negative("tip_thickness", self.tip_thickness)
+    }
+}
+
+impl Default for PipeModelParams {
+    fn default() -> Self {
+        Self::new(2.0, 1.0)
+    }
+}
+
+impl Layer for PipeModelLayer {
+    fn name(&self) -> &str {
+        "pipe_model"
+    }
+
+    fn generate(&self, mut tree: Tree, _seed: u64) -> Tree {
+        assign_thickness(&mut tree.nodes, 0, &self.params);
+        tree.nodes.rebuild_index();
+        tree
+    }
+}
+
+/// Sets the thickness of every node from `first` on with the pipe model. Nodes before
+/// `first` keep their thickness.
+pub(super) fn assign_thickness(graph: &mut NodeGraph, first: usize, params: &PipeModelParams) {
+    let nodes = &mut graph.nodes;
+    // Children come after their parents, so walking backwards visits every child first.
+    for index in (first..nodes.len()).rev() {
+        let thickness = if nodes[index].children_indices.is_empty() {
+            params.tip_thickness
+        } else {
+            nodes[index]
+                .children_indices
+                .iter()
+                .map(|&child| nodes[child].thickness.powf(params.exponent))
+                .sum::<f64>()
+                .powf(params.exponent.recip())
+        };
+        nodes[index].thickness = thickness;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::tree_params::TreeParams;
+
+    #[test]
+    fn forks_keep_the_sum_of_powers() {
+        let params = PipeModelParams::new(3.0, 0.5);
+        let tree = TreeParams::default().pipeline().run(Tree::new(), 4);
+        let tree = PipeModelLayer::new(params).generate(tree, 4);
+        let nodes = &tree.nodes.nodes;
+        assert!(nodes.len() > 10);
+        for node in nodes {
+            if node.children_indices.is_empty() {
+                assert_eq!(node.thickness, params.tip_thickness);
+                continue;
+            }
+            let carried: f64 = node
+                .children_indices
+                .iter()
+                .map(|&child| nodes[child].thickness.powf(params.exponent))
+                .sum();
+            let own = node.thickness.powf(params.exponent);
+            assert!((own - carried).abs() <= 1e-9 * carried, "{own} != {carried}");
+        }
+    }
+
+    #[test]
+    fn exponents_must_be_positive() {
+        let err = PipeModelParams::new(0.0, 1.0).validate().unwrap_err();
+        assert!(err.starts_with("exponent"), "{err}");
+    }
+}
@@ -0,0 +1,430 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::skeleton::{serialization::SerializationError, tree::Tree};
+
+use super::{
+    branch_layer::{BranchLayer, BranchParams},
+    foliage_layer::{FoliageLayer, FoliageParams},
+    growth::{GrowthLayer, GrowthParams},
+    layer::Layer,
+    lsystem_layer::{LSystemLayer, LSystemParams},
+    pipe_model_layer::{PipeModelLayer, PipeModelParams},
+    space_colonization_layer::{SpaceColonizationLayer, SpaceColonizationParams},
+    tree_params::TreeParams,
+    tropism_layer::{TropismLayer, TropismParams},
+    trunk_layer::{TrunkLayer, TrunkParams},
+    validation::nested,
+};
+
+/// A layer in a [`Pipeline`] together with its toggle.
+#[derive(Debug)]
+pub struct Stage {
+    pub layer: Box<dyn Layer>,
+    pub enabled: bool,
+}
+
+/// An ordered list of layers run one after another over a [`Tree`].
+///
+/// Every layer receives the same seed; layers derive their own random streams from it, so
+/// disabling one layer does not change what the others generate.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+}
+
+/// What a single stage did during [`Pipeline::run_with_report`].
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerReport {
+    pub name: String,
+    pub enabled: bool,
+    pub duration: Duration,
+    pub nodes_before: usize,
+    pub nodes_after: usize,
+    pub leaves_before: usize,
+    pub leaves_after: usize,
+}
+
+impl LayerReport {
+    pub fn nodes_added(&self) -> usize {
+        self.nodes_after.saturating_sub(self.nodes_before)
+    }
+
+    pub fn leaves_added(&self) -> usize {
+        self.leaves_after.saturating_sub(self.leaves_before)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PipelineReport {
+    pub layers: Vec<LayerReport>,
+}
+
+impl PipelineReport {
+    pub fn total_duration(&self) -> Duration {
+        self.layers.iter().map(|layer| layer.duration).sum()
+    }
+}
+
+impl Display for PipelineReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for layer in &self.layers {
+            if layer.enabled {
+                write!(
+                    f,
+                    "{:<12} {:>9.2?} {:>8} nodes (+{})",
+                    layer.name,
+                    layer.duration,
+                    layer.nodes_after,
+                    layer.nodes_added()
+                )?;
+                if layer.leaves_added() > 0 {
+                    write!(f, " {} leaves (+{})", layer.leaves_after, layer.leaves_added())?;
+                }
+                writeln!(f)?;
+            } else {
+                writeln!(f, "{:<12} disabled", layer.name)?;
+            }
+        }
+        write!(f, "{:<12} {:>9.2?}", "total", self.total_duration())
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an enabled layer.
+    pub fn with_layer<L: Layer + 'static>(self, layer: L) -> Self {
+        self.with_stage(layer, true)
+    }
+
+    pub fn with_stage<L: Layer + 'static>(mut self, layer: L, enabled: bool) -> Self {
+        self.push(Box::new(layer), enabled);
+        self
+    }
+
+    pub fn push(&mut self, layer: Box<dyn Layer>, enabled: bool) {
+        self.stages.push(Stage { layer, enabled });
+    }
+
+    /// Enables or disables the stage at `index`. Returns `false` if there is no such stage.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        match self.stages.get_mut(index) {
+            Some(stage) => {
+                stage.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Runs every enabled layer in order.
+    pub fn run(&self, tree: Tree, seed: u64) -> Tree {
+        self.run_with_report(tree, seed).0
+    }
+
+    /// Runs every enabled layer in order, timing each one and counting the nodes and leaves
+    /// it added.
+    pub fn run_with_report(&self, mut tree: Tree, seed: u64) -> (Tree, PipelineReport) {
+        let mut report = PipelineReport::default();
+        for stage in &self.stages {
+            let nodes_before = tree.nodes.nodes.len();
+            let leaves_before = tree.leaves.len();
+            let start = Instant::now();
+            if stage.enabled {
+                tree = stage.layer.generate(tree, seed);
+            }
+            report.layers.push(LayerReport {
+                name: stage.layer.name().to_owned(),
+                enabled: stage.enabled,
+                duration: start.elapsed(),
+                nodes_before,
+                nodes_after: tree.nodes.nodes.len(),
+                leaves_before,
+                leaves_after: tree.leaves.len(),
+            });
+        }
+        (tree, report)
+    }
+}
+
+/// A serializable description of a layer, used to build pipelines at runtime.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "layer", rename_all = "snake_case")]
+pub enum LayerConfig {
+    Trunk(TrunkParams),
+    Branch(BranchParams),
+    Foliage(FoliageParams),
+    SpaceColonization(SpaceColonizationParams),
+    #[serde(rename = "lsystem")]
+    LSystem(LSystemParams),
+    Tropism(TropismParams),
+    Growth(GrowthParams),
+    PipeModel(PipeModelParams),
+}
+
+impl LayerConfig {
+    pub fn build(&self) -> Box<dyn Layer> {
+        match self {
+            LayerConfig::Trunk(params) => Box::new(TrunkLayer::new(*params)),
+            LayerConfig::Branch(params) => Box::new(BranchLayer::new(*params)),
+            LayerConfig::Foliage(params) => Box::new(FoliageLayer::new(*params)),
+            LayerConfig::SpaceColonization(params) => {
+                Box::new(SpaceColonizationLayer::new(params.clone()))
+            }
+            LayerConfig::LSystem(params) => Box::new(LSystemLayer::new(params.clone())),
+            LayerConfig::Tropism(params) => Box::new(TropismLayer::new(*params)),
+            LayerConfig::Growth(params) => Box::new(GrowthLayer::new(*params)),
+            LayerConfig::PipeModel(params) => Box::new(PipeModelLayer::new(*params)),
+        }
+    }
+
+    /// Checks that the layer's params are ones it can grow a tree with.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            LayerConfig::Trunk(params) => params.validate(),
+            LayerConfig::Branch(params) => params.validate(),
+            LayerConfig::Foliage(params) => params.validate(),
+            LayerConfig::SpaceColonization(params) => params.validate(),
+            LayerConfig::LSystem(params) => params.validate(),
+            LayerConfig::Tropism(params) => params.validate(),
+            LayerConfig::Growth(params) => params.validate(),
+            LayerConfig::PipeModel(params) => params.validate(),
+        }
+    }
+}
+
+/// A layer config and its toggle. Serde cannot deny unknown fields next to a flattened
+/// field, so misspelled fields are caught by the layer params, which deny them instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageConfig {
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub layer: LayerConfig,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// A pipeline as a list of layer configs, e.g.
+///
+/// ```json
+/// { "stages": [
+///     { "layer": "trunk", "split": 0.6 },
+///     { "layer": "branch", "enabled": false }
+/// ] }
+/// ```
+///
+/// Parameters that are left out use their defaults; unknown fields are rejected.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub stages: Vec<StageConfig>,
+}
+
+impl PipelineConfig {
+    pub fn build(&self) -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        for stage in &self.stages {
+            pipeline.push(stage.layer.build(), stage.enabled);
+        }
+        pipeline
+    }
+
+    /// Checks the params of every stage, naming the first invalid value found, such as
+    /// `stages[1].base_size_reduction`. Disabled stages are checked too.
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, stage) in self.stages.iter().enumerate() {
+            nested(&format!("stages[{index}]"), stage.layer.validate())?;
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String, SerializationError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SerializationError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SerializationError> {
+        Ok(fs::write(path, self.to_json()?)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SerializationError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+impl From<TreeParams> for PipelineConfig {
+    fn from(params: TreeParams) -> Self {
+        let stage = |layer| StageConfig {
+            enabled: true,
+            layer,
+        };
+        let mut stages = vec![
+            stage(LayerConfig::Trunk(params.trunk)),
+            stage(LayerConfig::Branch(params.branch)),
+        ];
+        if let Some(pipe_model) = params.pipe_model {
+            stages.push(stage(LayerConfig::PipeModel(pipe_model)));
+        }
+        stages.push(stage(LayerConfig::Tropism(params.tropism)));
+        stages.push(stage(LayerConfig::Foliage(params.foliage)));
+        Self { stages }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// Appends a segment of `length` to the last node, or plants the root, and then waits
+    /// for `delay`.
+    #[derive(Debug)]
+    struct Append {
+        length: f64,
+        delay: Duration,
+    }
+
+    impl Append {
+        fn new(length: f64) -> Self {
+            Self {
+                length,
+                delay: Duration::ZERO,
+            }
+        }
+    }
+
+    impl Layer for Append {
+        fn name(&self) -> &str {
+            "append"
+        }
+
+        fn generate(&self, mut tree: Tree, _seed: u64) -> Tree {
+            let parent = tree.nodes.nodes.len().checked_sub(1);
+            tree.add_node(parent, self.length, 0.0, 1.0);
+            thread::sleep(self.delay);
+            tree
+        }
+    }
+
+    fn lengths(tree: &Tree) -> Vec<f64> {
+        tree.nodes.nodes.iter().map(|node| node.length).collect()
+    }
+
+    #[test]
+    fn stages_run_in_order_and_disabled_ones_are_skipped() {
+        let mut pipeline = Pipeline::new()
+            .with_layer(Append::new(1.0))
+            .with_layer(Append::new(2.0))
+            .with_stage(Append::new(3.0), false)
+            .with_layer(Append::new(4.0));
+        assert_eq!(lengths(&pipeline.run(Tree::new(), 0)), [1.0, 2.0, 4.0]);
+
+        assert!(pipeline.set_enabled(2, true));
+        assert!(pipeline.set_enabled(0, false));
+        assert_eq!(lengths(&pipeline.run(Tree::new(), 0)), [2.0, 3.0, 4.0]);
+        assert!(!pipeline.set_enabled(4, true));
+        assert_eq!(pipeline.len(), 4);
+    }
+
+    #[test]
+    fn reports_count_and_time_every_stage() {
+        let delay = Duration::from_millis(20);
+        let pipeline = Pipeline::new()
+            .with_layer(Append::new(1.0))
+            .with_stage(Append { length: 2.0, delay }, false)
+            .with_layer(Append { length: 3.0, delay });
+        let (tree, report) = pipeline.run_with_report(Tree::new(), 0);
+        assert_eq!(tree.nodes.nodes.len(), 2);
+
+        let counts: Vec<_> = report
+            .layers
+            .iter()
+            .map(|layer| (layer.enabled, layer.nodes_before, layer.nodes_after))
+            .collect();
+        assert_eq!(counts, [(true, 0, 1), (false, 1, 1), (true, 1, 2)]);
+        assert_eq!(report.layers[1].nodes_added(), 0);
+        assert!(report.layers[1].duration < delay);
+        assert!(report.layers[2].duration >= delay);
+        let sum: Duration = report.layers.iter().map(|layer| layer.duration).sum();
+        assert_eq!(report.total_duration(), sum);
+        assert!(report.to_string().contains("append       disabled"));
+    }
+
+    #[test]
+    fn configs_round_trip_through_json() {
+        let trunk = TrunkParams {
+            split: 0.6,
+            ..TrunkParams::default()
+        };
+        let config = PipelineConfig {
+            stages: vec![
+                StageConfig {
+                    enabled: true,
+                    layer: LayerConfig::Trunk(trunk),
+                },
+                StageConfig {
+                    enabled: false,
+                    layer: LayerConfig::Branch(BranchParams::default()),
+                },
+                StageConfig {
+                    enabled: true,
+                    layer: LayerConfig::Foliage(FoliageParams::default()),
+                },
+            ],
+        };
+        let json = config.to_json().unwrap();
+        let loaded = PipelineConfig::from_json(&json).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), json);
+
+        let stages = |config: &PipelineConfig| {
+            let pipeline = config.build();
+            let stages = pipeline.stages.iter();
+            stages.map(|stage| (stage.layer.name().to_owned(), stage.enabled)).collect::<Vec<_>>()
+        };
+        assert_eq!(stages(&loaded), stages(&config));
+        let shape = |config: &PipelineConfig| {
+            let tree = config.build().run(Tree::new(), 3);
+            format!("{:?} {:?}", tree.nodes.nodes, tree.leaves)
+        };
+        assert_eq!(shape(&loaded), shape(&config));
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let accepted = r#"{"stages": [{"layer": "trunk", "enabled": false, "split": 0.6}]}"#;
+        let config = PipelineConfig::from_json(accepted).unwrap();
+        assert!(!config.stages[0].enabled);
+        for json in [
+            r#"{"stages": [{"layer": "trunk", "splitt": 0.6}]}"#,
+            r#"{"stages": [{"layer": "growth", "trunk": {"collision": {"mod": "off"}}}]}"#,
+            r#"{"stages": [], "seed": 4}"#,
+        ] {
+            let err = PipelineConfig::from_json(json).unwrap_err().to_string();
+            assert!(err.contains("unknown field"), "{json}: {err}");
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+    fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::skeleton::serialization::SerializationError;
+
+use super::tree_params::TreeParams;
+
+/// The presets shipped with the crate, as `(name, json)` pairs.
+const BUILTIN_PRESETS: [(&str, &str); 4] = [
+    ("oak", include_str!("../../presets/oak.json")),
+    ("pine", include_str!("../../presets/pine.json")),
+    ("willow", include_str!("../../presets/willow.json")),
+    ("shrub", include_str!("../../presets/shrub.json")),
+];
+
+/// A named, complete parameter set, such as one of the built-in species profiles.
+///
+/// Parameters a preset file leaves out take their default values, like in any params file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub params: TreeParams,
+}
+
+/// A parameter whose value differs between two presets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamDiff {
+    /// Dotted path of the parameter, e.g. `trunk.spread`.
+    pub field: String,
+    pub left: Value,
+    pub right: Value,
+}
+
+impl Display for ParamDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.left, self.right)
+    }
+}
+
+impl Preset {
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        params: TreeParams,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            params,
+        }
+    }
+
+    /// Names of the built-in presets, in display order.
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        BUILTIN_PRESETS.iter().map(|(name, _)| *name)
+    }
+
+    /// Returns the built-in preset called `name`, if there is one.
+    pub fn builtin(name: &str) -> Option<Self> {
+        BUILTIN_PRESETS
+            .iter()
+            .find(|(builtin, _)| builtin.eq_ignore_ascii_case(name))
+            .map(|(_, json)| Self::from_json(json).expect("built-in presets are valid"))
+    }
+
+    /// All built-in presets, in display order.
+    pub fn builtins() -> Vec<Self> {
+        Self::builtin_names().filter_map(Self::builtin).collect()
+    }
+
+    pub fn to_json(&self) -> Result<String, SerializationError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SerializationError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SerializationError> {
+        Ok(fs::write(path, self.to_json()?)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SerializationError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Loads every `.json` file in `dir` as a preset, sorted by name.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>, SerializationError> {
+        let mut presets = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                presets.push(Self::load(path)?);
+            }
+        }
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(presets)
+    }
+
+    /// Lists the parameters whose values differ from `other`, sorted by field path. Fields
+    /// only one side sets are compared against `null`, so the diff is the same both ways
+    /// round, with sides swapped.
+    pub fn diff(&self, other: &Preset) -> Vec<ParamDiff> {
+        let left = serde_json::to_value(self.params).expect("params serialize to JSON");
+        let right = serde_json::to_value(other.params).expect("params serialize to JSON");
+        let mut diffs = Vec::new();
+        diff_values(String::new(), &left, &right, &mut diffs);
+        diffs
+    }
+}
+
+fn diff_values(field: String, left: &Value, right: &Value, diffs: &mut Vec<ParamDiff>) {
+    match (left, right) {
+        (Value::Object(left), Value::Object(right)) => {
+            let keys: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+            for key in keys {
+                let path = if field.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{field}.{key}")
+                };
+                let left_value = left.get(key).unwrap_or(&Value::Null);
+                let right_value = right.get(key).unwrap_or(&Value::Null);
+                diff_values(path, left_value, right_value, diffs);
+            }
+        }
+        _ if left != right => diffs.push(ParamDiff {
+            field,
+            left: left.clone(),
+            right: right.clone(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn builtin_presets_load_and_validate() {
+        for name in Preset::builtin_names() {
+            let preset = Preset::builtin(name).unwrap();
+            assert_eq!(preset.name, name);
+            preset.params.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn diffs_are_symmetric() {
+        for left in Preset::builtins() {
+            for right in Preset::builtins() {
+                let swapped: Vec<_> = right
+                    .diff(&left)
+                    .into_iter()
+                    .map(|diff| ParamDiff {
+                        field: diff.field,
+                        left: diff.right,
+                        right: diff.left,
+                    })
+                    .collect();
+                assert_eq!(left.diff(&right), swapped, "{} and {}", left.name, right.name);
+            }
+        }
+    }
+
+    #[test]
+    fn fields_set_on_one_side_only_are_reported() {
+        let left = json!({"trunk": {"spread": 1.0}});
+        let right = json!({"trunk": {"spread": 1.0, "curve": {"keys": []}}, "extra": 2});
+        for (left, right) in [(&left, &right), (&right, &left)] {
+            let mut diffs = Vec::new();
+            diff_values(String::new(), left, right, &mut diffs);
+            let fields: Vec<_> = diffs.iter().map(|diff| diff.field.as_str()).collect();
+            assert_eq!(fields, ["extra", "trunk.curve"]);
+        }
+    }
+}
@@ -0,0 +1,418 @@
+use nalgebra::Vector2;
+use palette::Srgb;
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    skeleton::{node::Point, tree::Tree},
+    utils::{
+        quadtree::{BoundingBox, Quadtree},
+        rng::{derive_seed, rng_from_seed},
+    },
+};
+
+use super::{
+    layer::Layer,
+    pipe_model_layer::{assign_thickness, PipeModelParams},
+    validation::{finite, nested, non_negative, positive},
+};
+
+const SPACE_COLONIZATION_SEED_KEY: u64 = 0x5350_4143;
+
+/// Branches whose thickness is derived from the tips they carry follow `t^2 = sum(t_i^2)`.
+const PIPE_EXPONENT: f64 = 2.0;
+
+/// Attempts per attractor before sampling gives up on an envelope it can't hit.
+const MAX_SAMPLING_ATTEMPTS: usize = 100;
+
+/// Upper bound on [`SpaceColonizationParams::attractor_count`]. Every attractor is looked up
+/// in every iteration, so crowns with more take too long to grow.
+pub const MAX_ATTRACTORS: usize = 100_000;
+
+/// Capacity of the quadtree nodes used for neighbor queries.
+const QUADTREE_CAPACITY: usize = 4;
+
+/// The shape of the crown that attractors are scattered in.
+///
+/// Shapes are given in crown coordinates: `x` grows to the right of the root and `y` grows
+/// upward from it, so they don't depend on where the tree is placed in world space.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum CrownEnvelope {
+    /// An ellipse whose bottom sits `crown_base` above the root.
+    Ellipse { width: f64, height: f64 },
+    /// A cone standing on its base `crown_base` above the root, pointing up.
+    Cone { width: f64, height: f64 },
+    /// An arbitrary polygon, in crown coordinates. `crown_base` is ignored.
+    Polygon { points: Vec<[f64; 2]> },
+}
+
+impl CrownEnvelope {
+    /// Returns the crown-coordinate box covering the envelope as `(min, max)` corners.
+    fn extent(&self, crown_base: f64) -> ([f64; 2], [f64; 2]) {
+        match self {
+            CrownEnvelope::Ellipse { width, height } | CrownEnvelope::Cone { width, height } => {
+                ([-width / 2.0, crown_base], [width / 2.0, crown_base + height])
+            }
+            CrownEnvelope::Polygon { points } => points.iter().fold(
+                ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]),
+                |(min, max), &[x, y]| {
+                    ([min[0].min(x), min[1].min(y)], [max[0].max(x), max[1].max(y)])
+                },
+            ),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            CrownEnvelope::Ellipse { width, height } | CrownEnvelope::Cone { width, height } => {
+                positive("width", *width)?;
+                positive("height", *height)
+            }
+            CrownEnvelope::Polygon { points } => {
+                if points.len() < 3 {
+                    return Err(format!(
+                        "points must hold at least 3 corners, got {}",
+                        points.len()
+                    ));
+                }
+                for (index, &[x, y]) in points.iter().enumerate() {
+                    finite(&format!("points[{index}][0]"), x)?;
+                    finite(&format!("points[{index}][1]"), y)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn contains(&self, crown_base: f64, x: f64, y: f64) -> bool {
+        match self {
+            CrownEnvelope::Ellipse { width, height } => {
+                let dx = x / (width / 2.0);
+                let dy = (y - crown_base - height / 2.0) / (height / 2.0);
+                dx * dx + dy * dy <= 1.0
+            }
+            CrownEnvelope::Cone { width, height } => {
+                let rise = (y - crown_base) / height;
+                (0.0..=1.0).contains(&rise) && x.abs() <= width / 2.0 * (1.0 - rise)
+            }
+            CrownEnvelope::Polygon { points } => {
+                // Even-odd rule: count the edges crossed by a ray running right from (x, y).
+                let mut inside = false;
+                for (i, &[x1, y1]) in points.iter().enumerate() {
+                    let [x2, y2] = points[(i + 1) % points.len()];
+                    if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// Grows branches toward a cloud of attractor points that fills a crown envelope.
+///
+/// On an empty tree a trunk first grows straight up from the root until it comes within
+/// `influence_distance` of the crown. On a tree that already has nodes (for example from a
+/// [`TrunkLayer`](super::trunk_layer::TrunkLayer)) every existing node end can sprout new
+/// branches.
+#[derive(Debug, Default, Clone)]
+pub struct SpaceColonizationLayer {
+    pub params: SpaceColonizationParams,
+}
+
+impl SpaceColonizationLayer {
+    pub fn new(params: SpaceColonizationParams) -> Self {
+        Self { params }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpaceColonizationParams {
+    pub envelope: CrownEnvelope,
+    /// Height of the bottom of the crown above the root.
+    pub crown_base: f64,
+    pub attractor_count: usize,
+    /// Attractors farther than this from every node end do not pull on the tree.
+    pub influence_distance: f64,
+    /// Attractors closer than this to a node end are removed.
+    pub kill_distance: f64,
+    pub segment_length: f64,
+    pub max_iterations: usize,
+    /// Thickness of a tip. Every other node is as thick as the tips it carries require.
+    pub tip_thickness: f64,
+    pub color: [u8; 3],
+}
+
+impl SpaceColonizationParams {
+    pub fn new(envelope: CrownEnvelope, attractor_count: usize) -> Self {
+        Self {
+            envelope,
+            crown_base: 80.0,
+            attractor_count,
+            influence_distance: 40.0,
+            kill_distance: 8.0,
+            segment_length: 5.0,
+            max_iterations: 300,
+            tip_thickness: 1.0,
+            color: [90, 60, 40],
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        nested("envelope", self.envelope.validate())?;
+        finite("crown_base", self.crown_base)?;
+        if self.attractor_count > MAX_ATTRACTORS {
+            return Err(format!(
+                "attractor_count must be at most {MAX_ATTRACTORS}, got {}",
+                self.attractor_count
+            ));
+        }
+        positive("influence_distance", self.influence_distance)?;
+        non_negative("kill_distance", self.kill_distance)?;
+        positive("segment_length", self.segment_length)?;
+        non_negative("tip_thickness", self.tip_thickness)
+    }
+}
+
+impl Default for SpaceColonizationParams {
+    fn default() -> Self {
+        Self::new(
+            CrownEnvelope::Ellipse {
+                width: 240.0,
+                height: 200.0,
+            },
+            2000,
+        )
+    }
+}
+
+impl Layer for SpaceColonizationLayer {
+    fn name(&self) -> &str {
+        "space_colonization"
+    }
+
+    fn generate(&self, mut tree: Tree, seed: u64) -> Tree {
+        let params = &self.params;
+        let root = tree.nodes.root_position();
+        let mut attractors = self.scatter_attractors(root, seed);
+        let first_new = tree.nodes.nodes.len();
+
+        let mut growing_trunk = tree.nodes.nodes.is_empty();
+        if growing_trunk {
+            let root = tree.add_node(None, params.segment_length, 0.0, params.tip_thickness);
+            tree.nodes.nodes[root].set_color(Srgb::from(params.color));
+        }
+
+        let mut ends = Quadtree::new(
+            BoundingBox {
+                x: root.x - params.influence_distance,
+                y: root.y - params.influence_distance,
+                width: 2.0 * params.influence_distance,
+                height: 2.0 * params.influence_distance,
+            },
+            QUADTREE_CAPACITY,
+        );
+        for (index, node) in tree.nodes.nodes.iter().enumerate() {
+            let end = node.next_point();
+            ends.insert(end, index);
+        }
+
+        for _ in 0..params.max_iterations {
+            if attractors.is_empty() {
+                break;
+            }
+
+            // Every attractor pulls on the node end closest to it within the influence distance.
+            let pulls: Vec<Option<usize>> = attractors
+                .par_iter()
+                .map(|attractor| nearest_end(&ends, attractor, params.influence_distance))
+                .collect();
+
+            let mut directions = vec![Vector2::zeros(); tree.nodes.nodes.len()];
+            let mut pulled = vec![false; tree.nodes.nodes.len()];
+            for (attractor, pull) in attractors.iter().zip(&pulls) {
+                if let Some(index) = *pull {
+                    let offset = attractor - tree.nodes.nodes[index].next_point();
+                    if offset.norm() > 0.0 {
+                        directions[index] += offset.normalize();
+                        pulled[index] = true;
+                    }
+                }
+            }
+
+            let mut grew = false;
+            if pulled.iter().any(|&pulled| pulled) {
+                growing_trunk = false;
+                for index in 0..pulled.len() {
+                    if pulled[index] && directions[index].norm() > f64::EPSILON {
+                        grew |= self.grow(&mut tree, &mut ends, index, directions[index]);
+                    }
+                }
+            } else if growing_trunk {
+                // Nothing is within reach yet: keep growing the trunk straight up.
+                let tip = tree.nodes.nodes.len() - 1;
+                grew = self.grow(&mut tree, &mut ends, tip, Vector2::new(0.0, -1.0));
+            }
+            if !grew {
+                break;
+            }
+
+            attractors.retain(|attractor| {
+                nearest_end(&ends, attractor, params.kill_distance).is_none()
+            });
+        }
+
+        let pipe_model = PipeModelParams::new(PIPE_EXPONENT, params.tip_thickness);
+        assign_thickness(&mut tree.nodes, first_new, &pipe_model);
+        tree.nodes.rebuild_index();
+        tree
+    }
+}
+
+impl SpaceColonizationLayer {
+    /// Scatters `attractor_count` points uniformly over the envelope, in world space.
+    fn scatter_attractors(&self, root: Point, seed: u64) -> Vec<Point> {
+        let params = &self.params;
+        let mut rng = rng_from_seed(derive_seed(seed, SPACE_COLONIZATION_SEED_KEY));
+        let (min, max) = params.envelope.extent(params.crown_base);
+        if !(min[0] < max[0] && min[1] < max[1]) {
+            return Vec::new();
+        }
+
+        let mut attractors = Vec::with_capacity(params.attractor_count.min(MAX_ATTRACTORS));
+        for _ in 0..params.attractor_count.saturating_mul(MAX_SAMPLING_ATTEMPTS) {
+            if attractors.len() == params.attractor_count {
+                break;
+            }
+            let x = rng.gen_range(min[0]..max[0]);
+            let y = rng.gen_range(min[1]..max[1]);
+            if params.envelope.contains(params.crown_base, x, y) {
+                attractors.push(Point::new(root.x + x, root.y - y));
+            }
+        }
+        attractors
+    }
+
+    /// Adds a child of `parent` growing along `direction`. Returns `false` when the parent
+    /// already has a child pointing that way, which happens when attractors balance out.
+    fn grow(
+        &self,
+        tree: &mut Tree,
+        ends: &mut Quadtree,
+        parent: usize,
+        direction: Vector2<f64>,
+    ) -> bool {
+        let angle = direction.x.atan2(-direction.y);
+        let duplicate = tree.nodes.nodes[parent]
+            .children_indices
+            .iter()
+            .any(|&child| (tree.nodes.nodes[child].angle - angle).abs() < 1e-3);
+        if duplicate {
+            return false;
+        }
+
+        let index = tree.add_node(
+            Some(parent),
+            self.params.segment_length,
+            angle,
+            self.params.tip_thickness,
+        );
+        tree.nodes.nodes[index].set_color(Srgb::from(self.params.color));
+        let end = tree.nodes.nodes[index].next_point();
+        ends.insert(end, index);
+        true
+    }
+}
+
+/// Returns the node whose end is closest to `point`, if one lies within `distance`.
+fn nearest_end(ends: &Quadtree, point: &Point, distance: f64) -> Option<usize> {
+    ends.nearest_within(point, distance).map(|(_, index)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_crown() -> SpaceColonizationParams {
+        SpaceColonizationParams {
+            crown_base: 40.0,
+            ..SpaceColonizationParams::new(
+                CrownEnvelope::Ellipse {
+                    width: 80.0,
+                    height: 60.0,
+                },
+                300,
+            )
+        }
+    }
+
+    #[test]
+    fn branches_grow_into_the_crown() {
+        let params = small_crown();
+        let tree = SpaceColonizationLayer::new(params.clone()).generate(Tree::new(), 9);
+        let nodes = &tree.nodes.nodes;
+        let root = tree.nodes.root_position();
+        assert!(nodes.len() > 20, "only {} nodes", nodes.len());
+
+        // The trunk grows straight up until the crown is within reach.
+        assert_eq!(nodes[0].angle, 0.0);
+        let tips: Vec<_> = nodes.iter().filter(|node| node.children_indices.is_empty()).collect();
+        assert!(tips.len() > 1);
+        for tip in tips {
+            let end = tip.next_point();
+            let (x, y) = (end.x - root.x, root.y - end.y);
+            assert!(y > params.crown_base - params.influence_distance, "tip at {x}, {y}");
+            assert!(x.abs() < 40.0 + params.influence_distance, "tip at {x}, {y}");
+        }
+    }
+
+    #[test]
+    fn thickness_follows_the_pipe_model() {
+        let params = small_crown();
+        let tree = SpaceColonizationLayer::new(params.clone()).generate(Tree::new(), 9);
+        let nodes = &tree.nodes.nodes;
+        for node in nodes {
+            if node.children_indices.is_empty() {
+                assert_eq!(node.thickness, params.tip_thickness);
+            } else {
+                let carried: f64 = node
+                    .children_indices
+                    .iter()
+                    .map(|&child| nodes[child].thickness.powi(2))
+                    .sum();
+                assert!((node.thickness.powi(2) - carried).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn envelopes_are_validated() {
+        let mut params = small_crown();
+        params.envelope = CrownEnvelope::Cone {
+            width: f64::NAN,
+            height: 10.0,
+        };
+        let err = params.validate().unwrap_err();
+        assert!(err.starts_with("envelope.width"), "{err}");
+
+        params.envelope = CrownEnvelope::Polygon {
+            points: vec![[0.0, 0.0], [1.0, 1.0]],
+        };
+        let err = params.validate().unwrap_err();
+        assert!(err.starts_with("envelope.points"), "{err}");
+    }
+
+    #[test]
+    fn attractor_counts_are_bounded() {
+        let mut params = small_crown();
+        params.attractor_count = MAX_ATTRACTORS;
+        assert_eq!(params.validate(), Ok(()));
+        params.attractor_count = usize::MAX;
+        let err = params.validate().unwrap_err();
+        assert!(err.starts_with("attractor_count must be at most"), "{err}");
+    }
+}
@@ -0,0 +1,122 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::skeleton::{serialization::SerializationError, tree::Tree};
+
+use super::{
+    branch_layer::{BranchLayer, BranchParams},
+    foliage_layer::{FoliageLayer, FoliageParams},
+    pipe_model_layer::{PipeModelLayer, PipeModelParams},
+    pipeline::Pipeline,
+    tropism_layer::{TropismLayer, TropismParams},
+    trunk_layer::{TrunkLayer, TrunkParams},
+    validation::nested,
+};
+
+/// The full parameter set of the default trunk, branch, tropism and foliage generator, with
+/// an optional pipe model pass.
+///
+/// Missing fields fall back to their defaults when deserializing, so parameter files only
+/// need to list the values they change.
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TreeParams {
+    pub trunk: TrunkParams,
+    pub branch: BranchParams,
+    pub tropism: TropismParams,
+    pub foliage: FoliageParams,
+    /// Recomputes thicknesses with the pipe model after branching when set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pipe_model: Option<PipeModelParams>,
+}
+
+impl TreeParams {
+    pub fn new(
+        trunk: TrunkParams,
+        branch: BranchParams,
+        tropism: TropismParams,
+        foliage: FoliageParams,
+    ) -> Self {
+        Self {
+            trunk,
+            branch,
+            tropism,
+            foliage,
+            pipe_model: None,
+        }
+    }
+
+    /// Checks every layer's params, naming the first invalid value found, such as
+    /// `branch.base_size_reduction`.
+    pub fn validate(&self) -> Result<(), String> {
+        nested("trunk", self.trunk.validate())?;
+        nested("branch", self.branch.validate())?;
+        if let Some(pipe_model) = &self.pipe_model {
+            nested("pipe_model", pipe_model.validate())?;
+        }
+        nested("tropism", self.tropism.validate())?;
+        nested("foliage", self.foliage.validate())
+    }
+
+    /// The trunk, branch, pipe model (if set), tropism and foliage layers, in that order.
+    /// Thicknesses are settled before tropism so thick branches resist bending, and leaves
+    /// are added last so they hang from the bent branches.
+    pub fn pipeline(&self) -> Pipeline {
+        let mut pipeline = Pipeline::new()
+            .with_layer(TrunkLayer::new(self.trunk))
+            .with_layer(BranchLayer::new(self.branch));
+        if let Some(pipe_model) = self.pipe_model {
+            pipeline = pipeline.with_layer(PipeModelLayer::new(pipe_model));
+        }
+        pipeline
+            .with_layer(TropismLayer::new(self.tropism))
+            .with_layer(FoliageLayer::new(self.foliage))
+    }
+
+    /// Grows a tree into `tree` with the trunk, branch, tropism and foliage layers.
+    pub fn generate(&self, tree: Tree, seed: u64) -> Tree {
+        self.pipeline().run(tree, seed)
+    }
+
+    pub fn to_json(&self) -> Result<String, SerializationError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SerializationError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Returns a copy of these params with every field present in `json` replaced, so a
+    /// partial parameter file can be layered on top of a preset.
+    pub fn with_json_overrides(&self, json: &str) -> Result<Self, SerializationError> {
+        let mut params = serde_json::to_value(self)?;
+        merge_json(&mut params, serde_json::from_str(json)?);
+        Ok(serde_json::from_value(params)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SerializationError> {
+        Ok(fs::write(path, self.to_json()?)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SerializationError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+fn merge_json(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
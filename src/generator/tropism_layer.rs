@@ -0,0 +1,183 @@
+use druid::{Data, Lens};
+use serde::{Deserialize, Serialize};
+
+use crate::skeleton::tree::Tree;
+
+use super::{
+    layer::Layer,
+    validation::{finite, non_negative},
+};
+
+/// Bends existing branches toward a direction, like gravity, light or a prevailing wind.
+///
+/// Every node turns toward `direction_deg` in proportion to the sine of the angle between
+/// them, and the turn is passed on to its whole subtree. Thin nodes far from the root bend
+/// the most; the thickest nodes don't bend at all. Node positions are recomputed afterwards
+/// and leaves follow the nodes they hang on.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TropismLayer {
+    pub params: TropismParams,
+}
+
+impl TropismLayer {
+    pub fn new(params: TropismParams) -> Self {
+        Self { params }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Data, Lens, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TropismParams {
+    /// Direction branches bend toward, as a node angle in degrees: 0 is up, 180 is down.
+    pub direction_deg: f64,
+    /// Largest turn of a single node in degrees. Negative values bend away from the
+    /// direction.
+    pub strength: f64,
+    /// How strongly thickness resists bending. At 0 every node bends equally.
+    pub stiffness: f64,
+    /// How quickly bending grows with the distance from the root. At 0 it doesn't depend on
+    /// the distance.
+    pub distance_exponent: f64,
+}
+
+impl TropismParams {
+    pub fn new(direction_deg: f64, strength: f64) -> Self {
+        Self {
+            direction_deg,
+            strength,
+            stiffness: 1.0,
+            distance_exponent: 1.0,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        finite("direction_deg", self.direction_deg)?;
+        finite("strength", self.strength)?;
+        non_negative("stiffness", self.stiffness)?;
+        non_negative("distance_exponent", self.distance_exponent)
+    }
+}
+
+impl Default for TropismParams {
+    /// Gravity with no strength, so the layer leaves trees unchanged.
+    fn default() -> Self {
+        Self::new(180.0, 0.0)
+    }
+}
+
+impl Layer for TropismLayer {
+    fn name(&self) -> &str {
+        "tropism"
+    }
+
+    fn generate(&self, mut tree: Tree, _seed: u64) -> Tree {
+        let params = &self.params;
+        let nodes = &mut tree.nodes.nodes;
+        if params.strength == 0.0 || nodes.is_empty() {
+            return tree;
+        }
+
+        let mut distances = vec![0.0; nodes.len()];
+        for index in 0..nodes.len() {
+            if let Some(parent_index) = nodes[index].parent_index {
+                distances[index] = distances[parent_index] + nodes[parent_index].length;
+            }
+        }
+        let max_distance = distances.iter().copied().fold(0.0, f64::max);
+        let max_thickness = nodes.iter().map(|node| node.thickness).fold(0.0, f64::max);
+
+        // Angles are absolute, so turning a node turns everything above it by the same
+        // amount. Parents come before children, so one pass in index order is enough.
+        let target = params.direction_deg.to_radians();
+        let mut rotations = vec![0.0; nodes.len()];
+        for index in 0..nodes.len() {
+            let inherited = nodes[index]
+                .parent_index
+                .map_or(0.0, |parent_index| rotations[parent_index]);
+            let angle = nodes[index].angle + inherited;
+
+            let reach = if max_distance > 0.0 {
+                (distances[index] / max_distance).powf(params.distance_exponent)
+            } else {
+                1.0
+            };
+            let flexibility = if max_thickness > 0.0 {
+                (1.0 - nodes[index].thickness / max_thickness)
+                    .max(0.0)
+                    .powf(params.stiffness)
+            } else {
+                1.0
+            };
+            let bend = params.strength.to_radians() * reach * flexibility * (target - angle).sin();
+
+            nodes[index].angle = angle + bend;
+            rotations[index] = inherited + bend;
+        }
+
+        // Leaves keep their place along their node's segment and turn with it.
+        let old_points: Vec<_> = nodes.iter().map(|node| (node.point, node.length)).collect();
+        tree.nodes.update_points();
+        for leaf in &mut tree.leaves {
+            let node = &tree.nodes.nodes[leaf.node_index];
+            let (old_point, length) = old_points[leaf.node_index];
+            let along = if length > 0.0 {
+                (leaf.position - old_point).norm() / length
+            } else {
+                0.0
+            };
+            leaf.position = node.point + (node.next_point() - node.point) * along;
+            leaf.angle += rotations[leaf.node_index];
+        }
+
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A straight chain growing up, thinning from 8 at the root to 1 at the tip.
+    fn chain() -> Tree {
+        let mut tree = Tree::new();
+        let mut parent = None;
+        for thickness in [8.0, 6.0, 4.0, 2.0, 1.0] {
+            parent = Some(tree.add_node(parent, 10.0, 0.0, thickness));
+        }
+        tree
+    }
+
+    #[test]
+    fn thin_tips_bend_the_most() {
+        let before = chain();
+        let after = TropismLayer::new(TropismParams::new(90.0, 30.0)).generate(chain(), 0);
+
+        // Each node's own turn, without what it inherits from its parent.
+        let turns: Vec<f64> = (0..before.nodes.nodes.len())
+            .map(|index| {
+                let turn = |tree: &Tree, index: usize| tree.nodes.nodes[index].angle;
+                let own = turn(&after, index) - turn(&before, index);
+                let inherited = before.nodes.nodes[index]
+                    .parent_index
+                    .map_or(0.0, |parent| turn(&after, parent) - turn(&before, parent));
+                own - inherited
+            })
+            .collect();
+
+        assert_eq!(turns[0], 0.0);
+        for pair in turns.windows(2) {
+            assert!(pair[0] < pair[1], "{turns:?}");
+        }
+        let tip = after.nodes.nodes.last().unwrap();
+        assert!(tip.next_point().x > after.nodes.root_position().x);
+    }
+
+    #[test]
+    fn negative_stiffness_is_rejected() {
+        let params = TropismParams {
+            stiffness: -1.0,
+            ..TropismParams::new(90.0, 30.0)
+        };
+        assert!(params.validate().unwrap_err().starts_with("stiffness"));
+    }
+}
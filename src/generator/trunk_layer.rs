@@ -1,19 +1,39 @@
 use druid::{Data, Lens};
 use palette::Srgb;
 use rayon::prelude::*;
-use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use std::{f64::consts::TAU, ops::Add};
 
 use rand::Rng;
-use rand_distr::{Distribution, Normal};
 
-use crate::skeleton::tree::Tree;
+use crate::{
+    sampler::{DistributionKind, Moments, ParameterizedSampler, Sampler},
+    skeleton::tree::Tree,
+    utils::rng::{derive_seed, rng_from_seed, stream_rng},
+};
 
-use super::layer::Layer;
+use super::{
+    collision::CollisionParams,
+    layer::Layer,
+    param_curve::{CurvePosition, ParamCurve},
+    validation::{distribution, finite, nested, non_negative, non_negative_curve, positive},
+};
 
-#[derive(Debug)]
-pub struct TrunkLayer;
+pub(super) const TRUNK_SEED_KEY: u64 = 0x5452_554e;
 
-#[derive(Debug, Copy, Clone, Data, Lens)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TrunkLayer {
+    pub params: TrunkParams,
+}
+
+impl TrunkLayer {
+    pub fn new(params: TrunkParams) -> Self {
+        Self { params }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Data, Lens, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct TrunkParams {
     pub spread: f64,
     pub split: f64,
@@ -29,6 +49,29 @@ pub struct TrunkParams {
     pub angle_spread_positive: f64,
     pub angle_spread_negative: f64,
     pub max_children: f64,
+    /// Distribution of the number of growth iterations.
+    pub height_distribution: DistributionKind,
+    /// Distribution of the angles of the two branches of a split.
+    pub split_angle_distribution: DistributionKind,
+    /// Distribution of the angle of a single new branch.
+    pub branch_angle_distribution: DistributionKind,
+    /// How new segments avoid the ones already grown.
+    pub collision: CollisionParams,
+    /// Scales `default_branch_length` along the tree.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length_curve: Option<ParamCurve>,
+    /// Scales `spread` along the tree.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spread_curve: Option<ParamCurve>,
+    /// Replaces the falloff of `split_rate` with a curve that scales `split`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_curve: Option<ParamCurve>,
+    /// Replaces the falloff of `branch_rate` with a curve that scales `branch`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch_curve: Option<ParamCurve>,
+    /// Replaces the falloff of `branch_size` with a curve that scales the root thickness.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thickness_curve: Option<ParamCurve>,
 }
 
 impl TrunkParams {
@@ -48,118 +91,257 @@ impl TrunkParams {
             angle_spread_positive: 10.0,
             angle_spread_negative: -10.0,
             max_children: 5.0,
+            height_distribution: DistributionKind::Normal,
+            split_angle_distribution: DistributionKind::Normal,
+            branch_angle_distribution: DistributionKind::Normal,
+            collision: CollisionParams::default(),
+            length_curve: None,
+            spread_curve: None,
+            split_curve: None,
+            branch_curve: None,
+            thickness_curve: None,
         }
     }
-}
 
-impl Layer<TrunkParams> for TrunkLayer {
-    fn generate(mut tree: Tree, params: &TrunkParams) -> Tree {
-        let mut rng = rand::thread_rng();
-        let normal = Normal::new(params.default_height_mean, 2.0 * params.variability).unwrap();
+    /// Checks that every value is one a trunk can grow with.
+    pub fn validate(&self) -> Result<(), String> {
+        finite("spread", self.spread)?;
+        finite("split", self.split)?;
+        finite("branch", self.branch)?;
+        non_negative("variability", self.variability)?;
+        non_negative("default_branch_length", self.default_branch_length)?;
+        non_negative("default_branch_size", self.default_branch_size)?;
+        positive("branch_size_falloff", self.branch_size_falloff)?;
+        non_negative("default_height_mean", self.default_height_mean)?;
+        finite("split_falloff_peak", self.split_falloff_peak)?;
+        finite("lean_bias", self.lean_bias)?;
+        non_negative("variability_modifier", self.variability_modifier)?;
+        finite("angle_spread_positive", self.angle_spread_positive)?;
+        finite("angle_spread_negative", self.angle_spread_negative)?;
+        non_negative("max_children", self.max_children)?;
+        distribution("height_distribution", &self.height_distribution)?;
+        distribution("split_angle_distribution", &self.split_angle_distribution)?;
+        distribution("branch_angle_distribution", &self.branch_angle_distribution)?;
+        non_negative_curve("length_curve", &self.length_curve)?;
+        non_negative_curve("thickness_curve", &self.thickness_curve)?;
+        nested("collision", self.collision.validate())
+    }
 
-        let height = normal.sample(&mut rng);
+    /// Probability of splitting a tip into two branches in growth iteration `n`.
+    pub fn split_rate(&self, n: usize) -> f64 {
+        self.split * (-(n as f64 - self.split_falloff_peak).powi(2)).exp()
+    }
 
-        let max_size = params.default_branch_size / params.branch_size_falloff;
-        let min_size =
-            params.default_branch_size / (params.default_height_mean + params.branch_size_falloff);
+    /// Probability of a tip growing a single new branch in growth iteration `n`.
+    pub fn branch_rate(&self, n: usize) -> f64 {
+        3.0 * self.branch * (-(n as f64 - 0.0).powi(2)).div_euclid(100.0).exp()
+    }
 
-        let root = tree.add_node(None, 2.0 * height, 0f64, max_size);
-        let mut tip_nodes: Vec<usize> = vec![root];
+    /// Thickness of the segments grown in growth iteration `n`.
+    pub fn branch_size(&self, n: usize) -> f64 {
+        self.default_branch_size / ((n as f64) + self.branch_size_falloff)
+    }
+
+    /// Thickness of the root and the thinnest segment a one-shot trunk grows.
+    fn size_range(&self) -> (f64, f64) {
+        let max_size = self.default_branch_size / self.branch_size_falloff;
+        let min_size =
+            self.default_branch_size / (self.default_height_mean + self.branch_size_falloff);
+        (min_size, max_size)
+    }
 
-        let split_rate =
-            |n: usize| params.split * (-(n as f64 - params.split_falloff_peak).powi(2)).exp();
-        let branch_rate =
-            |n: usize| 3.0 * params.branch * (-(n as f64 - 0.0).powi(2)).div_euclid(100.0).exp();
-        let branch_size =
-            |n: usize| params.default_branch_size / ((n as f64) + params.branch_size_falloff);
-
-        struct NodeToAdd {
-            parent_index: usize,
-            length: f64,
-            angle: f64,
-            size: f64,
-            color: Srgb<u8>,
+    /// What the curves of a trunk are measured against: the mean number of growth
+    /// iterations for depth, and for height and distance the length of a straight trunk of
+    /// that many segments on top of the root.
+    fn curve_extent(&self) -> CurvePosition {
+        let length = self.default_height_mean * (2.0 + self.default_branch_length);
+        CurvePosition {
+            depth: self.default_height_mean,
+            height: length,
+            distance: length,
         }
+    }
 
-        for i in 0..height as usize {
-            let is_split = rng.gen::<f64>() < split_rate(i);
-            let split_index = rng.gen_range(0..tip_nodes.len());
-
-            let new_tips: Vec<NodeToAdd> = tip_nodes
-                .par_iter()
-                .flat_map(|&j| {
-                    let mut local_rng = rand::thread_rng();
-                    let should_branch = local_rng.gen::<f64>() < branch_rate(i);
-
-                    let current_node = tree.nodes.nodes[j].clone();
-                    let size = branch_size(i);
-                    let color = Srgb::new(((225.0 * (size - min_size) / (max_size - min_size)) + 30.0) as u8, 0, 0);
-                    if current_node.children_indices.len() >= params.max_children as usize {
-                        return vec![];
-                    }
-                    if is_split && j == split_index {
-                        let normal = Normal::new(
-                            params.spread * params.angle_spread_positive.to_radians()
-                                + params.lean_bias.to_radians(),
-                            params.variability * params.variability_modifier,
-                        )
-                        .unwrap();
-                        let angle_a = normal
-                            .sample(&mut local_rng)
-                            .to_radians()
-                            .add(current_node.angle);
-                        let normal = Normal::new(
-                            params.spread * params.angle_spread_negative.to_radians()
-                                + params.lean_bias.to_radians(),
-                            params.variability * params.variability_modifier,
-                        )
-                        .unwrap();
-                        let angle_b = normal
-                            .sample(&mut local_rng)
-                            .to_radians()
-                            .add(current_node.angle);
-
-                        vec![
-                            NodeToAdd {
-                                parent_index: j,
-                                length: params.default_branch_length,
-                                angle: angle_a,
-                                size,
-                                color,
-                            },
-                            NodeToAdd {
-                                parent_index: j,
-                                length: params.default_branch_length,
-                                angle: angle_b,
-                                size,
-                                color,
-                            },
-                        ]
-                    } else if should_branch {
-                        let normal = Normal::new(
-                            params.lean_bias.to_radians(),
-                            params.spread * params.variability * params.variability_modifier,
-                        )
-                        .unwrap();
-                        let angle = normal.sample(&mut local_rng);
-                        vec![NodeToAdd {
+    fn curves(&self) -> [Option<ParamCurve>; 5] {
+        [
+            self.length_curve,
+            self.spread_curve,
+            self.split_curve,
+            self.branch_curve,
+            self.thickness_curve,
+        ]
+    }
+
+    /// Draws the number of growth iterations of a trunk.
+    pub(super) fn sample_height<R: Rng>(&self, rng: &mut R) -> f64 {
+        let moments = Moments::new(self.default_height_mean, 2.0 * self.variability);
+        self.height_distribution.sample_with_params(&moments, rng)
+    }
+
+    /// Decides which of `tip_nodes` sprout in growth iteration `i` and at what angles.
+    ///
+    /// `rng` drives the choices shared by the whole iteration and `seed` the per-tip
+    /// streams, so the result does not depend on how the tips are split across threads.
+    /// Parameters with a curve are scaled by it at the end of the tip they sprout from.
+    pub(super) fn sprout<R: Rng>(
+        &self,
+        tree: &Tree,
+        tip_nodes: &[usize],
+        i: usize,
+        rng: &mut R,
+        seed: u64,
+    ) -> Vec<Bud> {
+        let (min_size, max_size) = self.size_range();
+        let positions = self
+            .curves()
+            .iter()
+            .any(Option::is_some)
+            .then(|| CurvePosition::of_nodes(&tree.nodes, &self.curve_extent()));
+        // Scales `value` by `curve` at the end of node `j`, if both are set.
+        let scaled = |curve: Option<ParamCurve>, value: f64, j: usize| {
+            curve
+                .zip(positions.as_ref())
+                .map(|(curve, positions)| value * curve.value(&positions[j]))
+        };
+        // Split angles are drawn in degrees, single branch angles in radians.
+        let angle_samplers = |spread: f64| {
+            let split_angle = |spread_deg: f64| {
+                self.split_angle_distribution.sampler(Moments::angle(
+                    spread * spread_deg.to_radians() + self.lean_bias.to_radians(),
+                    self.variability * self.variability_modifier,
+                    360.0,
+                ))
+            };
+            let branch_angle = self.branch_angle_distribution.sampler(Moments::angle(
+                self.lean_bias.to_radians(),
+                spread * self.variability * self.variability_modifier,
+                TAU,
+            ));
+            (
+                split_angle(self.angle_spread_positive),
+                split_angle(self.angle_spread_negative),
+                branch_angle,
+            )
+        };
+        let samplers = angle_samplers(self.spread);
+        let split_draw = rng.gen::<f64>();
+        let split_node = tip_nodes[rng.gen_range(0..tip_nodes.len())];
+        let split_rate = scaled(self.split_curve, self.split, split_node)
+            .unwrap_or_else(|| self.split_rate(i));
+        let is_split = split_draw < split_rate;
+        let iteration_seed = derive_seed(seed, i as u64);
+
+        tip_nodes
+            .par_iter()
+            .flat_map(|&j| {
+                let mut local_rng = stream_rng(iteration_seed, j as u64);
+                let branch_rate = scaled(self.branch_curve, self.branch, j)
+                    .unwrap_or_else(|| self.branch_rate(i));
+                let should_branch = local_rng.gen::<f64>() < branch_rate;
+
+                let current_node = tree.nodes.nodes[j].clone();
+                let size = scaled(self.thickness_curve, max_size, j)
+                    .unwrap_or_else(|| self.branch_size(i));
+                let length = scaled(self.length_curve, self.default_branch_length, j)
+                    .unwrap_or(self.default_branch_length);
+                let (split_angle_a, split_angle_b, branch_angle) =
+                    match scaled(self.spread_curve, self.spread, j) {
+                        Some(spread) => angle_samplers(spread),
+                        None => samplers,
+                    };
+                let color = Srgb::new(((225.0 * (size - min_size) / (max_size - min_size)) + 30.0) as u8, 0, 0);
+                if current_node.children_indices.len() >= self.max_children as usize {
+                    return vec![];
+                }
+                if is_split && j == split_node {
+                    let angle_a = split_angle_a
+                        .sample(&mut local_rng)
+                        .to_radians()
+                        .add(current_node.angle);
+                    let angle_b = split_angle_b
+                        .sample(&mut local_rng)
+                        .to_radians()
+                        .add(current_node.angle);
+
+                    vec![
+                        Bud {
                             parent_index: j,
-                            length: params.default_branch_length,
-                            angle,
+                            length,
+                            angle: angle_a,
                             size,
                             color,
-                        }]
-                    } else {
-                        vec![]
-                    }
-                })
-                .collect();
+                        },
+                        Bud {
+                            parent_index: j,
+                            length,
+                            angle: angle_b,
+                            size,
+                            color,
+                        },
+                    ]
+                } else if should_branch {
+                    let angle = branch_angle.sample(&mut local_rng);
+                    vec![Bud {
+                        parent_index: j,
+                        length,
+                        angle,
+                        size,
+                        color,
+                    }]
+                } else {
+                    vec![]
+                }
+            })
+            .collect()
+    }
+}
 
+/// A segment about to sprout from an existing node.
+pub(super) struct Bud {
+    pub parent_index: usize,
+    pub length: f64,
+    pub angle: f64,
+    pub size: f64,
+    pub color: Srgb<u8>,
+}
+
+impl Default for TrunkParams {
+    fn default() -> Self {
+        Self::new(1.0, 1.0, 1.0, 0.5)
+    }
+}
+
+impl Layer for TrunkLayer {
+    fn name(&self) -> &str {
+        "trunk"
+    }
+
+    fn generate(&self, mut tree: Tree, seed: u64) -> Tree {
+        let params = &self.params;
+        let seed = derive_seed(seed, TRUNK_SEED_KEY);
+        let mut rng = rng_from_seed(seed);
+        let height = params.sample_height(&mut rng);
+        let (_, max_size) = params.size_range();
+
+        let root = tree.add_node(None, 2.0 * height, 0f64, max_size);
+        let mut tip_nodes: Vec<usize> = vec![root];
+
+        for i in 0..height as usize {
+            let new_tips = params.sprout(&tree, &tip_nodes, i, &mut rng, seed);
             for new_tip in new_tips {
+                let Some(angle) = params.collision.place(
+                    &tree,
+                    new_tip.parent_index,
+                    new_tip.length,
+                    new_tip.angle,
+                    new_tip.size,
+                ) else {
+                    continue;
+                };
                 let node_index = tree.add_node(
                     Some(new_tip.parent_index),
                     new_tip.length,
-                    new_tip.angle,
+                    angle,
                     new_tip.size,
                 );
                 tree.nodes.nodes[node_index].color = Some(new_tip.color);
@@ -170,3 +352,71 @@ impl Layer<TrunkParams> for TrunkLayer {
         tree
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generator::param_curve::CurveInput,
+        utils::curve::{Curve, Interpolation},
+    };
+
+    #[test]
+    fn split_curves_are_looked_up_at_the_chosen_tip() {
+        let mut params = TrunkParams::new(1.0, 1.0, 0.0, 0.5);
+        params.default_height_mean = 6.0;
+        // No splits up to half the height, certain splits above it.
+        let curve = Curve::new(Interpolation::Linear, &[[0.5, 0.0], [0.6, 1.0]]).unwrap();
+        params.split_curve = Some(ParamCurve::new(CurveInput::Depth, curve));
+
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 0.0, 5.0);
+        let mut deep = root;
+        for _ in 0..5 {
+            deep = tree.add_node(Some(deep), 10.0, 0.0, 5.0);
+        }
+
+        let mut splits = 0;
+        for seed in 0..64 {
+            let mut rng = rng_from_seed(seed);
+            let buds = params.sprout(&tree, &[root, deep], 0, &mut rng, seed);
+            assert!(matches!(buds.len(), 0 | 2), "{} buds", buds.len());
+            for bud in &buds {
+                assert_eq!(bud.parent_index, deep);
+            }
+            splits += buds.len() / 2;
+        }
+        // The split tip is picked at random, so roughly half the seeds pick the deep one.
+        assert!(splits > 16, "{splits} splits");
+    }
+
+    #[test]
+    fn curves_may_not_shrink_lengths_or_thicknesses_below_zero() {
+        // Flat at 0 up to 0.2, then up to 1: straight lines stay at 0, a spline dips below.
+        let keys = [[0.0, 0.0], [0.2, 0.0], [0.4, 1.0], [1.0, 1.0]];
+        let linear = ParamCurve::new(
+            CurveInput::Depth,
+            Curve::new(Interpolation::Linear, &keys).unwrap(),
+        );
+        let smooth = ParamCurve::new(
+            CurveInput::Depth,
+            Curve::new(Interpolation::CatmullRom, &keys).unwrap(),
+        );
+
+        let mut params = TrunkParams {
+            length_curve: Some(linear),
+            thickness_curve: Some(linear),
+            ..TrunkParams::default()
+        };
+        assert_eq!(params.validate(), Ok(()));
+
+        params.thickness_curve = Some(smooth);
+        let err = params.validate().unwrap_err();
+        assert!(err.starts_with("thickness_curve must not go below zero"), "{err}");
+        // Other curves only scale rates and angles, which may go negative.
+        params.thickness_curve = None;
+        params.spread_curve = Some(smooth);
+        params.split_curve = Some(smooth);
+        assert_eq!(params.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,49 @@
+use crate::sampler::DistributionKind;
+
+use super::param_curve::ParamCurve;
+
+pub(super) fn finite(name: &str, value: f64) -> Result<(), String> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{name} must be finite, got {value}"))
+    }
+}
+
+pub(super) fn non_negative(name: &str, value: f64) -> Result<(), String> {
+    if value.is_finite() && value >= 0.0 {
+        Ok(())
+    } else {
+        Err(format!("{name} must be finite and not negative, got {value}"))
+    }
+}
+
+pub(super) fn positive(name: &str, value: f64) -> Result<(), String> {
+    if value.is_finite() && value > 0.0 {
+        Ok(())
+    } else {
+        Err(format!("{name} must be finite and positive, got {value}"))
+    }
+}
+
+pub(super) fn distribution(name: &str, kind: &DistributionKind) -> Result<(), String> {
+    kind.validate().map_err(|err| format!("{name}: {err}"))
+}
+
+/// Checks that `curve`, if set, never scales its parameter below zero.
+pub(super) fn non_negative_curve(name: &str, curve: &Option<ParamCurve>) -> Result<(), String> {
+    let Some(curve) = curve else {
+        return Ok(());
+    };
+    let (min, _) = curve.curve.range();
+    if min >= 0.0 {
+        Ok(())
+    } else {
+        Err(format!("{name} must not go below zero, goes down to {min}"))
+    }
+}
+
+/// Prefixes the field named in `result`'s error with `name`, for params nested in others.
+pub(super) fn nested(name: &str, result: Result<(), String>) -> Result<(), String> {
+    result.map_err(|err| format!("{name}.{err}"))
+}
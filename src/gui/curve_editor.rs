@@ -0,0 +1,149 @@
+use druid::{
+    kurbo::{BezPath, Circle, Line},
+    BoxConstraints, Color, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx,
+    PaintCtx, Point, RenderContext, Size, UpdateCtx, Widget,
+};
+
+use crate::utils::curve::Curve;
+
+/// Largest value the editor shows. Curves scale parameters, so 1 sits in the middle.
+const MAX_VALUE: f64 = 2.0;
+const KEY_RADIUS: f64 = 4.0;
+/// How far from a key a click still grabs it.
+const GRAB_RADIUS: f64 = 2.0 * KEY_RADIUS;
+/// Number of straight pieces the curve is drawn with.
+const SAMPLES: usize = 64;
+
+/// Edits a [`Curve`] over inputs from 0 to 1 and values from 0 to [`MAX_VALUE`].
+///
+/// Clicking empty space adds a key, dragging moves one and right-clicking removes one.
+pub struct CurveEditor {
+    size: Size,
+    /// The key being dragged.
+    dragging: Option<usize>,
+}
+
+impl CurveEditor {
+    pub fn new(size: Size) -> Self {
+        Self {
+            size,
+            dragging: None,
+        }
+    }
+
+    fn to_screen(&self, key: [f64; 2], area: Size) -> Point {
+        Point::new(
+            key[0] * area.width,
+            (1.0 - key[1] / MAX_VALUE) * area.height,
+        )
+    }
+
+    fn to_key(&self, point: Point, area: Size) -> [f64; 2] {
+        [
+            (point.x / area.width).clamp(0.0, 1.0),
+            ((1.0 - point.y / area.height) * MAX_VALUE).clamp(0.0, MAX_VALUE),
+        ]
+    }
+
+    /// Returns the index of the key closest to `point`, if one is close enough to grab.
+    fn key_at(&self, curve: &Curve, point: Point, area: Size) -> Option<usize> {
+        curve
+            .keys()
+            .iter()
+            .map(|&key| self.to_screen(key, area).distance(point))
+            .enumerate()
+            .filter(|&(_, distance)| distance <= GRAB_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+}
+
+impl Widget<Curve> for CurveEditor {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Curve, _env: &Env) {
+        let area = ctx.size();
+        match event {
+            Event::MouseDown(mouse) if mouse.button.is_left() => {
+                self.dragging = self
+                    .key_at(data, mouse.pos, area)
+                    .or_else(|| data.insert_key(self.to_key(mouse.pos, area)));
+                if self.dragging.is_some() {
+                    ctx.set_active(true);
+                }
+            }
+            Event::MouseDown(mouse) if mouse.button.is_right() => {
+                if let Some(index) = self.key_at(data, mouse.pos, area) {
+                    data.remove_key(index);
+                }
+            }
+            Event::MouseMove(mouse) if ctx.is_active() => {
+                if let Some(index) = self.dragging {
+                    self.dragging = data.move_key(index, self.to_key(mouse.pos, area));
+                }
+            }
+            Event::MouseUp(_) if ctx.is_active() => {
+                self.dragging = None;
+                ctx.set_active(false);
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &Curve,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &Curve, data: &Curve, _env: &Env) {
+        if !old_data.same(data) {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &Curve,
+        _env: &Env,
+    ) -> Size {
+        bc.constrain(self.size)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &Curve, _env: &Env) {
+        let area = ctx.size();
+        ctx.fill(area.to_rect(), &Color::grey8(40));
+        let unit = self.to_screen([0.0, 1.0], area).y;
+        ctx.stroke(
+            Line::new((0.0, unit), (area.width, unit)),
+            &Color::grey8(90),
+            1.0,
+        );
+
+        let mut path = BezPath::new();
+        for i in 0..=SAMPLES {
+            let x = i as f64 / SAMPLES as f64;
+            let point = self.to_screen([x, data.evaluate(x).clamp(0.0, MAX_VALUE)], area);
+            if i == 0 {
+                path.move_to(point);
+            } else {
+                path.line_to(point);
+            }
+        }
+        ctx.with_save(|ctx| {
+            ctx.clip(area.to_rect());
+            ctx.stroke(path, &Color::rgb8(120, 200, 120), 2.0);
+            for (index, &key) in data.keys().iter().enumerate() {
+                let color = if self.dragging == Some(index) {
+                    Color::rgb8(255, 200, 0)
+                } else {
+                    Color::WHITE
+                };
+                ctx.fill(Circle::new(self.to_screen(key, area), KEY_RADIUS), &color);
+            }
+        });
+    }
+}
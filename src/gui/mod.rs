@@ -1,3 +1,5 @@
+pub mod curve_editor;
+
 use std::{
     cell::RefCell,
     rc::Rc,
@@ -13,6 +15,8 @@ use image::DynamicImage;
 use crate::{
     generator::{
         branch_layer::BranchParams,
+        foliage_layer::FoliageParams,
+        tropism_layer::TropismParams,
         trunk_layer::TrunkParams,
     },
     skeleton::tree::Tree,
@@ -25,10 +29,18 @@ pub const ADJUST_SLIDER: Selector = Selector::new("treegen.adjust_slider");
 pub struct AppData {
     pub trunk_params: TrunkParams,
     pub branch_params: BranchParams,
+    pub tropism_params: TropismParams,
+    pub foliage_params: FoliageParams,
     pub tree: Rc<RefCell<Tree>>,
     pub image: Rc<RefCell<DynamicImage>>,
     pub image_updated: bool,
     pub auto_generate: bool,
+    pub seed: u64,
+    pub tapered: bool,
+    pub antialiased: bool,
+    pub branches_enabled: bool,
+    pub foliage_enabled: bool,
+    pub report: String,
 }
 
 pub struct DynamicImageWidget;
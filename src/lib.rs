@@ -1,6 +1,8 @@
 pub mod utils;
 pub mod skeleton;
+pub mod analysis;
 pub mod generator;
+pub mod lsystem;
 pub mod sampler;
 pub mod rendering;
 pub mod gui;
@@ -0,0 +1,80 @@
+//! Classic grammars, mostly from *The Algorithmic Beauty of Plants* by Prusinkiewicz and
+//! Lindenmayer.
+
+const EXAMPLES: [(&str, &str); 6] = [
+    (
+        "fractal_plant",
+        "axiom: X
+angle: 25
+iterations: 5
+X -> F+[[X]-X]-F[-FX]+X
+F -> FF
+",
+    ),
+    (
+        "bush",
+        "axiom: F
+angle: 22.5
+iterations: 4
+F -> FF-[-F+F+F]+[+F-F-F]
+",
+    ),
+    (
+        "weed",
+        "axiom: F
+angle: 25.7
+iterations: 5
+F -> F[+F]F[-F]F
+",
+    ),
+    (
+        "twig",
+        "axiom: X
+angle: 20
+iterations: 7
+X -> F[+X]F[-X]+X
+F -> FF
+",
+    ),
+    (
+        "stochastic_weed",
+        "# Each F picks one of three productions with equal probability.
+axiom: F
+angle: 25.7
+iterations: 5
+F ~ 0.33 -> F[+F]F[-F]F
+F ~ 0.33 -> F[+F]F
+F ~ 0.34 -> F[-F]F
+",
+    ),
+    (
+        "parametric_tree",
+        "# A(length, width): each apex forks into two shorter, thinner ones until they
+# get too short.
+axiom: A(4, 1)
+angle: 30
+iterations: 9
+A(l, w) : l >= 0.6 -> !(w) F(l) [ +(35) A(l * 0.7, w * 0.7) ] [ -(25) A(l * 0.75, w * 0.7) ]
+A(l, w) : l < 0.6 -> !(w) F(l)
+",
+    ),
+];
+
+/// Names of the example grammars.
+pub const NAMES: [&str; EXAMPLES.len()] = {
+    let mut names = [""; EXAMPLES.len()];
+    let mut i = 0;
+    while i < EXAMPLES.len() {
+        names[i] = EXAMPLES[i].0;
+        i += 1;
+    }
+    names
+};
+
+/// Returns the source text of the example grammar called `name`.
+pub fn source(name: &str) -> Option<&'static str> {
+    EXAMPLES
+        .iter()
+        .find(|(example, _)| *example == name)
+        .map(|(_, source)| *source)
+}
@@ -0,0 +1,61 @@
+/// An arithmetic or logical expression over the parameters of a module.
+///
+/// Logical operators treat any non-zero value as true and evaluate to `1.0` or `0.0`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(f64),
+    /// The parameter of the predecessor at this position.
+    Parameter(usize),
+    Negate(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+impl Expression {
+    /// Evaluates the expression with `parameters` bound to the predecessor's parameters.
+    pub fn evaluate(&self, parameters: &[f64]) -> f64 {
+        let truth = |value: bool| if value { 1.0 } else { 0.0 };
+        match self {
+            Expression::Number(value) => *value,
+            Expression::Parameter(index) => parameters[*index],
+            Expression::Negate(operand) => -operand.evaluate(parameters),
+            Expression::Not(operand) => truth(operand.evaluate(parameters) == 0.0),
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(parameters);
+                let right = right.evaluate(parameters);
+                match operator {
+                    BinaryOperator::Add => left + right,
+                    BinaryOperator::Subtract => left - right,
+                    BinaryOperator::Multiply => left * right,
+                    BinaryOperator::Divide => left / right,
+                    BinaryOperator::Power => left.powf(right),
+                    BinaryOperator::Less => truth(left < right),
+                    BinaryOperator::LessOrEqual => truth(left <= right),
+                    BinaryOperator::Greater => truth(left > right),
+                    BinaryOperator::GreaterOrEqual => truth(left >= right),
+                    BinaryOperator::Equal => truth(left == right),
+                    BinaryOperator::NotEqual => truth(left != right),
+                    BinaryOperator::And => truth(left != 0.0 && right != 0.0),
+                    BinaryOperator::Or => truth(left != 0.0 || right != 0.0),
+                }
+            }
+        }
+    }
+}
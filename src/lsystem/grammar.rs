@@ -0,0 +1,171 @@
+use std::fmt::{self, Display, Formatter};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{examples, expression::Expression, parser::ParseError};
+
+/// Derivation stops early rather than grow a string past this many modules.
+pub const MAX_MODULES: usize = 2_000_000;
+
+/// Upper bound on the derivation depth. Growing grammars hit [`MAX_MODULES`] long before.
+pub const MAX_ITERATIONS: usize = 64;
+
+/// A symbol with its actual parameters, e.g. `F(2.5)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub symbol: char,
+    pub parameters: Vec<f64>,
+}
+
+impl Module {
+    pub fn new(symbol: char, parameters: Vec<f64>) -> Self {
+        Self { symbol, parameters }
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol)?;
+        if !self.parameters.is_empty() {
+            let parameters: Vec<String> = self.parameters.iter().map(f64::to_string).collect();
+            write!(f, "({})", parameters.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// A module in a successor, whose parameters are computed from the predecessor's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleTemplate {
+    pub symbol: char,
+    pub arguments: Vec<Expression>,
+}
+
+impl ModuleTemplate {
+    fn instantiate(&self, parameters: &[f64]) -> Module {
+        Module {
+            symbol: self.symbol,
+            parameters: self
+                .arguments
+                .iter()
+                .map(|argument| argument.evaluate(parameters))
+                .collect(),
+        }
+    }
+}
+
+/// A production `predecessor(formals) : condition ~ weight -> successor`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub predecessor: char,
+    /// Number of formal parameters; a rule only matches modules with exactly this many.
+    pub arity: usize,
+    pub condition: Option<Expression>,
+    /// Relative probability of this rule among the rules matching the same module.
+    pub weight: f64,
+    pub successor: Vec<ModuleTemplate>,
+}
+
+impl Rule {
+    fn matches(&self, module: &Module) -> bool {
+        self.predecessor == module.symbol
+            && self.arity == module.parameters.len()
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.evaluate(&module.parameters) != 0.0)
+    }
+}
+
+/// A parsed L-system: an axiom, its productions and the defaults of its interpretation.
+///
+/// L-systems are written in the text format described in the [module docs](super) and
+/// serialize back to their source text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LSystem {
+    pub(super) source: String,
+    pub axiom: Vec<Module>,
+    pub rules: Vec<Rule>,
+    /// Turning angle in degrees used by `+` and `-` without a parameter.
+    pub angle_deg: f64,
+    /// Default number of derivation steps.
+    pub iterations: usize,
+}
+
+impl LSystem {
+    /// Parses an L-system from its text format.
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        super::parser::parse(source)
+    }
+
+    /// Returns one of the [example grammars](examples::NAMES) by name.
+    pub fn example(name: &str) -> Option<Self> {
+        examples::source(name).map(|source| Self::parse(source).expect("examples are valid"))
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Rewrites the axiom `iterations` times. Stochastic rules draw from `rng`, so the same
+    /// rng state always gives the same string. A rewrite that would grow the string past
+    /// [`MAX_MODULES`] is abandoned as soon as it does, and the string before it is returned.
+    pub fn derive<R: Rng + ?Sized>(&self, iterations: usize, rng: &mut R) -> Vec<Module> {
+        let mut modules = self.axiom.clone();
+        for _ in 0..iterations {
+            let mut next = Vec::with_capacity((modules.len() * 2).min(MAX_MODULES));
+            for module in &modules {
+                match self.choose_rule(module, rng) {
+                    Some(rule) => next.extend(
+                        rule.successor
+                            .iter()
+                            .map(|template| template.instantiate(&module.parameters)),
+                    ),
+                    None => next.push(module.clone()),
+                }
+                if next.len() > MAX_MODULES {
+                    return modules;
+                }
+            }
+            modules = next;
+        }
+        modules
+    }
+
+    fn choose_rule<R: Rng + ?Sized>(&self, module: &Module, rng: &mut R) -> Option<&Rule> {
+        let matching: Vec<&Rule> = self.rules.iter().filter(|rule| rule.matches(module)).collect();
+        if matching.len() <= 1 {
+            return matching.first().copied();
+        }
+
+        let total: f64 = matching.iter().map(|rule| rule.weight).sum();
+        let mut pick = rng.gen::<f64>() * total;
+        for &rule in &matching {
+            if pick < rule.weight {
+                return Some(rule);
+            }
+            pick -= rule.weight;
+        }
+        matching.last().copied()
+    }
+}
+
+impl TryFrom<String> for LSystem {
+    type Error = ParseError;
+
+    /// Accepts either the name of an example grammar or a grammar in the text format.
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match LSystem::example(&value) {
+            Some(example) => Ok(example),
+            None => LSystem::parse(&value),
+        }
+    }
+}
+
+impl From<LSystem> for String {
+    fn from(value: LSystem) -> Self {
+        value.source
+    }
+}
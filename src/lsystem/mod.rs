@@ -0,0 +1,28 @@
+//! L-systems: string rewriting grammars interpreted by a turtle into a tree skeleton.
+//!
+//! Grammars are written one statement per line; `#` starts a comment.
+//!
+//! ```text
+//! axiom: A(4, 1)           # the starting string, required
+//! angle: 30                # default turning angle in degrees, 25 if omitted
+//! iterations: 9            # default derivation depth, 4 if omitted, at most 64
+//! F -> FF                  # a production
+//! F ~ 0.3 -> F[+F]F        # a stochastic production with weight 0.3
+//! A(l, w) : l >= 0.6 -> !(w) F(l) [+(40) A(l*0.6, w*0.6)] A(l*0.8, w*0.75)
+//! ```
+//!
+//! Productions are `predecessor[(params)] [: condition] [~ weight] -> successor`. A module
+//! with parameters only matches productions with the same number of parameters. When
+//! several productions match a module, one is picked at random in proportion to the
+//! weights. Parameter expressions support `+ - * / ^`, comparisons, `&&`, `||` and `!`.
+//! See [`Turtle`] for how symbols are drawn.
+
+pub mod examples;
+pub mod expression;
+pub mod grammar;
+pub mod parser;
+pub mod turtle;
+
+pub use grammar::{LSystem, Module};
+pub use parser::ParseError;
+pub use turtle::Turtle;
@@ -0,0 +1,492 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use super::{
+    expression::{BinaryOperator, Expression},
+    grammar::{LSystem, Module, ModuleTemplate, Rule, MAX_ITERATIONS},
+};
+
+const DEFAULT_ANGLE_DEG: f64 = 25.0;
+const DEFAULT_ITERATIONS: usize = 4;
+
+/// Characters that have a meaning in the grammar and can't be used as module symbols.
+const RESERVED: &str = "(),:~#";
+
+/// A syntax error, with the 1-based line and column it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+pub(super) fn parse(source: &str) -> Result<LSystem, ParseError> {
+    let mut axiom = None;
+    let mut angle_deg = DEFAULT_ANGLE_DEG;
+    let mut iterations = DEFAULT_ITERATIONS;
+    let mut rules = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut cursor = Cursor::new(line, line_index + 1);
+        cursor.skip_whitespace();
+        if cursor.at_end() {
+            continue;
+        }
+
+        match cursor.directive() {
+            Some((name, column)) => match name.as_str() {
+                "axiom" => {
+                    let templates = cursor.modules(&[])?;
+                    axiom = Some(
+                        templates
+                            .iter()
+                            .map(|template| Module {
+                                symbol: template.symbol,
+                                parameters: template
+                                    .arguments
+                                    .iter()
+                                    .map(|argument| argument.evaluate(&[]))
+                                    .collect(),
+                            })
+                            .collect(),
+                    );
+                }
+                "angle" => angle_deg = cursor.constant()?,
+                "iterations" => {
+                    let value = cursor.constant()?;
+                    if !(0.0..=MAX_ITERATIONS as f64).contains(&value) || value.fract() != 0.0 {
+                        return Err(cursor.error_at(
+                            column,
+                            format!("iterations must be a whole number from 0 to {MAX_ITERATIONS}"),
+                        ));
+                    }
+                    iterations = value as usize;
+                }
+                _ => return Err(cursor.error_at(column, format!("unknown directive `{name}`"))),
+            },
+            None => rules.push(cursor.rule()?),
+        }
+    }
+
+    let axiom = axiom.ok_or_else(|| ParseError {
+        line: 1,
+        column: 1,
+        message: "missing `axiom:` line".to_owned(),
+    })?;
+    Ok(LSystem {
+        source: source.to_owned(),
+        axiom,
+        rules,
+        angle_deg,
+        iterations,
+    })
+}
+
+/// Reads a single line of the grammar.
+struct Cursor {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl Cursor {
+    fn new(line: &str, line_number: usize) -> Self {
+        Self {
+            chars: line.chars().collect(),
+            position: 0,
+            line: line_number,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        self.error_at(self.position + 1, message)
+    }
+
+    fn error_at(&self, column: usize, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.chars.get(self.position + 1).copied()
+    }
+
+    fn at_end(&self) -> bool {
+        self.position >= self.chars.len()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// Consumes `expected` after any whitespace, returning whether it was there.
+    fn eat(&mut self, expected: &str) -> bool {
+        self.skip_whitespace();
+        let matches = expected
+            .chars()
+            .enumerate()
+            .all(|(offset, c)| self.chars.get(self.position + offset) == Some(&c));
+        if matches {
+            self.position += expected.chars().count();
+        }
+        matches
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), ParseError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{expected}`")))
+        }
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        let start = self.position;
+        if !self.peek().is_some_and(|c| c.is_alphabetic() || c == '_') {
+            return None;
+        }
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.position += 1;
+        }
+        Some(self.chars[start..self.position].iter().collect())
+    }
+
+    /// Consumes `name:` when the line starts with a directive and returns the name and its
+    /// column. Module symbols are single characters, so rules never look like directives.
+    fn directive(&mut self) -> Option<(String, usize)> {
+        let start = self.position;
+        if let Some(name) = self.identifier() {
+            if name.chars().count() > 1 && self.eat(":") {
+                return Some((name, start + 1));
+            }
+        }
+        self.position = start;
+        None
+    }
+
+    /// Parses `predecessor(formals) : condition ~ weight -> successor`.
+    fn rule(&mut self) -> Result<Rule, ParseError> {
+        let predecessor = self.symbol()?;
+        let mut formals = Vec::new();
+        if self.peek() == Some('(') {
+            self.position += 1;
+            loop {
+                self.skip_whitespace();
+                let column = self.position + 1;
+                let name = self
+                    .identifier()
+                    .ok_or_else(|| self.error("expected a parameter name"))?;
+                if formals.contains(&name) {
+                    return Err(self.error_at(column, format!("duplicate parameter `{name}`")));
+                }
+                formals.push(name);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+
+        let condition = if self.eat(":") {
+            Some(self.expression(&formals)?)
+        } else {
+            None
+        };
+        let weight = if self.eat("~") {
+            self.skip_whitespace();
+            let column = self.position + 1;
+            let weight = self.expression(&[])?.evaluate(&[]);
+            if !(weight.is_finite() && weight >= 0.0) {
+                return Err(self.error_at(column, "weights must be finite and not negative"));
+            }
+            weight
+        } else {
+            1.0
+        };
+        self.expect("->")?;
+        let successor = self.modules(&formals)?;
+
+        Ok(Rule {
+            predecessor,
+            arity: formals.len(),
+            condition,
+            weight,
+            successor,
+        })
+    }
+
+    fn symbol(&mut self) -> Result<char, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(self.error("expected a module")),
+            Some(c) if RESERVED.contains(c) => Err(self.error(format!("unexpected `{c}`"))),
+            Some(c) => {
+                self.position += 1;
+                Ok(c)
+            }
+        }
+    }
+
+    /// Parses modules up to the end of the line, e.g. `F(l*0.5)[+X]`.
+    fn modules(&mut self, formals: &[String]) -> Result<Vec<ModuleTemplate>, ParseError> {
+        let mut modules = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.at_end() {
+                return Ok(modules);
+            }
+            let symbol = self.symbol()?;
+            let mut arguments = Vec::new();
+            if self.peek() == Some('(') {
+                self.position += 1;
+                loop {
+                    arguments.push(self.expression(formals)?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            modules.push(ModuleTemplate { symbol, arguments });
+        }
+    }
+
+    /// Parses an expression that can't refer to any parameter and evaluates it.
+    fn constant(&mut self) -> Result<f64, ParseError> {
+        self.skip_whitespace();
+        let column = self.position + 1;
+        let value = self.expression(&[])?.evaluate(&[]);
+        self.skip_whitespace();
+        if !self.at_end() {
+            return Err(self.error("unexpected characters after the value"));
+        }
+        if !value.is_finite() {
+            return Err(self.error_at(column, format!("value must be finite, got {value}")));
+        }
+        Ok(value)
+    }
+
+    fn expression(&mut self, formals: &[String]) -> Result<Expression, ParseError> {
+        let mut left = self.conjunction(formals)?;
+        while self.eat("||") {
+            let right = self.conjunction(formals)?;
+            left = binary(BinaryOperator::Or, left, right);
+        }
+        Ok(left)
+    }
+
+    fn conjunction(&mut self, formals: &[String]) -> Result<Expression, ParseError> {
+        let mut left = self.comparison(formals)?;
+        while self.eat("&&") {
+            let right = self.comparison(formals)?;
+            left = binary(BinaryOperator::And, left, right);
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self, formals: &[String]) -> Result<Expression, ParseError> {
+        let left = self.sum(formals)?;
+        let operators = [
+            ("<=", BinaryOperator::LessOrEqual),
+            (">=", BinaryOperator::GreaterOrEqual),
+            ("==", BinaryOperator::Equal),
+            ("!=", BinaryOperator::NotEqual),
+            ("<", BinaryOperator::Less),
+            (">", BinaryOperator::Greater),
+        ];
+        for (token, operator) in operators {
+            if self.eat(token) {
+                let right = self.sum(formals)?;
+                return Ok(binary(operator, left, right));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self, formals: &[String]) -> Result<Expression, ParseError> {
+        let mut left = self.product(formals)?;
+        loop {
+            self.skip_whitespace();
+            let operator = match (self.peek(), self.peek_second()) {
+                (Some('+'), _) => BinaryOperator::Add,
+                // `->` ends a condition rather than subtracting.
+                (Some('-'), next) if next != Some('>') => BinaryOperator::Subtract,
+                _ => return Ok(left),
+            };
+            self.position += 1;
+            let right = self.product(formals)?;
+            left = binary(operator, left, right);
+        }
+    }
+
+    fn product(&mut self, formals: &[String]) -> Result<Expression, ParseError> {
+        let mut left = self.unary(formals)?;
+        loop {
+            let operator = if self.eat("*") {
+                BinaryOperator::Multiply
+            } else if self.eat("/") {
+                BinaryOperator::Divide
+            } else {
+                return Ok(left);
+            };
+            let right = self.unary(formals)?;
+            left = binary(operator, left, right);
+        }
+    }
+
+    fn unary(&mut self, formals: &[String]) -> Result<Expression, ParseError> {
+        self.skip_whitespace();
+        if self.peek() == Some('-') {
+            self.position += 1;
+            return Ok(Expression::Negate(Box::new(self.unary(formals)?)));
+        }
+        if self.peek() == Some('!') && self.peek_second() != Some('=') {
+            self.position += 1;
+            return Ok(Expression::Not(Box::new(self.unary(formals)?)));
+        }
+        let base = self.atom(formals)?;
+        if self.eat("^") {
+            let exponent = self.unary(formals)?;
+            return Ok(binary(BinaryOperator::Power, base, exponent));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self, formals: &[String]) -> Result<Expression, ParseError> {
+        self.skip_whitespace();
+        let column = self.position + 1;
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let inner = self.expression(formals)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.position;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.position += 1;
+                }
+                let text: String = self.chars[start..self.position].iter().collect();
+                text.parse()
+                    .map(Expression::Number)
+                    .map_err(|_| self.error_at(column, format!("invalid number `{text}`")))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.identifier().unwrap_or_default();
+                formals
+                    .iter()
+                    .position(|formal| *formal == name)
+                    .map(Expression::Parameter)
+                    .ok_or_else(|| self.error_at(column, format!("unknown parameter `{name}`")))
+            }
+            Some(c) => Err(self.error(format!("unexpected `{c}` in expression"))),
+            None => Err(self.error("expected an expression")),
+        }
+    }
+}
+
+fn binary(operator: BinaryOperator, left: Expression, right: Expression) -> Expression {
+    Expression::Binary(operator, Box::new(left), Box::new(right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lsystem::examples, utils::rng::rng_from_seed};
+
+    /// Returns the line and column `source` fails to parse at, checking the message.
+    fn error_at(source: &str, message: &str) -> (usize, usize) {
+        let err = parse(source).unwrap_err();
+        assert!(err.message.contains(message), "{err}");
+        (err.line, err.column)
+    }
+
+    fn derive(source: &str, iterations: usize, seed: u64) -> String {
+        let lsystem = parse(source).unwrap();
+        let modules = lsystem.derive(iterations, &mut rng_from_seed(seed));
+        modules.iter().map(Module::to_string).collect()
+    }
+
+    #[test]
+    fn examples_parse() {
+        for name in examples::NAMES {
+            let lsystem = parse(examples::source(name).unwrap()).unwrap();
+            assert!(!lsystem.axiom.is_empty(), "{name}");
+            assert!(!lsystem.rules.is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn defaults_and_directives() {
+        let lsystem = parse("# comment\n\naxiom: F\nF -> FF").unwrap();
+        assert_eq!(lsystem.angle_deg, DEFAULT_ANGLE_DEG);
+        assert_eq!(lsystem.iterations, DEFAULT_ITERATIONS);
+
+        let lsystem = parse("axiom: F\nangle: 90 / 4\niterations: 2 * 3").unwrap();
+        assert_eq!(lsystem.angle_deg, 22.5);
+        assert_eq!(lsystem.iterations, 6);
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error_at("axiom: F\nlength: 3", "unknown directive"), (2, 1));
+        assert_eq!(error_at("axiom: F\nA(x) -> F(y)", "unknown parameter `y`"), (2, 11));
+        assert_eq!(error_at("axiom: F\nF FF", "expected `->`"), (2, 3));
+        assert_eq!(error_at("axiom: F\nA(x, x) -> F", "duplicate parameter `x`"), (2, 6));
+        assert_eq!(error_at("axiom: F\nF ~ -1 -> FF", "weights must be finite"), (2, 5));
+        assert_eq!(error_at("F -> FF", "missing `axiom:`"), (1, 1));
+    }
+
+    #[test]
+    fn directives_must_be_finite_and_bounded() {
+        assert_eq!(error_at("axiom: F\nangle: 0/0", "must be finite"), (2, 8));
+        assert_eq!(error_at("axiom: F\nangle: 1/0", "must be finite"), (2, 8));
+        assert_eq!(error_at("iterations: 65\naxiom: F", "from 0 to 64"), (1, 1));
+        assert_eq!(error_at("iterations: 2.5\naxiom: F", "whole number"), (1, 1));
+        assert_eq!(error_at("axiom: F\nF ~ 1/0 -> FF", "weights must be finite"), (2, 5));
+    }
+
+    #[test]
+    fn parametric_rules_follow_their_conditions() {
+        let source = "axiom: A(4)\nA(l) : l > 1 -> F(l) A(l / 2)";
+        assert_eq!(derive(source, 1, 0), "F(4)A(2)");
+        assert_eq!(derive(source, 5, 0), "F(4)F(2)A(1)");
+        // Rules only match modules with the same number of parameters.
+        assert_eq!(derive("axiom: A A(1, 2)\nA(l) -> B", 1, 0), "AA(1,2)");
+    }
+
+    #[test]
+    fn stochastic_rules_are_picked_by_weight() {
+        let source = "axiom: FFFFFFFFFFFFFFFFFFFF\nF ~ 1 -> A\nF ~ 3 -> B";
+        let mut b_count = 0;
+        for seed in 0..50 {
+            let derived = derive(source, 1, seed);
+            assert_eq!(derived, derive(source, 1, seed));
+            assert!(derived.chars().all(|symbol| symbol == 'A' || symbol == 'B'));
+            b_count += derived.matches('B').count();
+        }
+        let share = b_count as f64 / 1000.0;
+        assert!((0.7..0.8).contains(&share), "{share}");
+    }
+}
@@ -0,0 +1,198 @@
+use palette::Srgb;
+
+use crate::skeleton::tree::Tree;
+
+use super::grammar::Module;
+
+/// Interprets a derived string as drawing commands for a turtle that walks up from the root.
+///
+/// | Symbol  | Meaning                                                            |
+/// |---------|--------------------------------------------------------------------|
+/// | `F(l)`, `G(l)` | Draw a segment `l` times `step_length` long (`l` defaults to 1) |
+/// | `+(a)`  | Turn left by `a` degrees (defaults to `angle_deg`)                |
+/// | `-(a)`  | Turn right by `a` degrees (defaults to `angle_deg`)               |
+/// | `\|`    | Turn around                                                        |
+/// | `[`     | Start a branch; its thickness is scaled by `thickness_ratio`      |
+/// | `]`     | End the branch and return to where it started                     |
+/// | `!(w)`  | Set the thickness to `w` times `thickness`, or scale it by `thickness_ratio` |
+///
+/// Every other symbol is ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct Turtle {
+    pub angle_deg: f64,
+    pub step_length: f64,
+    pub thickness: f64,
+    pub thickness_ratio: f64,
+    pub color: Srgb<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+    node: Option<usize>,
+    angle: f64,
+    thickness: f64,
+}
+
+impl Turtle {
+    /// Draws `modules` into `tree` through [`Tree::add_node`]. The turtle starts at the end
+    /// of `base`, heading the way it points, or at a new root at the tree's root position if
+    /// `base` is `None`. A branch that opens before anything was drawn starts from a root of
+    /// zero length, so that segments drawn after it attach to the same root.
+    pub fn interpret(&self, modules: &[Module], tree: &mut Tree, base: Option<usize>) {
+        let mut state = State {
+            node: base,
+            angle: base.map_or(0.0, |index| tree.nodes.nodes[index].angle),
+            thickness: self.thickness,
+        };
+        let mut stack = Vec::new();
+        let argument = |module: &Module, default: f64| -> f64 {
+            module.parameters.first().copied().unwrap_or(default)
+        };
+
+        for module in modules {
+            match module.symbol {
+                'F' | 'G' => {
+                    let length = argument(module, 1.0) * self.step_length;
+                    let index = tree.add_node(state.node, length, state.angle, state.thickness);
+                    tree.nodes.nodes[index].set_color(self.color);
+                    state.node = Some(index);
+                }
+                // Angles grow clockwise on screen, so turning left decreases them.
+                '+' => state.angle -= argument(module, self.angle_deg).to_radians(),
+                '-' => state.angle += argument(module, self.angle_deg).to_radians(),
+                '|' => state.angle += std::f64::consts::PI,
+                '[' => {
+                    if state.node.is_none() {
+                        let root = tree.add_node(None, 0.0, state.angle, state.thickness);
+                        tree.nodes.nodes[root].set_color(self.color);
+                        state.node = Some(root);
+                    }
+                    stack.push(state);
+                    state.thickness *= self.thickness_ratio;
+                }
+                ']' => {
+                    if let Some(saved) = stack.pop() {
+                        state = saved;
+                    }
+                }
+                '!' => {
+                    state.thickness = match module.parameters.first() {
+                        Some(width) => width * self.thickness,
+                        None => state.thickness * self.thickness_ratio,
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::{lsystem::LSystem, utils::rng::rng_from_seed};
+
+    const TURTLE: Turtle = Turtle {
+        angle_deg: 30.0,
+        step_length: 10.0,
+        thickness: 4.0,
+        thickness_ratio: 0.5,
+        color: Srgb::new(0, 0, 0),
+    };
+
+    /// Draws the axiom of a grammar made of `axiom` alone into a new tree.
+    fn draw(axiom: &str) -> Tree {
+        let lsystem = LSystem::parse(&format!("axiom: {axiom}")).unwrap();
+        let mut tree = Tree::new();
+        TURTLE.interpret(&lsystem.axiom, &mut tree, None);
+        tree
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn branches_return_to_where_they_started() {
+        let tree = draw("F[+F[-F]F]F");
+        let nodes = &tree.nodes.nodes;
+        assert_eq!(nodes.len(), 5);
+        let parents: Vec<_> = nodes.iter().map(|node| node.parent_index).collect();
+        assert_eq!(parents, [None, Some(0), Some(1), Some(1), Some(0)]);
+
+        assert_close(nodes[1].angle, -30f64.to_radians());
+        assert_close(nodes[2].angle, 0.0);
+        assert_close(nodes[3].angle, -30f64.to_radians());
+        assert_close(nodes[4].angle, 0.0);
+        let thicknesses: Vec<_> = nodes.iter().map(|node| node.thickness).collect();
+        assert_eq!(thicknesses, [4.0, 2.0, 1.0, 2.0, 4.0]);
+    }
+
+    #[test]
+    fn branches_before_the_first_segment_share_its_root() {
+        let tree = draw("[+F]F");
+        let nodes = &tree.nodes.nodes;
+        let parents: Vec<_> = nodes.iter().map(|node| node.parent_index).collect();
+        assert_eq!(parents, [None, Some(0), Some(0)]);
+        assert_eq!(nodes[0].length, 0.0);
+        assert_eq!(nodes[1].point, nodes[2].point);
+        assert_close(nodes[1].angle, -30f64.to_radians());
+        assert_close(nodes[2].angle, 0.0);
+
+        let loaded = Tree::from_json(&tree.to_json().unwrap()).unwrap();
+        assert_eq!(format!("{:?}", loaded.nodes.nodes), format!("{:?}", tree.nodes.nodes));
+    }
+
+    #[test]
+    fn turns_change_the_heading_of_later_segments() {
+        let angles = |axiom: &str| -> Vec<f64> {
+            draw(axiom).nodes.nodes.iter().map(|node| node.angle).collect()
+        };
+        let [left] = angles("+F")[..] else { panic!() };
+        assert_close(left, -30f64.to_radians());
+        let [right] = angles("-F")[..] else { panic!() };
+        assert_close(right, 30f64.to_radians());
+        let [explicit] = angles("+(10)F")[..] else { panic!() };
+        assert_close(explicit, -10f64.to_radians());
+        let [up, down] = angles("F|F")[..] else { panic!() };
+        assert_close(up, 0.0);
+        assert_close(down, PI);
+    }
+
+    #[test]
+    fn only_draw_symbols_add_segments() {
+        let tree = draw("F f F(2) X G");
+        let lengths: Vec<_> = tree.nodes.nodes.iter().map(|node| node.length).collect();
+        assert_eq!(lengths, [10.0, 20.0, 10.0]);
+        let parents: Vec<_> = tree.nodes.nodes.iter().map(|node| node.parent_index).collect();
+        assert_eq!(parents, [None, Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn every_f_of_a_derivation_becomes_a_node() {
+        let lsystem = LSystem::example("weed").unwrap();
+        let modules = lsystem.derive(3, &mut rng_from_seed(0));
+        let mut tree = Tree::new();
+        TURTLE.interpret(&modules, &mut tree, None);
+        // Every F turns into five.
+        assert_eq!(tree.nodes.nodes.len(), 125);
+        assert_eq!(tree.get_tip_nodes().len(), 1 + 2 * (1 + 5 + 25));
+    }
+
+    #[test]
+    fn drawing_on_a_base_continues_it() {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 0.5, 4.0);
+        let lsystem = LSystem::parse("axiom: F+F").unwrap();
+        TURTLE.interpret(&lsystem.axiom, &mut tree, Some(root));
+
+        let nodes = &tree.nodes.nodes;
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[1].parent_index, Some(root));
+        assert_close(nodes[1].angle, 0.5);
+        assert_close(nodes[2].angle, 0.5 - 30f64.to_radians());
+        assert_eq!(nodes[1].point, nodes[0].next_point());
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter},
+    ops::RangeInclusive,
+    path::Path,
+    time::Duration,
+};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, ImageError, RgbaImage,
+};
+
+use crate::skeleton::{
+    node::{Node, NodeGraph},
+    tree::Tree,
+};
+
+/// Color quantization speed of GIF frames, from 1 (best) to 30 (fastest).
+const GIF_SPEED: i32 = 10;
+
+/// Longest delay a single GIF frame can have, in hundredths of a second.
+const MAX_GIF_DELAY: Duration = Duration::from_millis(u16::MAX as u64 * 10);
+
+/// Upper bound on [`GrowthAnimation::frames`], including the frames a frame sequence repeats
+/// for the hold.
+pub const MAX_FRAMES: usize = 10_000;
+
+/// Bounds on [`GrowthAnimation::frame_rate`], in frames per second.
+pub const FRAME_RATES: RangeInclusive<f64> = 0.01..=1000.0;
+
+/// Upper bound on [`GrowthAnimation::hold`].
+pub const MAX_HOLD: Duration = Duration::from_secs(3600);
+
+/// How the reveal speeds up and slows down over the length of an animation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Starts slowly and speeds up.
+    EaseIn,
+    /// Starts quickly and slows down.
+    EaseOut,
+    /// Starts and ends slowly.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps linear time `t` in `0..=1` to progress in `0..=1`.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// Timing of an animation that grows a tree in from its root.
+///
+/// Segments are revealed depth by depth: every depth of the tree gets an equal share of the
+/// animation, and a segment grows along its length during its share. Leaves appear once their
+/// segment has grown past them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrowthAnimation {
+    /// Number of frames the tree grows over, including the empty first and the full last one.
+    pub frames: usize,
+    pub frame_rate: f64,
+    pub easing: Easing,
+    /// How long the finished tree stays on screen before the animation loops.
+    pub hold: Duration,
+}
+
+impl GrowthAnimation {
+    pub fn new(frames: usize, frame_rate: f64) -> Self {
+        Self {
+            frames,
+            frame_rate,
+            easing: Easing::Linear,
+            hold: Duration::ZERO,
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_FRAMES).contains(&self.frames) {
+            return Err(format!(
+                "frames must be between 1 and {MAX_FRAMES}, got {}",
+                self.frames
+            ));
+        }
+        if !FRAME_RATES.contains(&self.frame_rate) {
+            return Err(format!(
+                "frame_rate must be between {} and {}, got {}",
+                FRAME_RATES.start(),
+                FRAME_RATES.end(),
+                self.frame_rate
+            ));
+        }
+        if self.hold > MAX_HOLD {
+            return Err(format!(
+                "hold must be at most {} seconds, got {}",
+                MAX_HOLD.as_secs(),
+                self.hold.as_secs_f64()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Number of files a frame sequence of the animation takes: every frame and the copies of
+    /// the last one that make up the hold.
+    pub fn sequence_len(&self) -> usize {
+        self.frames.saturating_add(self.hold_frames())
+    }
+
+    /// How long every frame is shown.
+    pub fn frame_delay(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.frame_rate.max(f64::EPSILON))
+    }
+
+    /// Number of extra copies of the last frame that make up the hold in a frame sequence.
+    pub fn hold_frames(&self) -> usize {
+        (self.hold.as_secs_f64() * self.frame_rate).round() as usize
+    }
+
+    /// Share of the tree shown in `frame`, from 0 to 1.
+    pub fn progress(&self, frame: usize) -> f64 {
+        if self.frames <= 1 {
+            return 1.0;
+        }
+        self.easing.apply(frame as f64 / (self.frames - 1) as f64)
+    }
+
+    /// Returns the part of `tree` shown in `frame`.
+    pub fn frame(&self, tree: &Tree, frame: usize) -> Tree {
+        reveal(tree, self.progress(frame))
+    }
+
+    /// Returns the part of `tree` shown in every frame, in order.
+    pub fn frame_trees<'a>(&'a self, tree: &'a Tree) -> impl Iterator<Item = Tree> + 'a {
+        (0..self.frames).map(move |frame| self.frame(tree, frame))
+    }
+
+    /// Writes rendered frames as a looping GIF, encoding each one as soon as `frames` yields
+    /// it. The last frame is shown for the hold on top of its own delay; holds too long for a
+    /// single GIF frame repeat the last frame.
+    pub fn save_gif<I, P>(&self, frames: I, path: P) -> Result<(), AnimationError>
+    where
+        I: IntoIterator<Item = RgbaImage>,
+        P: AsRef<Path>,
+    {
+        let last_delay = self.last_delay()?;
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = GifEncoder::new_with_speed(file, GIF_SPEED);
+        encoder.set_repeat(Repeat::Infinite)?;
+        let mut frames = frames.into_iter().peekable();
+        while let Some(image) = frames.next() {
+            let mut remaining = if frames.peek().is_none() {
+                last_delay
+            } else {
+                self.frame_delay()
+            };
+            loop {
+                let delay = remaining.min(MAX_GIF_DELAY);
+                let frame_delay = Delay::from_saturating_duration(delay);
+                encoder.encode_frame(Frame::from_parts(image.clone(), 0, 0, frame_delay))?;
+                remaining -= delay;
+                if remaining.is_zero() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes rendered frames as a looping animated PNG, encoding each one as soon as `frames`
+    /// yields it. `frames` must yield [`GrowthAnimation::frames`] images. The last frame is
+    /// shown for the hold on top of its own delay, up to about 18 hours.
+    pub fn save_apng<I, P>(&self, frames: I, path: P) -> Result<(), AnimationError>
+    where
+        I: IntoIterator<Item = RgbaImage>,
+        P: AsRef<Path>,
+    {
+        let last_delay = self.last_delay()?;
+        let mut frames = frames.into_iter().peekable();
+        let Some(first) = frames.peek() else {
+            return Err(AnimationError::NoFrames);
+        };
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, first.width(), first.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.frames as u32, 0)?;
+        encoder.set_blend_op(png::BlendOp::Source)?;
+        let mut writer = encoder.write_header()?;
+        while let Some(image) = frames.next() {
+            let delay = if frames.peek().is_none() {
+                last_delay
+            } else {
+                self.frame_delay()
+            };
+            let (numerator, denominator) = apng_delay(delay);
+            writer.set_frame_delay(numerator, denominator)?;
+            writer.write_image_data(image.as_raw())?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// How long the last frame is shown: its own delay and the hold.
+    fn last_delay(&self) -> Result<Duration, AnimationError> {
+        self.validate().map_err(AnimationError::Invalid)?;
+        self.frame_delay()
+            .checked_add(self.hold)
+            .ok_or_else(|| AnimationError::Invalid("hold is too long".to_string()))
+    }
+}
+
+/// Returns `delay` as an APNG frame delay fraction in seconds, in the finest unit that can
+/// hold it: milliseconds for delays up to a minute, down to whole seconds. Longer delays than
+/// `u16::MAX` seconds are cut short.
+fn apng_delay(delay: Duration) -> (u16, u16) {
+    for denominator in [1000, 100, 10] {
+        let numerator = (delay.as_secs_f64() * denominator as f64).round();
+        if numerator <= u16::MAX as f64 {
+            return (numerator as u16, denominator);
+        }
+    }
+    (delay.as_secs_f64().round().min(u16::MAX as f64) as u16, 1)
+}
+
+/// Returns the part of `tree` that has grown in after `progress` (from 0 to 1) of a
+/// [`GrowthAnimation`]: nodes that have not started growing yet are left out and nodes that
+/// are growing are shortened.
+pub fn reveal(tree: &Tree, progress: f64) -> Tree {
+    let graph = &tree.nodes;
+    let depths = graph.depths();
+    let depth_count = depths.iter().max().map_or(0, |max| max + 1);
+    let shown = |index: usize| {
+        (progress.clamp(0.0, 1.0) * depth_count as f64 - depths[index] as f64).clamp(0.0, 1.0)
+    };
+
+    // A node only starts growing once its parent is complete, so the nodes left out are whole
+    // subtrees and the remaining nodes keep their positions.
+    let mut new_indices = vec![None; graph.nodes.len()];
+    let mut nodes: Vec<Node> = Vec::new();
+    for (index, node) in graph.nodes.iter().enumerate() {
+        if shown(index) <= 0.0 {
+            continue;
+        }
+        new_indices[index] = Some(nodes.len());
+        nodes.push(Node {
+            parent_index: node.parent_index.and_then(|parent| new_indices[parent]),
+            children_indices: Vec::new(),
+            length: node.length * shown(index),
+            ..node.clone()
+        });
+    }
+    for (index, node) in graph.nodes.iter().enumerate() {
+        if let Some(new_index) = new_indices[index] {
+            nodes[new_index].children_indices =
+                node.children_indices.iter().filter_map(|&child| new_indices[child]).collect();
+        }
+    }
+
+    let leaves = tree
+        .leaves
+        .iter()
+        .filter(|leaf| {
+            let node = &graph.nodes[leaf.node_index];
+            let along = if node.length > 0.0 {
+                (leaf.position - node.point).norm() / node.length
+            } else {
+                0.0
+            };
+            new_indices[leaf.node_index].is_some() && shown(leaf.node_index) >= along
+        })
+        .map(|leaf| {
+            let mut leaf = *leaf;
+            leaf.node_index = new_indices[leaf.node_index].expect("shown leaves have a node");
+            leaf
+        })
+        .collect();
+
+    Tree {
+        nodes: NodeGraph::from_nodes(graph.root_position(), nodes),
+        leaves,
+    }
+}
+
+#[derive(Debug)]
+pub enum AnimationError {
+    Io(io::Error),
+    Gif(ImageError),
+    Png(png::EncodingError),
+    NoFrames,
+    Invalid(String),
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationError::Io(err) => write!(f, "i/o error: {err}"),
+            AnimationError::Gif(err) => write!(f, "could not encode gif: {err}"),
+            AnimationError::Png(err) => write!(f, "could not encode png: {err}"),
+            AnimationError::NoFrames => write!(f, "an animation needs at least one frame"),
+            AnimationError::Invalid(err) => write!(f, "invalid animation: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AnimationError::Io(err) => Some(err),
+            AnimationError::Gif(err) => Some(err),
+            AnimationError::Png(err) => Some(err),
+            AnimationError::NoFrames | AnimationError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for AnimationError {
+    fn from(value: io::Error) -> Self {
+        AnimationError::Io(value)
+    }
+}
+
+impl From<ImageError> for AnimationError {
+    fn from(value: ImageError) -> Self {
+        AnimationError::Gif(value)
+    }
+}
+
+impl From<png::EncodingError> for AnimationError {
+    fn from(value: png::EncodingError) -> Self {
+        AnimationError::Png(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+    use super::*;
+
+    #[test]
+    fn apng_delays_keep_long_holds() {
+        assert_eq!(apng_delay(Duration::from_millis(40)), (40, 1000));
+        assert_eq!(apng_delay(Duration::from_secs(65)), (65000, 1000));
+        assert_eq!(apng_delay(Duration::from_secs(100)), (10000, 100));
+        assert_eq!(apng_delay(Duration::from_secs(3600)), (36000, 10));
+        assert_eq!(apng_delay(Duration::from_secs(40000)), (40000, 1));
+    }
+
+    /// A root of length 10 carrying a branch of length 8, which carries one of length 6, and
+    /// a branch of length 4: three depths. Every node has its own thickness, so it can be
+    /// told apart in revealed trees.
+    fn forked_tree() -> Tree {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 0.0, 4.0);
+        let branch = tree.add_node(Some(root), 8.0, 0.3, 3.0);
+        tree.add_node(Some(branch), 6.0, 0.1, 2.0);
+        tree.add_node(Some(root), 4.0, -0.5, 1.0);
+        tree
+    }
+
+    fn lengths(tree: &Tree) -> Vec<f64> {
+        tree.nodes.nodes.iter().map(|node| node.length).collect()
+    }
+
+    #[test]
+    fn reveal_grows_the_tree_depth_by_depth() {
+        let tree = forked_tree();
+        assert!(reveal(&tree, 0.0).nodes.nodes.is_empty());
+        assert_eq!(lengths(&reveal(&tree, 1.0 / 6.0)), [5.0]);
+        // Halfway through, the root is complete and both of its branches are half grown.
+        let half = reveal(&tree, 0.5);
+        assert_eq!(lengths(&half), [10.0, 4.0, 2.0]);
+        assert_eq!(half.nodes.nodes[0].children_indices, [1, 2]);
+        assert_eq!(half.nodes.nodes[2].parent_index, Some(0));
+        assert_eq!(
+            format!("{:?}", reveal(&tree, 1.0).nodes.nodes),
+            format!("{:?}", tree.nodes.nodes)
+        );
+    }
+
+    #[test]
+    fn children_only_appear_once_their_parent_is_complete() {
+        let tree = forked_tree();
+        for step in 0..=60 {
+            let shown = reveal(&tree, step as f64 / 60.0);
+            for node in &shown.nodes.nodes {
+                let Some(parent) = node.parent_index.map(|parent| &shown.nodes.nodes[parent])
+                else {
+                    continue;
+                };
+                let full = tree.nodes.nodes.iter().find(|old| old.thickness == parent.thickness);
+                assert_eq!(parent.length, full.unwrap().length, "step {step}");
+            }
+        }
+    }
+
+    #[test]
+    fn easings_start_at_zero_end_at_one_and_never_go_back() {
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(0.0), 0.0, "{easing:?}");
+            assert_eq!(easing.apply(1.0), 1.0, "{easing:?}");
+            assert_eq!(easing.apply(-1.0), 0.0, "{easing:?}");
+            assert_eq!(easing.apply(2.0), 1.0, "{easing:?}");
+            let progress: Vec<f64> = (0..=1000).map(|t| easing.apply(t as f64 / 1000.0)).collect();
+            assert!(progress.windows(2).all(|pair| pair[0] <= pair[1]), "{easing:?}");
+        }
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert!(Easing::EaseIn.apply(0.25) < 0.25);
+        assert!(Easing::EaseOut.apply(0.25) > 0.25);
+    }
+
+    #[test]
+    fn frames_run_from_nothing_to_the_whole_tree() {
+        let animation = GrowthAnimation::new(5, 24.0).with_easing(Easing::EaseOut);
+        assert_eq!(animation.progress(0), 0.0);
+        assert_eq!(animation.progress(4), 1.0);
+        let trees: Vec<Tree> = animation.frame_trees(&forked_tree()).collect();
+        assert_eq!(trees.len(), 5);
+        assert!(trees[0].nodes.nodes.is_empty());
+        assert_eq!(lengths(&trees[4]), lengths(&forked_tree()));
+    }
+
+    #[test]
+    fn timings_are_bounded() {
+        assert!(GrowthAnimation::new(MAX_FRAMES + 1, 24.0).validate().is_err());
+        assert!(GrowthAnimation::new(10, 0.0).validate().is_err());
+        assert!(GrowthAnimation::new(10, f64::NAN).validate().is_err());
+        let held = GrowthAnimation::new(10, 24.0).with_hold(MAX_HOLD + Duration::from_secs(1));
+        assert!(held.validate().is_err());
+        let path = std::env::temp_dir().join("treegen_never_written.gif");
+        let err = held.save_gif(vec![RgbaImage::new(2, 2)], &path).unwrap_err();
+        assert!(matches!(err, AnimationError::Invalid(_)), "{err}");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn long_gif_holds_repeat_the_last_frame() {
+        let animation = GrowthAnimation::new(2, 10.0).with_hold(Duration::from_secs(1000));
+        let frames = vec![RgbaImage::new(2, 2); 2];
+        let dir = std::env::temp_dir().join(format!("treegen_long_hold_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("animation.gif");
+        animation.save_gif(frames, &path).unwrap();
+
+        let decoder = GifDecoder::new(File::open(&path).unwrap()).unwrap();
+        let delays: Vec<Duration> = decoder
+            .into_frames()
+            .map(|frame| Duration::from(frame.unwrap().delay()))
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(delays.len(), 3);
+        assert_eq!(delays.iter().sum::<Duration>(), Duration::from_millis(1_000_200));
+    }
+}
@@ -5,6 +5,9 @@ where
     ColorType: Copy,
 {
     fn initialize(&mut self);
+    /// Marks the primitives drawn after this call as belonging to `layer`. Canvases that
+    /// can group their output (like SVG) use it; raster canvases ignore it.
+    fn set_layer(&mut self, _layer: &str) {}
     fn set_pixel(&mut self, point: Point2<usize>, color: ColorType);
     fn draw_line(&mut self, p1: Point2<f64>, p2: Point2<f64>, color: ColorType) {
         let mut x1 = p1.x;
@@ -42,6 +45,9 @@ where
     }
     fn draw_rotated_rectangle(&mut self, p1: Point2<f64>, p2: Point2<f64>, thickness: f64, color: ColorType) {
         let direction = p2 - p1;
+        if direction.norm() == 0.0 {
+            return;
+        }
         let direction = direction.normalize();
         let normal = Vector2::new(-direction.y, direction.x);
         let half_thickness = thickness / 2.0;
@@ -52,7 +58,6 @@ where
         let corner4 = p2 + normal * half_thickness;
 
         self.draw_filled_polygon(&[corner1, corner2, corner3, corner4], color);
-
     }
 
     fn draw_filled_polygon(&mut self, poly: &[Point2<f64>], color: ColorType);
@@ -0,0 +1,322 @@
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use nalgebra::Point2;
+use palette::{LinSrgba, Srgb, Srgba};
+
+use crate::rendering::canvas::Canvas;
+
+/// Number of coverage samples taken per pixel row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Supersampling {
+    X1,
+    X2,
+    #[default]
+    X4,
+    X8,
+}
+
+impl Supersampling {
+    pub fn samples(&self) -> usize {
+        match self {
+            Supersampling::X1 => 1,
+            Supersampling::X2 => 2,
+            Supersampling::X4 => 4,
+            Supersampling::X8 => 8,
+        }
+    }
+}
+
+/// A raster canvas that fills polygons with anti-aliased edges.
+///
+/// Every pixel row is split into `supersampling` scanlines. Along each scanline the exact
+/// horizontal span of the polygon is measured, so coverage is continuous in x and sampled in
+/// y. Colors are composited with their coverage and alpha in linear light and stored
+/// premultiplied, which keeps thin, overlapping twigs from darkening or fringing.
+#[derive(Debug, Clone)]
+pub struct AntialiasedCanvas {
+    width: u32,
+    height: u32,
+    supersampling: Supersampling,
+    pixels: Vec<LinSrgba<f32>>,
+}
+
+impl AntialiasedCanvas {
+    /// Creates a fully transparent canvas.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            supersampling: Supersampling::default(),
+            pixels: vec![LinSrgba::new(0.0, 0.0, 0.0, 0.0); width as usize * height as usize],
+        }
+    }
+
+    pub fn with_supersampling(mut self, supersampling: Supersampling) -> Self {
+        self.supersampling = supersampling;
+        self
+    }
+
+    pub fn with_background(mut self, color: Srgba<u8>) -> Self {
+        self.fill(color);
+        self
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn fill(&mut self, color: Srgba<u8>) {
+        let color = premultiplied_linear(color);
+        self.pixels.iter_mut().for_each(|pixel| *pixel = color);
+    }
+
+    /// Converts the canvas to 8-bit sRGB, dropping alpha. Transparent areas come out black.
+    pub fn to_rgb_image(&self) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            let pixel = self.pixels[self.offset(x as usize, y as usize)];
+            let color: Srgb<u8> = Srgb::from_linear(pixel.color);
+            Rgb([color.red, color.green, color.blue])
+        })
+    }
+
+    /// Converts the canvas to 8-bit sRGB with straight (non-premultiplied) alpha.
+    pub fn to_rgba_image(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let pixel = self.pixels[self.offset(x as usize, y as usize)];
+            if pixel.alpha <= 0.0 {
+                return Rgba([0, 0, 0, 0]);
+            }
+            let straight = pixel.color / pixel.alpha;
+            let color: Srgb<u8> = Srgb::from_linear(straight);
+            let alpha = (pixel.alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+            Rgba([color.red, color.green, color.blue, alpha])
+        })
+    }
+
+    fn offset(&self, x: usize, y: usize) -> usize {
+        y * self.width as usize + x
+    }
+
+    fn blend(&mut self, x: usize, y: usize, color: LinSrgba<f32>, coverage: f32) {
+        let offset = self.offset(x, y);
+        let source = color * coverage.clamp(0.0, 1.0);
+        let destination = self.pixels[offset];
+        self.pixels[offset] = source + destination * (1.0 - source.alpha);
+    }
+
+    fn fill_polygon(&mut self, poly: &[Point2<f64>], color: LinSrgba<f32>) {
+        if poly.len() < 3 || self.width == 0 || self.height == 0 {
+            return;
+        }
+        let (mut min_x, mut min_y, mut max_x, mut max_y) =
+            (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for point in poly {
+            if !point.x.is_finite() || !point.y.is_finite() {
+                return;
+            }
+            min_x = min_x.min(point.x);
+            min_y = min_y.min(point.y);
+            max_x = max_x.max(point.x);
+            max_y = max_y.max(point.y);
+        }
+
+        let row_start = min_y.floor().max(0.0) as usize;
+        let row_end = (max_y.ceil().max(0.0) as usize).min(self.height as usize);
+        let column_start = min_x.floor().max(0.0) as usize;
+        let column_end = (max_x.ceil().max(0.0) as usize).min(self.width as usize);
+        if row_start >= row_end || column_start >= column_end {
+            return;
+        }
+
+        let samples = self.supersampling.samples();
+        let sample_weight = 1.0 / samples as f64;
+        let mut coverage = vec![0.0f64; column_end - column_start];
+        let mut crossings: Vec<(f64, i32)> = Vec::new();
+
+        for row in row_start..row_end {
+            coverage.iter_mut().for_each(|value| *value = 0.0);
+
+            for sample in 0..samples {
+                let scan_y = row as f64 + (sample as f64 + 0.5) * sample_weight;
+                crossings.clear();
+                for (i, start) in poly.iter().enumerate() {
+                    let end = poly[(i + 1) % poly.len()];
+                    if (start.y <= scan_y) == (end.y <= scan_y) {
+                        continue;
+                    }
+                    let t = (scan_y - start.y) / (end.y - start.y);
+                    let winding = if end.y > start.y { 1 } else { -1 };
+                    crossings.push((start.x + t * (end.x - start.x), winding));
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                // Non-zero winding: accumulate the spans where the winding number is not zero.
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    if winding == 0 {
+                        continue;
+                    }
+                    let span_start = pair[0].0.max(column_start as f64);
+                    let span_end = pair[1].0.min(column_end as f64);
+                    if span_start >= span_end {
+                        continue;
+                    }
+                    let first = span_start.floor() as usize;
+                    let last = (span_end.ceil() as usize).min(column_end);
+                    for column in first..last {
+                        let overlap =
+                            span_end.min(column as f64 + 1.0) - span_start.max(column as f64);
+                        coverage[column - column_start] += overlap.max(0.0) * sample_weight;
+                    }
+                }
+            }
+
+            for (i, &value) in coverage.iter().enumerate() {
+                if value > 0.0 {
+                    self.blend(column_start + i, row, color, value as f32);
+                }
+            }
+        }
+    }
+
+    fn draw_pixel(&mut self, point: Point2<usize>, color: LinSrgba<f32>) {
+        if point.x < self.width as usize && point.y < self.height as usize {
+            self.blend(point.x, point.y, color, 1.0);
+        }
+    }
+
+    fn draw_segment(&mut self, p1: Point2<f64>, p2: Point2<f64>, color: LinSrgba<f32>) {
+        let direction = p2 - p1;
+        if direction.norm() == 0.0 {
+            return;
+        }
+        let direction = direction.normalize();
+        let normal = nalgebra::Vector2::new(-direction.y, direction.x) * 0.5;
+        self.fill_polygon(&[p1 + normal, p2 + normal, p2 - normal, p1 - normal], color);
+    }
+}
+
+fn premultiplied_linear(color: Srgba<u8>) -> LinSrgba<f32> {
+    let linear: LinSrgba<f32> = color.into_format::<f32, f32>().into_linear();
+    LinSrgba::new(
+        linear.red * linear.alpha,
+        linear.green * linear.alpha,
+        linear.blue * linear.alpha,
+        linear.alpha,
+    )
+}
+
+impl Canvas<Srgb<u8>> for AntialiasedCanvas {
+    fn initialize(&mut self) {}
+
+    fn set_pixel(&mut self, point: Point2<usize>, color: Srgb<u8>) {
+        self.draw_pixel(point, premultiplied_linear(color.into()));
+    }
+
+    fn draw_line(&mut self, p1: Point2<f64>, p2: Point2<f64>, color: Srgb<u8>) {
+        self.draw_segment(p1, p2, premultiplied_linear(color.into()));
+    }
+
+    fn draw_filled_polygon(&mut self, poly: &[Point2<f64>], color: Srgb<u8>) {
+        self.fill_polygon(poly, premultiplied_linear(color.into()));
+    }
+}
+
+impl Canvas<Srgba<u8>> for AntialiasedCanvas {
+    fn initialize(&mut self) {}
+
+    fn set_pixel(&mut self, point: Point2<usize>, color: Srgba<u8>) {
+        self.draw_pixel(point, premultiplied_linear(color));
+    }
+
+    fn draw_line(&mut self, p1: Point2<f64>, p2: Point2<f64>, color: Srgba<u8>) {
+        self.draw_segment(p1, p2, premultiplied_linear(color));
+    }
+
+    fn draw_filled_polygon(&mut self, poly: &[Point2<f64>], color: Srgba<u8>) {
+        self.fill_polygon(poly, premultiplied_linear(color));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Srgba<u8> = Srgba::new(255, 255, 255, 255);
+
+    fn alpha_at(canvas: &AntialiasedCanvas, x: u32, y: u32) -> u8 {
+        canvas.to_rgba_image().get_pixel(x, y)[3]
+    }
+
+    /// Sum of the alpha of every pixel, in pixels of full coverage.
+    fn total_coverage(canvas: &AntialiasedCanvas) -> f64 {
+        canvas.to_rgba_image().pixels().map(|pixel| pixel[3] as f64 / 255.0).sum()
+    }
+
+    fn rectangle(x0: f64, y0: f64, x1: f64, y1: f64) -> [Point2<f64>; 4] {
+        [
+            Point2::new(x0, y0),
+            Point2::new(x1, y0),
+            Point2::new(x1, y1),
+            Point2::new(x0, y1),
+        ]
+    }
+
+    #[test]
+    fn edges_through_a_pixel_cover_it_partly() {
+        let mut canvas = AntialiasedCanvas::new(10, 10);
+        canvas.draw_filled_polygon(&rectangle(1.0, 1.0, 5.5, 5.0), WHITE);
+        canvas.draw_filled_polygon(&rectangle(7.0, 1.0, 7.25, 5.0), WHITE);
+        assert_eq!(alpha_at(&canvas, 4, 2), 255);
+        assert_eq!(alpha_at(&canvas, 5, 2), 128);
+        assert_eq!(alpha_at(&canvas, 6, 2), 0);
+        // A sliver thinner than a pixel still shows, faintly.
+        assert_eq!(alpha_at(&canvas, 7, 2), 64);
+    }
+
+    #[test]
+    fn supersampling_keeps_the_covered_area() {
+        let triangle: [Point2<f64>; 3] =
+            [Point2::new(2.3, 3.1), Point2::new(27.6, 8.4), Point2::new(9.2, 26.7)];
+        let area = ((triangle[1] - triangle[0]).perp(&(triangle[2] - triangle[0])) / 2.0).abs();
+        for supersampling in
+            [Supersampling::X1, Supersampling::X2, Supersampling::X4, Supersampling::X8]
+        {
+            let mut canvas = AntialiasedCanvas::new(32, 32).with_supersampling(supersampling);
+            canvas.draw_filled_polygon(&triangle, WHITE);
+            let coverage = total_coverage(&canvas);
+            let error = (coverage - area).abs();
+            assert!(error < 0.01 * area, "{supersampling:?}: {coverage} instead of {area}");
+        }
+    }
+
+    #[test]
+    fn translucent_colors_blend_over_the_background_in_linear_light() {
+        let mut canvas = AntialiasedCanvas::new(4, 4).with_background(WHITE);
+        canvas.draw_filled_polygon(&rectangle(0.0, 0.0, 4.0, 2.0), Srgba::new(0, 0, 0, 128));
+        // Half of a pixel covered by the same color lets three quarters of the white through.
+        canvas.draw_filled_polygon(&rectangle(0.0, 2.0, 4.0, 2.5), Srgba::new(0, 0, 0, 128));
+
+        let expected = |transmitted: f32| {
+            let white = palette::LinSrgb::new(1.0, 1.0, 1.0);
+            let color: Srgb<u8> = Srgb::from_linear(white * transmitted);
+            Rgb([color.red, color.green, color.blue])
+        };
+        let image = canvas.to_rgb_image();
+        assert_eq!(*image.get_pixel(1, 1), expected(1.0 - 128.0 / 255.0));
+        assert_eq!(*image.get_pixel(1, 2), expected(1.0 - 64.0 / 255.0));
+        assert_eq!(*image.get_pixel(1, 3), expected(1.0));
+    }
+
+    #[test]
+    fn transparent_canvases_keep_straight_alpha() {
+        let mut canvas = AntialiasedCanvas::new(2, 2);
+        canvas.draw_filled_polygon(&rectangle(0.0, 0.0, 2.0, 2.0), Srgba::new(255, 0, 0, 128));
+        assert_eq!(*canvas.to_rgba_image().get_pixel(0, 0), Rgba([255, 0, 0, 128]));
+        assert_eq!(*canvas.to_rgb_image().get_pixel(0, 0), Rgb([188, 0, 0]));
+    }
+}
@@ -9,12 +9,28 @@ impl Canvas<Srgb<u8>> for RgbImage {
     }
 
     fn set_pixel(&mut self, point: nalgebra::Point2<usize>, color: Srgb<u8>) {
+        if point.x >= self.width() as usize || point.y >= self.height() as usize {
+            return;
+        }
         let pixel = Rgb([color.red, color.green, color.blue]);
         self.put_pixel(point.x as u32, point.y as u32, pixel);
     }
 
     fn draw_filled_polygon(&mut self, poly: &[nalgebra::Point2<f64>], color: Srgb<u8>) {
-        let binding = poly.iter().map(|p| Point::new(p.x as i32, p.y as i32)).collect::<Vec<_>>();
+        let mut binding = poly.iter().map(|p| Point::new(p.x as i32, p.y as i32)).collect::<Vec<_>>();
+        // imageproc panics when the polygon is explicitly closed, which small shapes can end up
+        // being once their vertices are truncated to whole pixels.
+        binding.dedup();
+        while binding.len() > 1 && binding.first() == binding.last() {
+            binding.pop();
+        }
+        if binding.len() == 1 {
+            let point = binding[0];
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(nalgebra::Point2::new(point.x as usize, point.y as usize), color);
+            }
+            return;
+        }
         let poly = binding.as_slice();
         let color = Rgb([color.red, color.green, color.blue]);
         imageproc::drawing::draw_polygon_mut(self, poly, color);
@@ -1,4 +1,10 @@
+mod antialiased_canvas;
 pub mod image_canvas;
 mod polygon_renderer;
+mod svg_canvas;
+mod tapered_renderer;
 
+pub use antialiased_canvas::{AntialiasedCanvas, Supersampling};
 pub use polygon_renderer::PolygonRenderer;
+pub use svg_canvas::SvgCanvas;
+pub use tapered_renderer::{JointStyle, TaperedRenderer};
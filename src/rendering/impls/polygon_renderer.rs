@@ -1,8 +1,20 @@
 use palette::Srgb;
 
-use crate::rendering::renderer::Renderer;
+use crate::{
+    rendering::{renderer::Renderer, viewport::Viewport},
+    skeleton::leaf::Leaf,
+};
 
-pub struct PolygonRenderer;
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PolygonRenderer {
+    pub viewport: Viewport,
+}
+
+impl PolygonRenderer {
+    pub fn new(viewport: Viewport) -> Self {
+        Self { viewport }
+    }
+}
 
 impl Renderer<Srgb<u8>> for PolygonRenderer {
     fn initialize(&mut self, _canvas: &mut dyn crate::rendering::canvas::Canvas<Srgb<u8>>) {}
@@ -10,16 +22,27 @@ impl Renderer<Srgb<u8>> for PolygonRenderer {
     fn render_node(
         &self,
         canvas: &mut dyn crate::rendering::canvas::Canvas<Srgb<u8>>,
-        node: &crate::skeleton::node::Node,
+        graph: &crate::skeleton::node::NodeGraph,
+        index: usize,
     ) {
+        let node = &graph.nodes[index];
         canvas.draw_rotated_rectangle(
-            node.point,
-            node.next_point(),
-            node.thickness,
+            self.viewport.to_screen(node.point),
+            self.viewport.to_screen(node.next_point()),
+            self.viewport.scale_length(node.thickness),
             Self::get_color_for_node(node),
         )
     }
 
+    fn render_leaf(&self, canvas: &mut dyn crate::rendering::canvas::Canvas<Srgb<u8>>, leaf: &Leaf) {
+        let outline: Vec<_> = leaf
+            .outline()
+            .into_iter()
+            .map(|point| self.viewport.to_screen(point))
+            .collect();
+        canvas.draw_filled_polygon(&outline, leaf.color);
+    }
+
     fn get_color_for_node(node: &crate::skeleton::node::Node) -> Srgb<u8> {
         if let Some(color) = node.color {
             color
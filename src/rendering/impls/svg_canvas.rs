@@ -0,0 +1,314 @@
+use std::{fmt::Write, fs, io, path::Path};
+
+use nalgebra::Point2;
+use palette::Srgb;
+
+use crate::rendering::canvas::Canvas;
+
+const DEFAULT_LAYER: &str = "default";
+
+/// A resolution-independent canvas that records primitives as SVG elements.
+///
+/// Segments drawn through [`Canvas::draw_rotated_rectangle`] become stroked `<path>`
+/// elements with the segment's thickness as `stroke-width`. Primitives are grouped by the
+/// layer set through [`Canvas::set_layer`], and every group is marked as an Inkscape layer
+/// so it can be edited on its own in vector tools. Group ids are the layer names with every
+/// character that is not allowed in an id replaced; labels keep the names as given.
+#[derive(Debug, Clone)]
+pub struct SvgCanvas {
+    width: f64,
+    height: f64,
+    background: Option<Srgb<u8>>,
+    layers: Vec<Layer>,
+    current_layer: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    id: String,
+    label: String,
+    elements: Vec<String>,
+}
+
+impl Layer {
+    fn new(name: &str) -> Self {
+        Self {
+            id: sanitize_id(name),
+            label: name.to_string(),
+            elements: Vec::new(),
+        }
+    }
+}
+
+impl SvgCanvas {
+    pub fn new(width: f64, height: f64) -> Self {
+        Self {
+            width,
+            height,
+            background: None,
+            layers: vec![Layer::new(DEFAULT_LAYER)],
+            current_layer: 0,
+        }
+    }
+
+    pub fn with_background(mut self, color: Srgb<u8>) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    pub fn to_svg_string(&self) -> String {
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = format_number(self.width),
+            h = format_number(self.height),
+        );
+        if let Some(background) = self.background {
+            let _ = writeln!(
+                svg,
+                r#"  <rect width="100%" height="100%" fill="{}"/>"#,
+                hex_color(background)
+            );
+        }
+        for layer in self.layers.iter().filter(|layer| !layer.elements.is_empty()) {
+            let _ = writeln!(
+                svg,
+                r#"  <g id="{}" inkscape:groupmode="layer" inkscape:label="{}" stroke-linecap="butt">"#,
+                layer.id,
+                escape_attribute(&layer.label)
+            );
+            for element in &layer.elements {
+                let _ = writeln!(svg, "    {element}");
+            }
+            svg.push_str("  </g>\n");
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_svg_string())
+    }
+
+    fn push(&mut self, element: String) {
+        self.layers[self.current_layer].elements.push(element);
+    }
+}
+
+impl Canvas<Srgb<u8>> for SvgCanvas {
+    fn initialize(&mut self) {
+        self.layers = vec![Layer::new(DEFAULT_LAYER)];
+        self.current_layer = 0;
+    }
+
+    /// Layers whose names only differ in characters not allowed in ids share a group, so
+    /// that ids stay unique; the group keeps the label of the first of them.
+    fn set_layer(&mut self, layer: &str) {
+        let id = sanitize_id(layer);
+        self.current_layer = match self.layers.iter().position(|existing| existing.id == id) {
+            Some(index) => index,
+            None => {
+                self.layers.push(Layer::new(layer));
+                self.layers.len() - 1
+            }
+        };
+    }
+
+    fn set_pixel(&mut self, point: Point2<usize>, color: Srgb<u8>) {
+        self.push(format!(
+            r#"<rect x="{}" y="{}" width="1" height="1" fill="{}"/>"#,
+            point.x,
+            point.y,
+            hex_color(color)
+        ));
+    }
+
+    fn draw_line(&mut self, p1: Point2<f64>, p2: Point2<f64>, color: Srgb<u8>) {
+        self.draw_rotated_rectangle(p1, p2, 1.0, color);
+    }
+
+    fn draw_rotated_rectangle(
+        &mut self,
+        p1: Point2<f64>,
+        p2: Point2<f64>,
+        thickness: f64,
+        color: Srgb<u8>,
+    ) {
+        self.push(format!(
+            r#"<path d="M{} {} L{} {}" fill="none" stroke="{}" stroke-width="{}"/>"#,
+            format_number(p1.x),
+            format_number(p1.y),
+            format_number(p2.x),
+            format_number(p2.y),
+            hex_color(color),
+            format_number(thickness)
+        ));
+    }
+
+    fn draw_filled_polygon(&mut self, poly: &[Point2<f64>], color: Srgb<u8>) {
+        let Some((first, rest)) = poly.split_first() else {
+            return;
+        };
+        let mut path = format!("M{} {}", format_number(first.x), format_number(first.y));
+        for point in rest {
+            let _ = write!(path, " L{} {}", format_number(point.x), format_number(point.y));
+        }
+        path.push_str(" Z");
+        self.push(format!(r#"<path d="{path}" fill="{}"/>"#, hex_color(color)));
+    }
+}
+
+fn hex_color(color: Srgb<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+/// Formats with at most three decimals and without trailing zeros to keep files small.
+fn format_number(value: f64) -> String {
+    let formatted = format!("{value:.3}");
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" | "" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+/// Escapes the characters that may not appear as they are in a double-quoted XML attribute.
+fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn sanitize_id(layer: &str) -> String {
+    let id: String = layer
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if id.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        id
+    } else {
+        format!("layer-{id}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use roxmltree::Document;
+
+    use super::*;
+    use crate::{
+        rendering::{renderer::Renderer, viewport::Viewport, PolygonRenderer},
+        skeleton::tree::Tree,
+    };
+
+    const INKSCAPE: &str = "http://www.inkscape.org/namespaces/inkscape";
+
+    /// A root carrying two branches, one of which carries another: three depths, with a
+    /// color and thickness of its own for every node.
+    fn colored_tree() -> Tree {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 0.0, 4.0);
+        let branch = tree.add_node(Some(root), 8.0, 0.4, 2.5);
+        tree.add_node(Some(branch), 6.0, 0.2, 1.0);
+        tree.add_node(Some(root), 5.0, -0.6, 2.0);
+        let colors = [(120, 80, 40), (0, 128, 0), (255, 0, 17), (10, 20, 30)];
+        for (node, (red, green, blue)) in tree.nodes.nodes.iter_mut().zip(colors) {
+            node.set_color(Srgb::new(red, green, blue));
+        }
+        tree
+    }
+
+    fn render(tree: &Tree, viewport: Viewport) -> String {
+        let mut canvas = SvgCanvas::new(200.0, 100.0).with_background(Srgb::new(255, 255, 255));
+        PolygonRenderer::new(viewport).render_tree(&mut canvas, tree);
+        canvas.to_svg_string()
+    }
+
+    #[test]
+    fn output_is_well_formed_svg() {
+        let svg = render(&colored_tree(), Viewport::identity());
+        let document = Document::parse(&svg).unwrap();
+        let root = document.root_element();
+        assert_eq!(root.tag_name().name(), "svg");
+        assert_eq!(root.tag_name().namespace(), Some("http://www.w3.org/2000/svg"));
+        assert_eq!(root.attribute("viewBox"), Some("0 0 200 100"));
+        let background = root.first_element_child().unwrap();
+        assert_eq!(background.tag_name().name(), "rect");
+        assert_eq!(background.attribute("fill"), Some("#ffffff"));
+    }
+
+    #[test]
+    fn every_segment_is_one_stroked_path() {
+        let tree = colored_tree();
+        let viewport = Viewport::new(Point2::new(-50.0, -30.0), 2.0);
+        let svg = render(&tree, viewport);
+        let document = Document::parse(&svg).unwrap();
+        let paths: Vec<_> =
+            document.descendants().filter(|node| node.has_tag_name("path")).collect();
+        assert_eq!(paths.len(), tree.nodes.nodes.len());
+        for node in &tree.nodes.nodes {
+            let start = viewport.to_screen(node.point);
+            let end = viewport.to_screen(node.next_point());
+            let d = format!(
+                "M{} {} L{} {}",
+                format_number(start.x),
+                format_number(start.y),
+                format_number(end.x),
+                format_number(end.y)
+            );
+            let path = paths.iter().find(|path| path.attribute("d") == Some(d.as_str())).unwrap();
+            let color = hex_color(node.color.unwrap());
+            assert_eq!(path.attribute("stroke"), Some(color.as_str()));
+            let width: f64 = path.attribute("stroke-width").unwrap().parse().unwrap();
+            assert_eq!(width, node.thickness * 2.0);
+        }
+        assert!(svg.contains(r##"stroke="#ff0011" stroke-width="2""##));
+    }
+
+    #[test]
+    fn segments_are_grouped_by_depth() {
+        let svg = render(&colored_tree(), Viewport::identity());
+        let document = Document::parse(&svg).unwrap();
+        let groups: Vec<(&str, usize)> = document
+            .descendants()
+            .filter(|node| node.has_tag_name("g"))
+            .map(|group| {
+                assert_eq!(group.attribute((INKSCAPE, "groupmode")), Some("layer"));
+                assert_eq!(group.attribute((INKSCAPE, "label")), group.attribute("id"));
+                let elements = group.children().filter(|child| child.is_element()).count();
+                (group.attribute("id").unwrap(), elements)
+            })
+            .collect();
+        // The default layer stays empty and is left out.
+        assert_eq!(groups, [("depth-0", 1), ("depth-1", 2), ("depth-2", 1)]);
+    }
+
+    #[test]
+    fn layer_names_are_escaped() {
+        let mut canvas = SvgCanvas::new(10.0, 10.0);
+        let name = r#"<bark> & "moss" 'n' lichen"#;
+        canvas.set_layer(name);
+        canvas.draw_line(Point2::new(0.0, 0.0), Point2::new(5.0, 5.0), Srgb::new(0, 0, 0));
+        canvas.set_layer("2nd layer");
+        canvas.draw_line(Point2::new(5.0, 5.0), Point2::new(9.0, 9.0), Srgb::new(0, 0, 0));
+
+        let svg = canvas.to_svg_string();
+        let document = Document::parse(&svg).unwrap();
+        let groups: Vec<_> = document.descendants().filter(|node| node.has_tag_name("g")).collect();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].attribute("id"), Some("layer-_bark_____moss___n__lichen"));
+        assert_eq!(groups[0].attribute((INKSCAPE, "label")), Some(name));
+        assert_eq!(groups[1].attribute("id"), Some("layer-2nd_layer"));
+        assert_eq!(groups[1].attribute((INKSCAPE, "label")), Some("2nd layer"));
+    }
+}
@@ -0,0 +1,368 @@
+use std::f64::consts::TAU;
+
+use nalgebra::Vector2;
+use palette::Srgb;
+
+use crate::{
+    rendering::{canvas::Canvas, renderer::Renderer, viewport::Viewport},
+    skeleton::{
+        leaf::Leaf,
+        node::{Node, NodeGraph, Point},
+        tree::Tree,
+    },
+    utils::geometry::angle_between,
+};
+
+use super::PolygonRenderer;
+
+/// How the gap between a segment and the segment it continues from is filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JointStyle {
+    /// A disk as wide as the joint is drawn over every joint.
+    #[default]
+    Round,
+    /// Segment ends are cut along the bisector of the two segments so their edges meet.
+    Miter,
+}
+
+/// Renders every node as a trapezoid that tapers from its parent's thickness down to its own,
+/// so thickness changes smoothly along a branch instead of in steps.
+///
+/// Only the child that continues its parent's branch (the one bending the least) tapers from
+/// the parent's thickness; side branches start at their own.
+///
+/// With `merge_chains` set, each run of nodes from a branching point to a tip is drawn as a
+/// single outline polygon in the color of its first node.
+#[derive(Debug, Clone, Copy)]
+pub struct TaperedRenderer {
+    pub viewport: Viewport,
+    pub joint: JointStyle,
+    pub merge_chains: bool,
+    /// Mitered corners are never pushed further out than this many half-widths.
+    pub miter_limit: f64,
+}
+
+impl TaperedRenderer {
+    pub fn new(viewport: Viewport) -> Self {
+        Self {
+            viewport,
+            joint: JointStyle::default(),
+            merge_chains: false,
+            miter_limit: 4.0,
+        }
+    }
+
+    pub fn with_joint(mut self, joint: JointStyle) -> Self {
+        self.joint = joint;
+        self
+    }
+
+    pub fn with_merged_chains(mut self, merge_chains: bool) -> Self {
+        self.merge_chains = merge_chains;
+        self
+    }
+
+    /// Returns the offset direction for the corner shared by a segment at `incoming` and the
+    /// following segment at `outgoing`, scaled so both edges stay their full width.
+    fn miter(&self, incoming: f64, outgoing: f64) -> Vector2<f64> {
+        let incoming_normal = normal(incoming);
+        let outgoing_normal = normal(outgoing);
+        let sum = incoming_normal + outgoing_normal;
+        if sum.norm() < 1e-9 {
+            return outgoing_normal;
+        }
+        let bisector = sum.normalize();
+        let scale = (1.0 / bisector.dot(&outgoing_normal)).min(self.miter_limit);
+        bisector * scale
+    }
+
+    /// A node continuing its parent's branch starts as thick as the parent; side branches
+    /// start at their own thickness so they do not inherit the width of the trunk.
+    fn start_width(&self, graph: &NodeGraph, index: usize) -> f64 {
+        let node = &graph.nodes[index];
+        let thickness = match node.parent_index {
+            Some(parent_index) if continuation(graph, parent_index) == Some(index) => {
+                graph.nodes[parent_index].thickness
+            }
+            Some(parent_index) => node.thickness.min(graph.nodes[parent_index].thickness),
+            None => node.thickness,
+        };
+        self.viewport.scale_length(thickness)
+    }
+
+    fn render_chain(&self, canvas: &mut dyn Canvas<Srgb<u8>>, graph: &NodeGraph, chain: &[usize]) {
+        let first = &graph.nodes[chain[0]];
+        let color = Self::get_color_for_node(first);
+
+        let mut centers = vec![self.viewport.to_screen(first.point)];
+        let mut widths = vec![self.start_width(graph, chain[0])];
+        let mut offsets = vec![match first.parent_index {
+            Some(parent_index) if self.joint == JointStyle::Miter => {
+                self.miter(graph.nodes[parent_index].angle, first.angle)
+            }
+            _ => normal(first.angle),
+        }];
+        for (position, &index) in chain.iter().enumerate() {
+            let node = &graph.nodes[index];
+            centers.push(self.viewport.to_screen(node.next_point()));
+            widths.push(self.viewport.scale_length(node.thickness));
+            offsets.push(match chain.get(position + 1) {
+                Some(&next) => self.miter(node.angle, graph.nodes[next].angle),
+                None => normal(node.angle),
+            });
+        }
+
+        let left = centers
+            .iter()
+            .zip(&widths)
+            .zip(&offsets)
+            .map(|((center, width), offset)| center + offset * (width / 2.0));
+        let right = centers
+            .iter()
+            .zip(&widths)
+            .zip(&offsets)
+            .map(|((center, width), offset)| center - offset * (width / 2.0))
+            .rev();
+        let outline: Vec<Point> = left.chain(right).collect();
+        canvas.draw_filled_polygon(&outline, color);
+
+        if self.joint == JointStyle::Round && first.parent_index.is_some() {
+            canvas.draw_filled_polygon(&disk(centers[0], widths[0] / 2.0), color);
+        }
+    }
+}
+
+impl Renderer<Srgb<u8>> for TaperedRenderer {
+    fn initialize(&mut self, _canvas: &mut dyn Canvas<Srgb<u8>>) {}
+
+    fn render_node(&self, canvas: &mut dyn Canvas<Srgb<u8>>, graph: &NodeGraph, index: usize) {
+        let node = &graph.nodes[index];
+        let color = Self::get_color_for_node(node);
+        let start = self.viewport.to_screen(node.point);
+        let end = self.viewport.to_screen(node.next_point());
+        let start_width = self.start_width(graph, index);
+        let end_width = self.viewport.scale_length(node.thickness);
+
+        let (start_offset, end_offset) = match self.joint {
+            JointStyle::Round => (normal(node.angle), normal(node.angle)),
+            JointStyle::Miter => (
+                node.parent_index.map_or(normal(node.angle), |parent_index| {
+                    self.miter(graph.nodes[parent_index].angle, node.angle)
+                }),
+                continuation(graph, index).map_or(normal(node.angle), |child_index| {
+                    self.miter(node.angle, graph.nodes[child_index].angle)
+                }),
+            ),
+        };
+
+        canvas.draw_filled_polygon(
+            &[
+                start + start_offset * (start_width / 2.0),
+                end + end_offset * (end_width / 2.0),
+                end - end_offset * (end_width / 2.0),
+                start - start_offset * (start_width / 2.0),
+            ],
+            color,
+        );
+
+        if self.joint == JointStyle::Round && !node.children_indices.is_empty() {
+            canvas.draw_filled_polygon(&disk(end, end_width / 2.0), color);
+        }
+    }
+
+    fn render_tree<T: Canvas<Srgb<u8>>>(&mut self, canvas: &mut T, tree: &Tree) {
+        self.initialize(canvas);
+        let graph = &tree.nodes;
+        let depths = graph.depths();
+
+        if !self.merge_chains {
+            for (index, depth) in depths.into_iter().enumerate() {
+                canvas.set_layer(&format!("depth-{depth}"));
+                self.render_node(canvas, graph, index);
+            }
+            self.render_leaves(canvas, tree);
+            return;
+        }
+
+        // A chain starts wherever a node is not the continuation of its parent and follows
+        // continuations until it reaches a tip, so every node belongs to exactly one chain.
+        for (index, node) in graph.nodes.iter().enumerate() {
+            let starts_chain = node
+                .parent_index
+                .is_none_or(|parent_index| continuation(graph, parent_index) != Some(index));
+            if !starts_chain {
+                continue;
+            }
+
+            let mut chain = vec![index];
+            while let Some(next) = continuation(graph, *chain.last().unwrap()) {
+                chain.push(next);
+            }
+            canvas.set_layer(&format!("depth-{}", depths[index]));
+            self.render_chain(canvas, graph, &chain);
+        }
+        self.render_leaves(canvas, tree);
+    }
+
+    fn render_leaf(&self, canvas: &mut dyn Canvas<Srgb<u8>>, leaf: &Leaf) {
+        PolygonRenderer::new(self.viewport).render_leaf(canvas, leaf)
+    }
+
+    fn get_color_for_node(node: &Node) -> Srgb<u8> {
+        PolygonRenderer::get_color_for_node(node)
+    }
+}
+
+/// Returns the child that continues the branch of `graph.nodes[index]`, which is the child
+/// that bends the least. Bends are measured the short way around, so a child at -pi + 0.1
+/// barely bends away from a parent at pi - 0.1.
+fn continuation(graph: &NodeGraph, index: usize) -> Option<usize> {
+    let node = &graph.nodes[index];
+    node.children_indices.iter().copied().min_by(|&a, &b| {
+        let bend_a = angle_between(node.angle, graph.nodes[a].angle);
+        let bend_b = angle_between(node.angle, graph.nodes[b].angle);
+        bend_a.total_cmp(&bend_b)
+    })
+}
+
+/// Unit normal of a segment at `angle`, pointing to its left in screen space.
+fn normal(angle: f64) -> Vector2<f64> {
+    let direction = Vector2::new(angle.sin(), -angle.cos());
+    Vector2::new(-direction.y, direction.x)
+}
+
+fn disk(center: Point, radius: f64) -> Vec<Point> {
+    let segments = ((radius.max(0.0).sqrt() * 6.0) as usize).clamp(8, 64);
+    (0..segments)
+        .map(|i| {
+            let theta = TAU * i as f64 / segments as f64;
+            Point::new(center.x + radius * theta.cos(), center.y + radius * theta.sin())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+
+    /// Records the outlines drawn on it.
+    #[derive(Default)]
+    struct Outlines(Vec<Vec<Point>>);
+
+    impl Canvas<Srgb<u8>> for Outlines {
+        fn initialize(&mut self) {}
+
+        fn set_pixel(&mut self, _point: Point2<usize>, _color: Srgb<u8>) {}
+
+        fn draw_filled_polygon(&mut self, poly: &[Point], _color: Srgb<u8>) {
+            self.0.push(poly.to_vec());
+        }
+    }
+
+    fn render(mut renderer: TaperedRenderer, tree: &Tree) -> Vec<Vec<Point>> {
+        let mut canvas = Outlines::default();
+        renderer.render_tree(&mut canvas, tree);
+        canvas.0
+    }
+
+    fn assert_close(a: Point, b: Point) {
+        assert!((a - b).norm() < 1e-9, "{a} != {b}");
+    }
+
+    /// A root of thickness 4 continued by a node of thickness 2 that bends by `bend`.
+    fn bent_chain(bend: f64) -> Tree {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 0.0, 4.0);
+        tree.add_node(Some(root), 10.0, bend, 2.0);
+        tree
+    }
+
+    #[test]
+    fn continuations_are_found_across_the_angle_seam() {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 3.1, 4.0);
+        tree.add_node(Some(root), 10.0, 2.6, 2.0);
+        let across = tree.add_node(Some(root), 10.0, -3.1, 2.0);
+        assert_eq!(continuation(&tree.nodes, root), Some(across));
+    }
+
+    #[test]
+    fn continuations_taper_from_the_parent_thickness_and_side_branches_do_not() {
+        let mut tree = bent_chain(0.0);
+        tree.add_node(Some(0), 5.0, 1.0, 3.0);
+        let outlines = render(TaperedRenderer::new(Viewport::identity()), &tree);
+        // Round joints draw a disk over the root's end; the segments are the quadrilaterals.
+        let widths: Vec<(f64, f64)> = outlines
+            .iter()
+            .filter(|outline| outline.len() == 4)
+            .map(|quad| ((quad[0] - quad[3]).norm(), (quad[1] - quad[2]).norm()))
+            .collect();
+        assert_eq!(widths.len(), 3);
+        for ((start, end), expected) in widths.into_iter().zip([(4.0, 4.0), (4.0, 2.0), (3.0, 3.0)])
+        {
+            assert!((start - expected.0).abs() < 1e-9, "{start} != {}", expected.0);
+            assert!((end - expected.1).abs() < 1e-9, "{end} != {}", expected.1);
+        }
+    }
+
+    #[test]
+    fn mitered_segments_share_their_corners() {
+        let tree = bent_chain(0.6);
+        let renderer = TaperedRenderer::new(Viewport::identity()).with_joint(JointStyle::Miter);
+        let outlines = render(renderer, &tree);
+        assert_eq!(outlines.len(), 2);
+        let (parent, child) = (&outlines[0], &outlines[1]);
+        assert_close(parent[1], child[0]);
+        assert_close(parent[2], child[3]);
+    }
+
+    #[test]
+    fn round_joints_cover_both_segment_ends() {
+        let tree = bent_chain(0.6);
+        let joint = tree.nodes.nodes[0].next_point();
+        let outlines = render(TaperedRenderer::new(Viewport::identity()), &tree);
+        // The root, the disk over its end and the child.
+        assert_eq!(outlines.len(), 3);
+        let (parent, disk, child) = (&outlines[0], &outlines[1], &outlines[2]);
+        for corner in [parent[1], parent[2], child[0], child[3]] {
+            assert!(((corner - joint).norm() - 2.0).abs() < 1e-9, "{corner}");
+        }
+        for point in disk {
+            assert!(((point - joint).norm() - 2.0).abs() < 1e-9, "{point}");
+        }
+    }
+
+    #[test]
+    fn merged_chains_follow_the_corners_of_their_segments() {
+        let mut tree = bent_chain(0.3);
+        tree.add_node(Some(1), 8.0, 0.5, 1.0);
+        tree.add_node(Some(0), 5.0, -1.0, 1.5);
+        let renderer = TaperedRenderer::new(Viewport::identity()).with_joint(JointStyle::Miter);
+        let segments = render(renderer, &tree);
+        let chains = render(renderer.with_merged_chains(true), &tree);
+
+        // The trunk is one outline along both sides of its three segments; the side branch
+        // is a chain of its own.
+        assert_eq!(chains.len(), 2);
+        let expected = [
+            segments[0][0],
+            segments[0][1],
+            segments[1][1],
+            segments[2][1],
+            segments[2][2],
+            segments[1][2],
+            segments[0][2],
+            segments[0][3],
+        ];
+        assert_eq!(chains[0].len(), expected.len());
+        for (&found, &expected) in chains[0].iter().zip(&expected) {
+            assert_close(found, expected);
+        }
+        assert_eq!(chains[1].len(), 4);
+        for (&found, &expected) in chains[1].iter().zip(&segments[3]) {
+            assert_close(found, expected);
+        }
+    }
+}
@@ -1,7 +1,10 @@
+pub mod animation;
 pub mod renderer;
 pub mod canvas;
+pub mod viewport;
 mod impls;
 
 pub use impls::*;
 pub use renderer::Renderer;
 pub use canvas::Canvas;
+pub use viewport::Viewport;
@@ -1,13 +1,36 @@
-use crate::skeleton::{node::Node, tree::Tree};
+use crate::skeleton::{
+    leaf::Leaf,
+    node::{Node, NodeGraph},
+    tree::Tree,
+};
 
 use super::canvas::Canvas;
 
 pub trait Renderer<ColorType: Copy> {
     fn initialize(&mut self, canvas: &mut dyn Canvas<ColorType>);
-    fn render_node(&self, canvas: &mut dyn Canvas<ColorType>, node: &Node);
+    /// Draws `graph.nodes[index]`. The whole graph is passed so that renderers can take the
+    /// node's parent and children into account.
+    fn render_node(&self, canvas: &mut dyn Canvas<ColorType>, graph: &NodeGraph, index: usize);
+    /// Draws a single leaf. Renderers that do not draw foliage keep this default.
+    fn render_leaf(&self, _canvas: &mut dyn Canvas<ColorType>, _leaf: &Leaf) {}
+    /// Draws all leaves of `tree` on their own layer, above the branches.
+    fn render_leaves(&self, canvas: &mut dyn Canvas<ColorType>, tree: &Tree) {
+        if tree.leaves.is_empty() {
+            return;
+        }
+        canvas.set_layer("leaves");
+        for leaf in &tree.leaves {
+            self.render_leaf(canvas, leaf);
+        }
+    }
     fn render_tree<T : Canvas<ColorType>>(&mut self, canvas: &mut T, tree: &Tree) {
         self.initialize(canvas);
-        tree.nodes.iter(0).for_each(|node| self.render_node(canvas, node));
+        let depths = tree.nodes.depths();
+        for (index, depth) in depths.into_iter().enumerate() {
+            canvas.set_layer(&format!("depth-{depth}"));
+            self.render_node(canvas, &tree.nodes, index);
+        }
+        self.render_leaves(canvas, tree);
     }
     fn get_color_for_node(node: &Node) -> ColorType;
 }
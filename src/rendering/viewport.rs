@@ -0,0 +1,154 @@
+use crate::{skeleton::node::Point, utils::quadtree::BoundingBox};
+
+/// Maps world coordinates onto an output surface.
+///
+/// A world point `p` lands on `(p - origin) * scale`, so `origin` is the world point shown
+/// in the top-left corner and `scale` is the number of output units per world unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub origin: Point,
+    pub scale: f64,
+}
+
+impl Viewport {
+    pub fn new(origin: Point, scale: f64) -> Self {
+        Self { origin, scale }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(Point::origin(), 1.0)
+    }
+
+    /// Returns the viewport that fits `bounds` centered inside a `width` x `height` surface,
+    /// leaving `margin` output units free on every side. Bounds without any extent, such as
+    /// those of an empty tree, are centered at world size.
+    pub fn fit(bounds: BoundingBox, width: f64, height: f64, margin: f64) -> Self {
+        let available_width = (width - 2.0 * margin).max(1.0);
+        let available_height = (height - 2.0 * margin).max(1.0);
+        let scale = if bounds.width > 0.0 || bounds.height > 0.0 {
+            (available_width / bounds.width).min(available_height / bounds.height)
+        } else {
+            1.0
+        };
+
+        let center_x = bounds.x + bounds.width / 2.0;
+        let center_y = bounds.y + bounds.height / 2.0;
+        let origin = Point::new(
+            center_x - width / (2.0 * scale),
+            center_y - height / (2.0 * scale),
+        );
+        Self::new(origin, scale)
+    }
+
+    /// Zooms by `factor` while keeping the world point under `focus` (in output units) fixed.
+    pub fn zoomed(&self, factor: f64, focus: Point) -> Self {
+        let world_focus = self.to_world(focus);
+        let scale = self.scale * factor;
+        let origin = Point::new(
+            world_focus.x - focus.x / scale,
+            world_focus.y - focus.y / scale,
+        );
+        Self::new(origin, scale)
+    }
+
+    pub fn to_screen(&self, point: Point) -> Point {
+        Point::new(
+            (point.x - self.origin.x) * self.scale,
+            (point.y - self.origin.y) * self.scale,
+        )
+    }
+
+    pub fn to_world(&self, point: Point) -> Point {
+        Point::new(
+            point.x / self.scale + self.origin.x,
+            point.y / self.scale + self.origin.y,
+        )
+    }
+
+    pub fn scale_length(&self, length: f64) -> f64 {
+        length * self.scale
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::tree::Tree;
+
+    fn assert_close(a: Point, b: Point) {
+        assert!((a - b).norm() < 1e-9, "{a} != {b}");
+    }
+
+    fn bounds(x: f64, y: f64, width: f64, height: f64) -> BoundingBox {
+        BoundingBox {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn fitted_bounds_fill_the_surface_inside_the_margin() {
+        let viewport = Viewport::fit(bounds(10.0, 20.0, 100.0, 50.0), 400.0, 300.0, 10.0);
+        assert_eq!(viewport.scale, 3.8);
+        assert_close(viewport.to_screen(Point::new(10.0, 20.0)), Point::new(10.0, 55.0));
+        assert_close(viewport.to_screen(Point::new(110.0, 70.0)), Point::new(390.0, 245.0));
+    }
+
+    #[test]
+    fn fitting_keeps_the_aspect_ratio() {
+        // A tall box is limited by the height of a wide surface and centered horizontally.
+        let viewport = Viewport::fit(bounds(-5.0, -40.0, 10.0, 40.0), 400.0, 200.0, 0.0);
+        assert_eq!(viewport.scale, 5.0);
+        let top_left = viewport.to_screen(Point::new(-5.0, -40.0));
+        let bottom_right = viewport.to_screen(Point::new(5.0, 0.0));
+        assert_close(top_left, Point::new(175.0, 0.0));
+        assert_close(bottom_right, Point::new(225.0, 200.0));
+        let size = bottom_right - top_left;
+        assert_eq!(size.x / size.y, 10.0 / 40.0);
+    }
+
+    #[test]
+    fn trees_grow_up_the_screen() {
+        let mut tree = Tree::with_root(Point::new(30.0, 50.0));
+        tree.add_node(None, 20.0, 0.0, 0.0);
+        let viewport = Viewport::fit(tree.bounds(), 100.0, 100.0, 10.0);
+        let root = viewport.to_screen(tree.nodes.nodes[0].point);
+        let tip = viewport.to_screen(tree.nodes.nodes[0].next_point());
+        assert_close(root, Point::new(50.0, 90.0));
+        assert_close(tip, Point::new(50.0, 10.0));
+    }
+
+    #[test]
+    fn bounds_without_extent_are_centered_at_world_size() {
+        let empty = Tree::with_root(Point::new(7.0, -3.0));
+        let viewport = Viewport::fit(empty.bounds(), 200.0, 100.0, 10.0);
+        assert_eq!(viewport.scale, 1.0);
+        assert_close(viewport.to_screen(Point::new(7.0, -3.0)), Point::new(100.0, 50.0));
+
+        // A single segment without thickness is a line, fitted along its length.
+        let mut line = Tree::new();
+        line.add_node(None, 10.0, 0.0, 0.0);
+        let viewport = Viewport::fit(line.bounds(), 200.0, 100.0, 10.0);
+        assert_eq!(viewport.scale, 8.0);
+        assert!(viewport.origin.x.is_finite() && viewport.origin.y.is_finite());
+        assert_close(viewport.to_screen(Point::new(0.0, -5.0)), Point::new(100.0, 50.0));
+    }
+
+    #[test]
+    fn zooming_keeps_the_focus_in_place() {
+        let viewport = Viewport::new(Point::new(-20.0, 10.0), 2.0);
+        let focus = Point::new(60.0, 30.0);
+        let zoomed = viewport.zoomed(1.5, focus);
+        assert_eq!(zoomed.scale, 3.0);
+        assert_close(zoomed.to_world(focus), viewport.to_world(focus));
+        assert_close(zoomed.to_screen(zoomed.to_world(focus)), focus);
+    }
+}
@@ -1,51 +1,365 @@
+use std::f64::consts::{PI, TAU};
+
+use druid::Data;
 use rand::Rng;
-use rand_distr::{Distribution, Normal};
+use rand_distr::{Beta, Distribution, LogNormal, Normal, Uniform};
+use serde::{Deserialize, Serialize};
+
+/// Draws a [`DistributionKind::TruncatedNormal`] rejects before it gives up on hitting a
+/// narrow range and draws uniformly from it instead.
+const MAX_TRUNCATION_ATTEMPTS: usize = 64;
 
+/// Concentration above which a von Mises distribution is drawn as the normal distribution it
+/// approaches, where the exact method loses precision.
+const VON_MISES_MAX_CONCENTRATION: f64 = 1e3;
+
+/// Samplers never own an RNG; the caller passes one in so that draws stay reproducible
+/// under a seed.
 pub trait Sampler {
     type SampleType;
-    fn sample(&self) -> Self::SampleType;
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Self::SampleType;
 }
 
 pub trait ParameterizedSampler {
     type Params;
     type SampleType;
-    fn sample_with_params(&self, params: &Self::Params) -> Self::SampleType;
+    fn sample_with_params<R: Rng + ?Sized>(&self, params: &Self::Params, rng: &mut R) -> Self::SampleType;
 }
 
+/// Returns the same value every time.
 pub struct UniformSampler<T> where T : Copy {
     value: T,
 }
 
+impl<T> UniformSampler<T> where T : Copy {
+    pub fn new(value: T) -> Self {
+        Self { value }
+    }
+}
+
 impl<T> Sampler for UniformSampler<T> where T : Copy {
     type SampleType = T;
 
-    fn sample(&self) -> Self::SampleType {
+    fn sample<R: Rng + ?Sized>(&self, _rng: &mut R) -> Self::SampleType {
         self.value
     }
 }
 
+/// Draws from a normal distribution. A standard deviation that is not finite counts as zero.
 pub struct GaussianSampler {
-    mean: f64,
-    std_dev: f64,
+    normal: Normal<f64>,
+}
+
+impl GaussianSampler {
+    pub fn new(mean: f64, std_dev: f64) -> Self {
+        Self {
+            normal: normal(mean, std_dev),
+        }
+    }
 }
 
 impl Sampler for GaussianSampler {
     type SampleType = f64;
 
-    fn sample(&self) -> Self::SampleType {
-        let normal = Normal::new(self.mean, self.std_dev).unwrap();
-        let mut rng = rand::thread_rng();
-        normal.sample(&mut rng)
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Self::SampleType {
+        self.normal.sample(rng)
     }
 }
 
+/// Draws uniformly from `0..1`.
 pub struct RandomSampler;
 
 impl Sampler for RandomSampler {
     type SampleType = f64;
 
-    fn sample(&self) -> Self::SampleType {
-        let mut rng = rand::thread_rng();
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Self::SampleType {
         rng.gen()
     }
 }
+
+/// Center and width a generator draws a random parameter with.
+///
+/// Generators derive these from their params, and the [`DistributionKind`] chosen for the
+/// parameter decides the shape of the spread around them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Moments {
+    pub mean: f64,
+    pub std_dev: f64,
+    /// Size of a full turn in the unit of an angle parameter, such as 360 for degrees, or
+    /// `None` for parameters that are not angles.
+    pub full_turn: Option<f64>,
+}
+
+impl Moments {
+    pub fn new(mean: f64, std_dev: f64) -> Self {
+        Self {
+            mean,
+            std_dev,
+            full_turn: None,
+        }
+    }
+
+    /// Moments of an angle measured in a unit in which a full turn is `full_turn`.
+    pub fn angle(mean: f64, std_dev: f64, full_turn: f64) -> Self {
+        Self {
+            mean,
+            std_dev,
+            full_turn: Some(full_turn),
+        }
+    }
+}
+
+/// The distribution a random parameter is drawn from.
+///
+/// Every kind is fitted to the [`Moments`] the generator asks for. Apart from the truncated
+/// normal, which is narrower, they all keep both the mean and the standard deviation, so
+/// switching kinds changes how values spread but not how far.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Data, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DistributionKind {
+    #[default]
+    Normal,
+    /// A normal distribution cut off `bound` standard deviations away from the mean.
+    TruncatedNormal { bound: f64 },
+    /// Every value in a range around the mean is equally likely.
+    Uniform,
+    /// Skewed away from zero and never changes sign. Means of zero fall back to a normal
+    /// distribution.
+    LogNormal,
+    /// A beta distribution with shapes `alpha` and `beta`, scaled and shifted to the moments.
+    /// It is bounded on both sides and skewed when the shapes differ. Shapes that are not
+    /// positive fall back to a normal distribution.
+    Beta { alpha: f64, beta: f64 },
+    /// The circular counterpart of a normal distribution, for angles: it wraps around a full
+    /// turn instead of piling up beyond it. Parameters that are not angles fall back to a
+    /// normal distribution.
+    VonMises,
+}
+
+impl DistributionKind {
+    /// Checks that the truncation bound is finite and not negative, and that beta shapes are
+    /// finite and positive.
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            DistributionKind::TruncatedNormal { bound } if !(bound.is_finite() && bound >= 0.0) => {
+                Err(format!("truncation bound must be finite and not negative, got {bound}"))
+            }
+            DistributionKind::Beta { alpha, beta }
+                if !(alpha.is_finite() && beta.is_finite() && alpha > 0.0 && beta > 0.0) =>
+            {
+                Err(format!("beta shapes must be finite and positive, got {alpha} and {beta}"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fits the distribution to `moments`, ready to draw from repeatedly.
+    pub fn sampler(&self, moments: Moments) -> DistributionSampler {
+        let Moments { mean, std_dev, .. } = moments;
+        // A normal distribution without spread still draws from the RNG, so a parameter that
+        // uses one consumes the same random numbers whatever its spread.
+        if *self != DistributionKind::Normal && !(std_dev.is_finite() && std_dev != 0.0) {
+            return DistributionSampler::Constant(mean);
+        }
+        let spread = std_dev.abs();
+        match *self {
+            DistributionKind::Normal => DistributionSampler::Normal(normal(mean, std_dev)),
+            DistributionKind::TruncatedNormal { bound } => {
+                let min = mean - bound.abs() * spread;
+                let max = mean + bound.abs() * spread;
+                // A bound that is not a number leaves nothing to draw from.
+                if (min..=max).is_empty() {
+                    return DistributionSampler::Constant(mean);
+                }
+                DistributionSampler::TruncatedNormal {
+                    normal: normal(mean, spread),
+                    min,
+                    max,
+                }
+            }
+            DistributionKind::Uniform => {
+                // A uniform distribution of width w has a standard deviation of w / sqrt(12).
+                let half_width = 3f64.sqrt() * spread;
+                DistributionSampler::Uniform(Uniform::new_inclusive(
+                    mean - half_width,
+                    mean + half_width,
+                ))
+            }
+            DistributionKind::LogNormal => {
+                match LogNormal::from_mean_cv(mean.abs(), spread / mean.abs()) {
+                    Ok(log_normal) => DistributionSampler::LogNormal {
+                        log_normal,
+                        sign: mean.signum(),
+                    },
+                    Err(_) => DistributionSampler::Normal(normal(mean, std_dev)),
+                }
+            }
+            DistributionKind::Beta { alpha, beta } => match Beta::new(alpha, beta) {
+                Ok(distribution) => {
+                    let sum = alpha + beta;
+                    let unit_std_dev = (alpha * beta / (sum * sum * (sum + 1.0))).sqrt();
+                    let width = spread / unit_std_dev;
+                    DistributionSampler::Beta {
+                        beta: distribution,
+                        min: mean - width * alpha / sum,
+                        width,
+                    }
+                }
+                Err(_) => DistributionSampler::Normal(normal(mean, std_dev)),
+            },
+            DistributionKind::VonMises => match moments.full_turn {
+                Some(full_turn) => {
+                    let radians = TAU / full_turn;
+                    DistributionSampler::VonMises {
+                        mean,
+                        concentration: (spread * radians).powi(-2),
+                        to_unit: radians.recip(),
+                    }
+                }
+                None => DistributionSampler::Normal(normal(mean, std_dev)),
+            },
+        }
+    }
+}
+
+impl ParameterizedSampler for DistributionKind {
+    type Params = Moments;
+    type SampleType = f64;
+
+    fn sample_with_params<R: Rng + ?Sized>(&self, params: &Moments, rng: &mut R) -> f64 {
+        self.sampler(*params).sample(rng)
+    }
+}
+
+/// A [`DistributionKind`] fitted to [`Moments`].
+#[derive(Debug, Clone, Copy)]
+pub enum DistributionSampler {
+    /// Moments without spread.
+    Constant(f64),
+    Normal(Normal<f64>),
+    TruncatedNormal { normal: Normal<f64>, min: f64, max: f64 },
+    Uniform(Uniform<f64>),
+    LogNormal { log_normal: LogNormal<f64>, sign: f64 },
+    Beta { beta: Beta<f64>, min: f64, width: f64 },
+    VonMises {
+        mean: f64,
+        concentration: f64,
+        /// Converts radians into the unit of the parameter.
+        to_unit: f64,
+    },
+}
+
+impl Sampler for DistributionSampler {
+    type SampleType = f64;
+
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match *self {
+            DistributionSampler::Constant(value) => value,
+            DistributionSampler::Normal(normal) => normal.sample(rng),
+            DistributionSampler::TruncatedNormal { normal, min, max } => {
+                for _ in 0..MAX_TRUNCATION_ATTEMPTS {
+                    let value = normal.sample(rng);
+                    if (min..=max).contains(&value) {
+                        return value;
+                    }
+                }
+                // Only narrow ranges get here, and over those the normal is nearly flat.
+                rng.gen_range(min..=max)
+            }
+            DistributionSampler::Uniform(uniform) => uniform.sample(rng),
+            DistributionSampler::LogNormal { log_normal, sign } => sign * log_normal.sample(rng),
+            DistributionSampler::Beta { beta, min, width } => min + width * beta.sample(rng),
+            DistributionSampler::VonMises {
+                mean,
+                concentration,
+                to_unit,
+            } => mean + von_mises(concentration, rng) * to_unit,
+        }
+    }
+}
+
+/// Builds a normal distribution, treating a standard deviation that is not finite as zero.
+fn normal(mean: f64, std_dev: f64) -> Normal<f64> {
+    let std_dev = if std_dev.is_finite() { std_dev } else { 0.0 };
+    Normal::new(mean, std_dev).expect("the standard deviation is finite")
+}
+
+/// Draws an angle in radians around zero from a von Mises distribution with the given
+/// concentration, using the rejection method of Best and Fisher (1979).
+fn von_mises<R: Rng + ?Sized>(concentration: f64, rng: &mut R) -> f64 {
+    if concentration > VON_MISES_MAX_CONCENTRATION {
+        return normal(0.0, concentration.sqrt().recip()).sample(rng);
+    }
+    if concentration < f64::EPSILON {
+        return rng.gen_range(-PI..PI);
+    }
+
+    let tau = 1.0 + (1.0 + 4.0 * concentration * concentration).sqrt();
+    let rho = (tau - (2.0 * tau).sqrt()) / (2.0 * concentration);
+    let r = (1.0 + rho * rho) / (2.0 * rho);
+    loop {
+        let z = (PI * rng.gen::<f64>()).cos();
+        let f = (1.0 + r * z) / (r + z);
+        let c = concentration * (r - f);
+        let u = rng.gen::<f64>();
+        if c * (2.0 - c) > u || (c / u).ln() + 1.0 - c >= 0.0 {
+            let angle = f.clamp(-1.0, 1.0).acos();
+            return if rng.gen::<bool>() { angle } else { -angle };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rng::rng_from_seed;
+
+    const DRAWS: usize = 100_000;
+
+    fn sample_moments(kind: DistributionKind, moments: Moments) -> (f64, f64) {
+        let sampler = kind.sampler(moments);
+        let mut rng = rng_from_seed(7);
+        let values: Vec<f64> = (0..DRAWS).map(|_| sampler.sample(&mut rng)).collect();
+        let mean = values.iter().sum::<f64>() / DRAWS as f64;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / DRAWS as f64;
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn distributions_keep_mean_and_std_dev() {
+        let kinds = [
+            DistributionKind::Normal,
+            DistributionKind::Uniform,
+            DistributionKind::LogNormal,
+            DistributionKind::Beta { alpha: 2.0, beta: 5.0 },
+            DistributionKind::VonMises,
+        ];
+        for kind in kinds {
+            let (mean, std_dev) = sample_moments(kind, Moments::angle(40.0, 8.0, 360.0));
+            assert!((mean - 40.0).abs() < 0.2, "{kind:?}: mean {mean}");
+            assert!((std_dev - 8.0).abs() < 0.2, "{kind:?}: std_dev {std_dev}");
+        }
+    }
+
+    #[test]
+    fn truncated_normals_stay_within_their_bound() {
+        let kind = DistributionKind::TruncatedNormal { bound: 1.5 };
+        let sampler = kind.sampler(Moments::new(10.0, 2.0));
+        let mut rng = rng_from_seed(7);
+        for _ in 0..DRAWS {
+            let value = sampler.sample(&mut rng);
+            assert!((7.0..=13.0).contains(&value), "{value}");
+        }
+        let (mean, std_dev) = sample_moments(kind, Moments::new(10.0, 2.0));
+        assert!((mean - 10.0).abs() < 0.05, "mean {mean}");
+        assert!(std_dev < 2.0, "std_dev {std_dev}");
+    }
+
+    #[test]
+    fn invalid_shapes_are_rejected() {
+        assert!(DistributionKind::TruncatedNormal { bound: f64::NAN }.validate().is_err());
+        assert!(DistributionKind::TruncatedNormal { bound: -1.0 }.validate().is_err());
+        assert!(DistributionKind::Beta { alpha: 0.0, beta: 1.0 }.validate().is_err());
+        assert!(DistributionKind::Beta { alpha: 2.0, beta: 5.0 }.validate().is_ok());
+    }
+}
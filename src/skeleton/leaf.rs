@@ -0,0 +1,55 @@
+use std::f64::consts::PI;
+
+use nalgebra::Vector2;
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use super::node::Point;
+
+/// Number of outline points on each side of a leaf, between its base and its tip.
+const OUTLINE_STEPS: usize = 6;
+
+/// A single leaf attached to a node of the skeleton.
+///
+/// Leaves use the same conventions as nodes: `position` is the base of the leaf in world
+/// coordinates and `angle` is measured from negative y toward positive x.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Leaf {
+    pub node_index: usize,
+    pub position: Point,
+    pub angle: f64,
+    pub length: f64,
+    pub width: f64,
+    pub color: Srgb<u8>,
+}
+
+impl Leaf {
+    pub fn tip(&self) -> Point {
+        self.position + self.direction() * self.length
+    }
+
+    /// Returns a pointed, leaf-shaped outline in world coordinates, widest a little below
+    /// the middle and closed at the base and the tip.
+    pub fn outline(&self) -> Vec<Point> {
+        let direction = self.direction();
+        let normal = Vector2::new(-direction.y, direction.x);
+        let half_width = |t: f64| self.width / 2.0 * (PI * t.powf(0.8)).sin();
+
+        let mut outline = Vec::with_capacity(2 * OUTLINE_STEPS);
+        outline.push(self.position);
+        for step in 1..OUTLINE_STEPS {
+            let t = step as f64 / OUTLINE_STEPS as f64;
+            outline.push(self.position + direction * (t * self.length) + normal * half_width(t));
+        }
+        outline.push(self.tip());
+        for step in (1..OUTLINE_STEPS).rev() {
+            let t = step as f64 / OUTLINE_STEPS as f64;
+            outline.push(self.position + direction * (t * self.length) - normal * half_width(t));
+        }
+        outline
+    }
+
+    fn direction(&self) -> Vector2<f64> {
+        Vector2::new(self.angle.sin(), -self.angle.cos())
+    }
+}
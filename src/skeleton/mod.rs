@@ -1,3 +1,5 @@
 pub mod tree;
 pub mod node;
+pub mod leaf;
+pub mod serialization;
 
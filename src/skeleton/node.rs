@@ -1,15 +1,24 @@
 use nalgebra::Point2;
 
 use palette::Srgb;
+use serde::{Deserialize, Serialize};
 
-use crate::utils::quadtree::{BoundingBox, Quadtree};
-
-pub const WIDTH: usize = 400;
-pub const HEIGHT: usize = 400;
+use crate::utils::{
+    geometry::segment_distance,
+    quadtree::BoundingBox,
+    segment_index::{Segment, SegmentIndex},
+};
 
 pub type Point = Point2<f64>;
 
-#[derive(Debug, Clone)]
+/// Side length of the index boundary a new graph starts with. The index doubles in size
+/// whenever a segment lands outside of it.
+const INITIAL_INDEX_EXTENT: f64 = 64.0;
+
+/// Number of segments a quadrant of the index holds before it subdivides.
+const INDEX_CAPACITY: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub parent_index: Option<usize>,
     pub children_indices: Vec<usize>,
@@ -18,6 +27,9 @@ pub struct Node {
     pub angle: f64,
     pub thickness: f64,
     pub color: Option<Srgb<u8>>,
+    /// Number of growth steps the node has lived through. Nodes grown in one pass are 0.
+    #[serde(default)]
+    pub age: u32,
 }
 
 impl Node {
@@ -30,28 +42,181 @@ impl Node {
     pub fn set_color(&mut self, color: Srgb<u8>) {
         self.color = Some(color);
     }
+
+    /// Returns the segment from the node's point to its end.
+    pub fn segment(&self) -> Segment {
+        Segment::new(self.point, self.next_point(), self.thickness)
+    }
 }
 
-#[derive(Debug, Clone)]
+/// The skeleton of a tree in world coordinates.
+///
+/// World space has no fixed extent: +x points right and +y points down, so a node with an
+/// angle of zero grows toward negative y. Renderers map world coordinates to pixels through
+/// a [`Viewport`](crate::rendering::Viewport).
+///
+/// The index is derived data: it is skipped when serializing and rebuilt on load. It holds
+/// the segment of every node, thickness included, and is kept up to date by
+/// [`NodeGraph::add_node`], [`NodeGraph::update_points`] and [`NodeGraph::remove_subtree`];
+/// call [`NodeGraph::rebuild_index`] after editing `nodes` directly in any other way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "SerializedNodeGraph")]
 pub struct NodeGraph {
     pub nodes: Vec<Node>,
-    pub quadtree: Quadtree,
+    #[serde(skip)]
+    pub index: SegmentIndex,
+    root_position: Point,
+    /// The segment every node is indexed under, indexed like `nodes`, so that entries can
+    /// be found again after the nodes changed.
+    #[serde(skip)]
+    segments: Vec<Segment>,
+}
+
+#[derive(Deserialize)]
+struct SerializedNodeGraph {
+    nodes: Vec<Node>,
+    root_position: Point,
+}
+
+impl TryFrom<SerializedNodeGraph> for NodeGraph {
+    type Error = String;
+
+    fn try_from(value: SerializedNodeGraph) -> Result<Self, Self::Error> {
+        NodeGraph::try_from_nodes(value.root_position, value.nodes)
+    }
 }
 
 impl NodeGraph {
+    /// Creates an empty graph whose root will be placed at the world origin.
     pub fn new() -> Self {
+        Self::with_root(Point::origin())
+    }
+
+    /// Creates an empty graph whose root will be placed at `root_position`.
+    pub fn with_root(root_position: Point) -> Self {
         let boundary = BoundingBox {
-            x: 0.0,
-            y: 0.0,
-            width: WIDTH as f64,
-            height: HEIGHT as f64,
+            x: root_position.x - INITIAL_INDEX_EXTENT / 2.0,
+            y: root_position.y - INITIAL_INDEX_EXTENT / 2.0,
+            width: INITIAL_INDEX_EXTENT,
+            height: INITIAL_INDEX_EXTENT,
         };
         NodeGraph {
             nodes: Vec::new(),
-            quadtree: Quadtree::new(boundary, 4),
+            index: SegmentIndex::new(boundary, INDEX_CAPACITY),
+            root_position,
+            segments: Vec::new(),
         }
     }
 
+    /// Creates a graph from existing nodes and builds their spatial index. Parents must come
+    /// before their children in `nodes`.
+    pub fn from_nodes(root_position: Point, nodes: Vec<Node>) -> Self {
+        let mut graph = Self::with_root(root_position);
+        graph.nodes = nodes;
+        graph.rebuild_index();
+        graph
+    }
+
+    /// Like [`NodeGraph::from_nodes`], but checks first that parents come before their
+    /// children, that parent and child links match both ways and that node 0 is the only
+    /// root.
+    pub fn try_from_nodes(root_position: Point, nodes: Vec<Node>) -> Result<Self, String> {
+        let node_count = nodes.len();
+        let mut child_count = 0;
+        for (index, node) in nodes.iter().enumerate() {
+            match node.parent_index {
+                Some(parent_index) if parent_index >= index => {
+                    return Err(format!(
+                        "node {index} has parent {parent_index}, parents must come before their children"
+                    ));
+                }
+                Some(parent_index) if !nodes[parent_index].children_indices.contains(&index) => {
+                    return Err(format!(
+                        "node {index} has parent {parent_index}, which does not list it as a child"
+                    ));
+                }
+                None if index > 0 => {
+                    return Err(format!(
+                        "node {index} has no parent, only node 0 can be the root"
+                    ));
+                }
+                _ => {}
+            }
+            if let Some(&child_index) = node.children_indices.iter().find(|&&child_index| {
+                child_index <= index
+                    || child_index >= node_count
+                    || nodes[child_index].parent_index != Some(index)
+            }) {
+                return Err(format!("node {index} has invalid child {child_index}"));
+            }
+            child_count += node.children_indices.len();
+        }
+        if child_count != node_count.saturating_sub(1) {
+            return Err("some nodes list the same child more than once".to_string());
+        }
+        Ok(NodeGraph::from_nodes(root_position, nodes))
+    }
+
+    /// Rebuilds the index from the current node segments.
+    pub fn rebuild_index(&mut self) {
+        self.segments = self.nodes.iter().map(Node::segment).collect();
+        let entries: Vec<(Segment, usize)> = self.segments.iter().copied().zip(0..).collect();
+        let boundary = Self::with_root(self.root_position).index.boundary();
+        self.index = SegmentIndex::from_entries(boundary, INDEX_CAPACITY, &entries);
+    }
+
+    /// Returns the nodes whose segments come within `clearance` of the segment from `start`
+    /// to `end`, measured between the surfaces of both segments given their thicknesses.
+    pub fn segments_near(
+        &self,
+        start: Point,
+        end: Point,
+        thickness: f64,
+        clearance: f64,
+    ) -> Vec<usize> {
+        let mut near = Vec::new();
+        let radius = clearance + thickness / 2.0;
+        self.index.visit_capsule(start, end, radius, &mut |segment, index| {
+            let distance = segment_distance(start, end, segment.start, segment.end);
+            if distance - (thickness + segment.thickness) / 2.0 < clearance {
+                near.push(index);
+            }
+        });
+        near.sort_unstable();
+        near
+    }
+
+    /// Returns the node whose segment is closest to `point` if its surface is at most
+    /// `tolerance` away, for picking nodes with a pointer.
+    pub fn node_at(&self, point: &Point, tolerance: f64) -> Option<usize> {
+        self.index
+            .nearest_within(point, tolerance)
+            .map(|(_, index)| index)
+    }
+
+    pub fn root_position(&self) -> Point {
+        self.root_position
+    }
+
+    /// Returns the world-space box covering every segment, including its thickness.
+    pub fn bounds(&self) -> BoundingBox {
+        let mut bounds = BoundingBox {
+            x: self.root_position.x,
+            y: self.root_position.y,
+            width: 0.0,
+            height: 0.0,
+        };
+        for node in &self.nodes {
+            let half_thickness = node.thickness / 2.0;
+            for point in [node.point, node.next_point()] {
+                bounds = bounds
+                    .including(&Point::new(point.x - half_thickness, point.y - half_thickness))
+                    .including(&Point::new(point.x + half_thickness, point.y + half_thickness));
+            }
+        }
+        bounds
+    }
+
     pub fn add_node(
         &mut self,
         parent_index: Option<usize>,
@@ -63,7 +228,7 @@ impl NodeGraph {
             let parent = &self.nodes[parent_index];
             parent.next_point()
         } else {
-            Point::new(WIDTH as f64 / 2.0, HEIGHT as f64)
+            self.root_position
         };
         let new_node = Node {
             parent_index,
@@ -73,10 +238,13 @@ impl NodeGraph {
             angle,
             thickness,
             color: None,
+            age: 0,
         };
 
         let node_index = self.nodes.len();
-        self.quadtree.insert(new_node.point, node_index);
+        let segment = new_node.segment();
+        self.index.insert(segment, node_index);
+        self.segments.push(segment);
         self.nodes.push(new_node);
 
         if let Some(parent_index) = parent_index {
@@ -86,6 +254,101 @@ impl NodeGraph {
         node_index
     }
 
+    /// Moves every node to the end of its parent after lengths or angles changed. Only the
+    /// index entries of nodes whose segments moved or changed thickness are updated.
+    pub fn update_points(&mut self) {
+        for index in 0..self.nodes.len() {
+            self.nodes[index].point = match self.nodes[index].parent_index {
+                Some(parent_index) => self.nodes[parent_index].next_point(),
+                None => self.root_position,
+            };
+            let segment = self.nodes[index].segment();
+            if segment != self.segments[index] {
+                // Non-finite segments were never indexed, so there may be nothing to remove.
+                self.index.remove(&self.segments[index], index);
+                self.index.insert(segment, index);
+                self.segments[index] = segment;
+            }
+        }
+    }
+
+    /// Removes `index` and every node above it, returning the new index of every old node,
+    /// or `None` for removed ones. Remaining nodes keep their order and positions.
+    pub fn remove_subtree(&mut self, index: usize) -> Vec<Option<usize>> {
+        // Children come after their parents, so one pass finds every descendant.
+        let mut removed = vec![false; self.nodes.len()];
+        removed[index] = true;
+        for descendant in index + 1..self.nodes.len() {
+            if let Some(parent_index) = self.nodes[descendant].parent_index {
+                removed[descendant] = removed[parent_index];
+            }
+        }
+
+        let mut next_index = 0;
+        let new_indices: Vec<Option<usize>> = removed
+            .iter()
+            .map(|&removed| {
+                (!removed).then(|| {
+                    next_index += 1;
+                    next_index - 1
+                })
+            })
+            .collect();
+
+        let nodes = std::mem::take(&mut self.nodes);
+        self.nodes = nodes
+            .into_iter()
+            .zip(&new_indices)
+            .filter(|(_, new_index)| new_index.is_some())
+            .map(|(mut node, _)| {
+                node.parent_index = node.parent_index.map(|parent| {
+                    new_indices[parent].expect("parents of kept nodes are kept")
+                });
+                node.children_indices = node
+                    .children_indices
+                    .iter()
+                    .filter_map(|&child| new_indices[child])
+                    .collect();
+                node
+            })
+            .collect();
+        self.index.remap(&mut |old_index| new_indices[old_index]);
+        self.segments = std::mem::take(&mut self.segments)
+            .into_iter()
+            .zip(&new_indices)
+            .filter(|(_, new_index)| new_index.is_some())
+            .map(|(segment, _)| segment)
+            .collect();
+        new_indices
+    }
+
+    /// Returns the number of ancestors of every node, indexed like `nodes`.
+    pub fn depths(&self) -> Vec<usize> {
+        let mut depths: Vec<usize> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let depth = node.parent_index.map_or(0, |parent_index| depths[parent_index] + 1);
+            depths.push(depth);
+        }
+        depths
+    }
+
+    /// Returns the number of steps from every node down to its nearest tip, indexed like
+    /// `nodes`. Tips are at distance zero.
+    pub fn tip_distances(&self) -> Vec<usize> {
+        let mut distances = vec![0; self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate().rev() {
+            if let Some(nearest) = node
+                .children_indices
+                .iter()
+                .map(|&child_index| distances[child_index])
+                .min()
+            {
+                distances[index] = nearest + 1;
+            }
+        }
+        distances
+    }
+
     pub fn traverse(&self, start_index: usize, visitor: &mut dyn NodeVisitor) {
         let node = &self.nodes[start_index];
         visitor.visit(node);
@@ -95,7 +358,7 @@ impl NodeGraph {
         }
     }
 
-    pub fn iter(&self, start_index: usize) -> NodeGraphIterator {
+    pub fn iter(&self, start_index: usize) -> NodeGraphIterator<'_> {
         NodeGraphIterator::new(self, start_index)
     }
 }
@@ -135,3 +398,105 @@ impl<'a> Iterator for NodeGraphIterator<'a> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use palette::Srgb;
+
+    use super::{NodeGraph, Point};
+    use crate::skeleton::{leaf::Leaf, tree::Tree};
+
+    /// A root with a left branch of three nodes and a right branch of two.
+    fn forked_tree() -> Tree {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 0.0, 2.0);
+        let left = tree.add_node(Some(root), 10.0, -1.0, 1.0);
+        let left_top = tree.add_node(Some(left), 10.0, -1.0, 1.0);
+        let right = tree.add_node(Some(root), 10.0, 1.0, 1.0);
+        tree.add_node(Some(right), 10.0, 1.0, 1.0);
+        tree.add_node(Some(left_top), 10.0, -1.0, 1.0);
+        tree
+    }
+
+    fn midpoint(tree: &Tree, index: usize) -> Point {
+        let node = &tree.nodes.nodes[index];
+        Point::from((node.point.coords + node.next_point().coords) / 2.0)
+    }
+
+    fn leaf(node_index: usize) -> Leaf {
+        Leaf {
+            node_index,
+            position: Point::origin(),
+            angle: 0.0,
+            length: 1.0,
+            width: 1.0,
+            color: Srgb::new(0, 0, 0),
+        }
+    }
+
+    #[test]
+    fn nodes_are_indexed_once_their_segments_become_finite() {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 10.0, 0.0, 2.0);
+        let tip = tree.add_node(Some(root), f64::NAN, 0.0, 1.0);
+        assert_eq!(tree.nodes.index.len(), 1);
+
+        tree.nodes.nodes[tip].length = 5.0;
+        tree.nodes.update_points();
+        assert_eq!(tree.nodes.index.len(), 2);
+        let end = tree.nodes.nodes[tip].next_point();
+        let closest = tree.nodes.index.nearest(&end);
+        assert_eq!(closest.map(|(_, index)| index), Some(tip));
+    }
+
+    #[test]
+    fn removing_a_subtree_renumbers_the_survivors() {
+        let mut tree = forked_tree();
+        let new_indices = tree.remove_subtree(1);
+        assert_eq!(new_indices, [Some(0), None, None, Some(1), Some(2), None]);
+
+        let nodes = &tree.nodes.nodes;
+        let parents: Vec<_> = nodes.iter().map(|node| node.parent_index).collect();
+        assert_eq!(parents, [None, Some(0), Some(1)]);
+        let children: Vec<_> = nodes.iter().map(|node| node.children_indices.clone()).collect();
+        assert_eq!(children, [vec![1], vec![2], vec![]]);
+        assert!(NodeGraph::try_from_nodes(tree.nodes.root_position(), nodes.clone()).is_ok());
+    }
+
+    #[test]
+    fn spatial_queries_follow_removed_subtrees() {
+        let mut tree = forked_tree();
+        let removed_midpoints = [1, 2, 5].map(|index| midpoint(&tree, index));
+        let kept_midpoints = [0, 3, 4].map(|index| midpoint(&tree, index));
+        tree.remove_subtree(1);
+
+        assert_eq!(tree.nodes.index.len(), 3);
+        for (new_index, point) in kept_midpoints.iter().enumerate() {
+            assert_eq!(tree.nodes.node_at(point, 0.1), Some(new_index));
+        }
+        for point in &removed_midpoints {
+            assert_eq!(tree.nodes.node_at(point, 0.1), None);
+        }
+        let everything = tree.nodes.segments_near(
+            Point::new(-100.0, 0.0),
+            Point::new(100.0, 0.0),
+            0.0,
+            100.0,
+        );
+        assert_eq!(everything, [0, 1, 2]);
+
+        // Nodes added afterwards are indexed under their new numbers too.
+        let tip = tree.add_node(Some(2), 10.0, 0.0, 1.0);
+        let end = tree.nodes.nodes[tip].next_point();
+        assert_eq!(tree.nodes.node_at(&end, 0.1), Some(tip));
+    }
+
+    #[test]
+    fn removing_a_subtree_drops_its_leaves() {
+        let mut tree = forked_tree();
+        tree.leaves = vec![leaf(2), leaf(4), leaf(0), leaf(5)];
+        tree.remove_subtree(1);
+        let leaf_nodes: Vec<_> = tree.leaves.iter().map(|leaf| leaf.node_index).collect();
+        assert_eq!(leaf_nodes, [2, 0]);
+    }
+}
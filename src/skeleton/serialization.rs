@@ -0,0 +1,221 @@
+use std::{fmt, fs, io, path::Path};
+
+use super::tree::Tree;
+
+/// Magic bytes at the start of the binary format, followed by a one byte format version.
+const BINARY_MAGIC: &[u8; 4] = b"TREE";
+const BINARY_VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub enum SerializationError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    InvalidHeader,
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializationError::Io(err) => write!(f, "i/o error: {err}"),
+            SerializationError::Json(err) => write!(f, "invalid json: {err}"),
+            SerializationError::Binary(err) => write!(f, "invalid binary data: {err}"),
+            SerializationError::InvalidHeader => write!(f, "not a binary tree file"),
+            SerializationError::UnsupportedVersion(version) => {
+                write!(f, "unsupported binary format version {version}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SerializationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SerializationError::Io(err) => Some(err),
+            SerializationError::Json(err) => Some(err),
+            SerializationError::Binary(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SerializationError {
+    fn from(value: io::Error) -> Self {
+        SerializationError::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for SerializationError {
+    fn from(value: serde_json::Error) -> Self {
+        SerializationError::Json(value)
+    }
+}
+
+impl From<bincode::Error> for SerializationError {
+    fn from(value: bincode::Error) -> Self {
+        SerializationError::Binary(value)
+    }
+}
+
+impl Tree {
+    /// Serializes the tree as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String, SerializationError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SerializationError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serializes the tree into the compact binary format: a `TREE` header, a version byte
+    /// and the bincode encoding of the tree.
+    pub fn to_binary(&self) -> Result<Vec<u8>, SerializationError> {
+        let mut bytes = Vec::with_capacity(BINARY_MAGIC.len() + 1);
+        bytes.extend_from_slice(BINARY_MAGIC);
+        bytes.push(BINARY_VERSION);
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let body = bytes
+            .strip_prefix(BINARY_MAGIC)
+            .ok_or(SerializationError::InvalidHeader)?;
+        let (&version, body) = body.split_first().ok_or(SerializationError::InvalidHeader)?;
+        match version {
+            BINARY_VERSION => Ok(bincode::deserialize(body)?),
+            _ => Err(SerializationError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn save_json<P: AsRef<Path>>(&self, path: P) -> Result<(), SerializationError> {
+        Ok(fs::write(path, self.to_json()?)?)
+    }
+
+    pub fn load_json<P: AsRef<Path>>(path: P) -> Result<Self, SerializationError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save_binary<P: AsRef<Path>>(&self, path: P) -> Result<(), SerializationError> {
+        Ok(fs::write(path, self.to_binary()?)?)
+    }
+
+    pub fn load_binary<P: AsRef<Path>>(path: P) -> Result<Self, SerializationError> {
+        Self::from_binary(&fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::{leaf::Leaf, node::Point};
+
+    fn sample_tree() -> Tree {
+        let mut tree = Tree::new();
+        let root = tree.add_node(None, 40.0, 0.0, 8.0);
+        let left = tree.add_node(Some(root), 25.0, -0.4, 5.0);
+        tree.add_node(Some(root), 25.0, 0.3, 5.0);
+        tree.add_node(Some(left), 10.0, -0.1, 2.0);
+        tree.nodes.nodes[left].set_color(palette::Srgb::new(10, 200, 30));
+        tree
+    }
+
+    fn leaf(node_index: usize) -> Leaf {
+        Leaf {
+            node_index,
+            position: Point::new(1.5, -2.0),
+            angle: 0.7,
+            length: 8.0,
+            width: 3.5,
+            color: palette::Srgb::new(60, 140, 40),
+        }
+    }
+
+    /// Trees have no `PartialEq`, but their debug output prints every float exactly.
+    fn assert_same(loaded: &Tree, tree: &Tree) {
+        assert_eq!(format!("{:?}", loaded.nodes.nodes), format!("{:?}", tree.nodes.nodes));
+        assert_eq!(loaded.nodes.root_position(), tree.nodes.root_position());
+        assert_eq!(loaded.nodes.index.len(), tree.nodes.nodes.len());
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let tree = sample_tree();
+        assert_same(&Tree::from_json(&tree.to_json().unwrap()).unwrap(), &tree);
+    }
+
+    #[test]
+    fn binary_round_trips() {
+        let mut tree = sample_tree();
+        tree.nodes.nodes[3].age = 4;
+        assert_same(&Tree::from_binary(&tree.to_binary().unwrap()).unwrap(), &tree);
+    }
+
+    #[test]
+    fn binary_headers_are_checked() {
+        let mut bytes = sample_tree().to_binary().unwrap();
+        bytes[BINARY_MAGIC.len()] = BINARY_VERSION + 1;
+        assert!(matches!(
+            Tree::from_binary(&bytes),
+            Err(SerializationError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            Tree::from_binary(b"BUSH"),
+            Err(SerializationError::InvalidHeader)
+        ));
+    }
+
+    #[test]
+    fn leaves_round_trip() {
+        let mut tree = sample_tree();
+        tree.leaves.push(leaf(3));
+        let loaded = Tree::from_binary(&tree.to_binary().unwrap()).unwrap();
+        assert_eq!(format!("{:?}", loaded.leaves), format!("{:?}", tree.leaves));
+        let loaded = Tree::from_json(&tree.to_json().unwrap()).unwrap();
+        assert_eq!(format!("{:?}", loaded.leaves), format!("{:?}", tree.leaves));
+    }
+
+    #[test]
+    fn leaves_on_missing_nodes_are_rejected() {
+        let mut json = serde_json::to_value(sample_tree()).unwrap();
+        json["leaves"] = serde_json::json!([{
+            "node_index": 4,
+            "position": [0.0, 0.0],
+            "angle": 0.0,
+            "length": 1.0,
+            "width": 1.0,
+            "color": {"red": 0, "green": 0, "blue": 0},
+        }]);
+        let err = Tree::from_json(&json.to_string()).unwrap_err().to_string();
+        assert!(err.contains("leaf on node 4"), "{err}");
+    }
+
+    /// Loads `tree` after letting `corrupt` edit its JSON nodes.
+    fn load_corrupted(corrupt: impl FnOnce(&mut Vec<serde_json::Value>)) -> String {
+        let mut json = serde_json::to_value(sample_tree()).unwrap();
+        corrupt(json["nodes"]["nodes"].as_array_mut().unwrap());
+        Tree::from_json(&json.to_string()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn broken_links_are_rejected() {
+        let err = load_corrupted(|nodes| nodes[1]["children_indices"] = Vec::<usize>::new().into());
+        assert!(err.contains("node 3 has parent 1, which does not list it"), "{err}");
+
+        let err = load_corrupted(|nodes| nodes[3]["parent_index"] = 2.into());
+        assert!(err.contains("node 1 has invalid child 3"), "{err}");
+
+        let err = load_corrupted(|nodes| nodes[0]["children_indices"] = vec![1, 2, 3].into());
+        assert!(err.contains("node 0 has invalid child 3"), "{err}");
+
+        let err = load_corrupted(|nodes| {
+            nodes[2]["parent_index"] = serde_json::Value::Null;
+            nodes[0]["children_indices"] = vec![1].into();
+        });
+        assert!(err.contains("only node 0 can be the root"), "{err}");
+
+        let err = load_corrupted(|nodes| nodes[1]["children_indices"] = vec![3, 3].into());
+        assert!(err.contains("more than once"), "{err}");
+    }
+}
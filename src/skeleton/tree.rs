@@ -1,14 +1,60 @@
-use super::node::NodeGraph;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+use crate::utils::quadtree::BoundingBox;
+
+use super::{
+    leaf::Leaf,
+    node::{NodeGraph, Point},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "SerializedTree")]
 pub struct Tree {
     pub nodes: NodeGraph,
+    pub leaves: Vec<Leaf>,
+}
+
+#[derive(Deserialize)]
+struct SerializedTree {
+    nodes: NodeGraph,
+    #[serde(default)]
+    leaves: Vec<Leaf>,
+}
+
+impl TryFrom<SerializedTree> for Tree {
+    type Error = String;
+
+    fn try_from(value: SerializedTree) -> Result<Self, Self::Error> {
+        let node_count = value.nodes.nodes.len();
+        if let Some(leaf) = value.leaves.iter().find(|leaf| leaf.node_index >= node_count) {
+            return Err(format!(
+                "leaf on node {}, but the tree only has {node_count} nodes",
+                leaf.node_index
+            ));
+        }
+        Ok(Tree {
+            nodes: value.nodes,
+            leaves: value.leaves,
+        })
+    }
 }
 
 impl Tree {
     pub fn new() -> Self {
-        Self { nodes: NodeGraph::new() }
+        Self {
+            nodes: NodeGraph::new(),
+            leaves: Vec::new(),
+        }
+    }
+
+    /// Creates an empty tree whose root will be placed at `root_position` in world space.
+    pub fn with_root(root_position: Point) -> Self {
+        Self {
+            nodes: NodeGraph::with_root(root_position),
+            leaves: Vec::new(),
+        }
     }
+
     pub fn add_node(
         &mut self,
         parent: Option<usize>,
@@ -19,14 +65,38 @@ impl Tree {
         self.nodes.add_node(parent, length, angle, thickness)
     }
 
-    pub(crate) fn get_tip_nodes(&self) -> Vec<usize> {
+    /// Prunes `index` and every node above it along with their leaves. Returns the new index
+    /// of every old node, or `None` for removed ones.
+    pub fn remove_subtree(&mut self, index: usize) -> Vec<Option<usize>> {
+        let new_indices = self.nodes.remove_subtree(index);
+        self.leaves.retain_mut(|leaf| match new_indices[leaf.node_index] {
+            Some(new_index) => {
+                leaf.node_index = new_index;
+                true
+            }
+            None => false,
+        });
+        new_indices
+    }
+
+    /// Returns the indices of all nodes without children.
+    pub fn get_tip_nodes(&self) -> Vec<usize> {
         self.nodes
-            .iter(0)
+            .nodes
+            .iter()
             .enumerate()
-            .filter(|(_, node)| node.thickness >= 0.0)
+            .filter(|(_, node)| node.children_indices.is_empty())
             .map(|(index, _)| index)
             .collect()
     }
+
+    /// Returns the world-space box covering every segment and leaf.
+    pub fn bounds(&self) -> BoundingBox {
+        self.leaves
+            .iter()
+            .flat_map(|leaf| leaf.outline())
+            .fold(self.nodes.bounds(), |bounds, point| bounds.including(&point))
+    }
 }
 
 impl Default for Tree {
@@ -34,3 +104,27 @@ impl Default for Tree {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tips_are_the_nodes_without_children() {
+        let mut tree = Tree::new();
+        assert!(tree.get_tip_nodes().is_empty());
+        let root = tree.add_node(None, 10.0, 0.0, 3.0);
+        assert_eq!(tree.get_tip_nodes(), [root]);
+
+        let left = tree.add_node(Some(root), 5.0, -0.5, 2.0);
+        let right = tree.add_node(Some(root), 5.0, 0.5, 2.0);
+        let twig = tree.add_node(Some(left), 2.0, -0.2, 1.0);
+        // A node added after a tip can still carry children.
+        let leaf_twig = tree.add_node(Some(right), 2.0, 0.2, 1.0);
+        tree.add_node(Some(leaf_twig), 1.0, 0.1, 0.5);
+        assert_eq!(tree.get_tip_nodes(), [twig, leaf_twig + 1]);
+        for tip in tree.get_tip_nodes() {
+            assert!(tree.nodes.nodes[tip].children_indices.is_empty());
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use druid::Data;
+use serde::{Deserialize, Serialize};
+
+/// Most keys a [`Curve`] holds. The keys live inline so that curves are `Copy`, like the
+/// params they are part of.
+pub const MAX_CURVE_KEYS: usize = 8;
+
+/// How a [`Curve`] runs between its keys.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Data, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Straight lines from key to key.
+    #[default]
+    Linear,
+    /// A smooth Catmull-Rom spline through the keys.
+    CatmullRom,
+}
+
+/// A function given by up to [`MAX_CURVE_KEYS`] `[x, y]` keys, sorted by `x`.
+///
+/// Before the first key and after the last the curve stays at their values.
+#[derive(Debug, Clone, Copy, PartialEq, Data, Serialize, Deserialize)]
+#[serde(try_from = "SerializedCurve", into = "SerializedCurve")]
+pub struct Curve {
+    pub interpolation: Interpolation,
+    keys: [[f64; 2]; MAX_CURVE_KEYS],
+    len: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedCurve {
+    #[serde(default)]
+    interpolation: Interpolation,
+    keys: Vec<[f64; 2]>,
+}
+
+impl TryFrom<SerializedCurve> for Curve {
+    type Error = String;
+
+    fn try_from(value: SerializedCurve) -> Result<Self, Self::Error> {
+        Curve::new(value.interpolation, &value.keys)
+    }
+}
+
+impl From<Curve> for SerializedCurve {
+    fn from(value: Curve) -> Self {
+        SerializedCurve {
+            interpolation: value.interpolation,
+            keys: value.keys().to_vec(),
+        }
+    }
+}
+
+impl Curve {
+    /// Creates a curve through `keys`, which may come in any order. There must be between
+    /// one and [`MAX_CURVE_KEYS`] of them, all finite.
+    pub fn new(interpolation: Interpolation, keys: &[[f64; 2]]) -> Result<Self, String> {
+        if keys.is_empty() || keys.len() > MAX_CURVE_KEYS {
+            return Err(format!(
+                "a curve needs between 1 and {MAX_CURVE_KEYS} keys, got {}",
+                keys.len()
+            ));
+        }
+        if let Some(key) = keys
+            .iter()
+            .find(|key| !key.iter().all(|value| value.is_finite()))
+        {
+            return Err(format!("curve key {key:?} is not finite"));
+        }
+
+        let mut curve = Curve {
+            interpolation,
+            keys: [[0.0; 2]; MAX_CURVE_KEYS],
+            len: keys.len(),
+        };
+        curve.keys[..keys.len()].copy_from_slice(keys);
+        curve.sort();
+        Ok(curve)
+    }
+
+    /// Creates a curve that is `value` everywhere.
+    pub fn constant(value: f64) -> Self {
+        Self::new(Interpolation::Linear, &[[0.0, value]]).expect("one finite key is valid")
+    }
+
+    pub fn keys(&self) -> &[[f64; 2]] {
+        &self.keys[..self.len]
+    }
+
+    /// Returns the value of the curve at `x`.
+    pub fn evaluate(&self, x: f64) -> f64 {
+        let keys = self.keys();
+        let last = keys.len() - 1;
+        if x <= keys[0][0] {
+            return keys[0][1];
+        }
+        if x >= keys[last][0] {
+            return keys[last][1];
+        }
+
+        // The index of the last key at or before `x`, which is before the last key.
+        let i = keys.partition_point(|key| key[0] <= x) - 1;
+        let (x1, x2) = (keys[i][0], keys[i + 1][0]);
+        let t = if x2 > x1 { (x - x1) / (x2 - x1) } else { 1.0 };
+        let [a, b, c, d] = self.coefficients(i);
+        a + t * (b + t * (c + t * d))
+    }
+
+    /// Returns the smallest and the largest value the curve takes. Catmull-Rom curves can
+    /// overshoot their keys, so this is not always the range of the key values.
+    pub fn range(&self) -> (f64, f64) {
+        let keys = self.keys();
+        let mut range = (keys[0][1], keys[0][1]);
+        let mut include = |value: f64| range = (range.0.min(value), range.1.max(value));
+        for i in 0..keys.len() - 1 {
+            let [a, b, c, d] = self.coefficients(i);
+            let value = |t: f64| a + t * (b + t * (c + t * d));
+            include(value(1.0));
+            // Extremes inside the span are where the derivative b + 2ct + 3dt² is zero.
+            let roots = if d == 0.0 {
+                [(c != 0.0).then(|| -b / (2.0 * c)), None]
+            } else {
+                let discriminant = c * c - 3.0 * b * d;
+                if discriminant < 0.0 {
+                    [None, None]
+                } else {
+                    let root = discriminant.sqrt();
+                    [Some((-c + root) / (3.0 * d)), Some((-c - root) / (3.0 * d))]
+                }
+            };
+            for t in roots.into_iter().flatten() {
+                if t > 0.0 && t < 1.0 {
+                    include(value(t));
+                }
+            }
+        }
+        range
+    }
+
+    /// Returns the coefficients of the cubic in `t` the curve follows from key `i` to key
+    /// `i + 1`, lowest power first, with `t` running from 0 to 1 over the span.
+    fn coefficients(&self, i: usize) -> [f64; 4] {
+        let keys = self.keys();
+        let last = keys.len() - 1;
+        let y1 = keys[i][1];
+        let y2 = keys[i + 1][1];
+        match self.interpolation {
+            Interpolation::Linear => [y1, y2 - y1, 0.0, 0.0],
+            Interpolation::CatmullRom => {
+                // Missing neighbors at the ends are mirrored, which keeps the ends straight.
+                let y0 = if i > 0 { keys[i - 1][1] } else { 2.0 * y1 - y2 };
+                let y3 = if i + 2 <= last {
+                    keys[i + 2][1]
+                } else {
+                    2.0 * y2 - y1
+                };
+                [
+                    y1,
+                    0.5 * (y2 - y0),
+                    0.5 * (2.0 * y0 - 5.0 * y1 + 4.0 * y2 - y3),
+                    0.5 * (3.0 * (y1 - y2) + y3 - y0),
+                ]
+            }
+        }
+    }
+
+    /// Adds `key` and returns its index, or `None` if the curve is full or the key is not
+    /// finite.
+    pub fn insert_key(&mut self, key: [f64; 2]) -> Option<usize> {
+        if self.len == MAX_CURVE_KEYS || !key.iter().all(|value| value.is_finite()) {
+            return None;
+        }
+        self.keys[self.len] = key;
+        self.len += 1;
+        Some(self.sort_moving(self.len - 1))
+    }
+
+    /// Removes the key at `index`. The last key is never removed. Returns whether a key was.
+    pub fn remove_key(&mut self, index: usize) -> bool {
+        if self.len <= 1 || index >= self.len {
+            return false;
+        }
+        self.keys.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        true
+    }
+
+    /// Moves the key at `index` to `key` and returns its index after re-sorting, or `None`
+    /// if there is no such key or `key` is not finite.
+    pub fn move_key(&mut self, index: usize, key: [f64; 2]) -> Option<usize> {
+        if index >= self.len || !key.iter().all(|value| value.is_finite()) {
+            return None;
+        }
+        self.keys[index] = key;
+        Some(self.sort_moving(index))
+    }
+
+    fn sort(&mut self) {
+        self.keys[..self.len].sort_by(|a, b| a[0].total_cmp(&b[0]));
+    }
+
+    /// Sorts the keys after the key at `index` changed and returns where it ended up.
+    fn sort_moving(&mut self, mut index: usize) -> usize {
+        while index > 0 && self.keys[index - 1][0] > self.keys[index][0] {
+            self.keys.swap(index - 1, index);
+            index -= 1;
+        }
+        while index + 1 < self.len && self.keys[index + 1][0] < self.keys[index][0] {
+            self.keys.swap(index, index + 1);
+            index += 1;
+        }
+        index
+    }
+}
+
+impl Default for Curve {
+    /// A flat line at 1 from 0 to 1, so the parameter it scales is left as it is.
+    fn default() -> Self {
+        Self::new(Interpolation::Linear, &[[0.0, 1.0], [1.0, 1.0]])
+            .expect("two finite keys are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(interpolation: Interpolation, keys: &[[f64; 2]]) -> Curve {
+        Curve::new(interpolation, keys).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn keys_are_sorted_and_checked() {
+        let sorted = curve(Interpolation::Linear, &[[0.8, 3.0], [0.1, 1.0], [0.5, 2.0]]);
+        assert_eq!(sorted.keys(), [[0.1, 1.0], [0.5, 2.0], [0.8, 3.0]]);
+
+        assert!(Curve::new(Interpolation::Linear, &[]).is_err());
+        assert!(Curve::new(Interpolation::Linear, &[[0.0, 1.0]; MAX_CURVE_KEYS + 1]).is_err());
+        let err = Curve::new(Interpolation::Linear, &[[0.0, f64::NAN]]).unwrap_err();
+        assert!(err.contains("not finite"), "{err}");
+    }
+
+    #[test]
+    fn curves_hold_their_end_values_outside_the_keys() {
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom] {
+            let curve = curve(interpolation, &[[0.2, 1.0], [0.5, 4.0], [0.7, 2.0]]);
+            assert_eq!(curve.evaluate(-3.0), 1.0);
+            assert_eq!(curve.evaluate(0.0), 1.0);
+            assert_eq!(curve.evaluate(1.0), 2.0);
+            assert_eq!(curve.evaluate(12.0), 2.0);
+        }
+        assert_eq!(Curve::constant(0.3).evaluate(0.9), 0.3);
+    }
+
+    #[test]
+    fn curves_pass_through_their_keys() {
+        let keys = [[0.0, 1.0], [0.25, 3.0], [0.5, -1.0], [1.0, 2.0]];
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom] {
+            let curve = curve(interpolation, &keys);
+            for [x, y] in keys {
+                assert_close(curve.evaluate(x), y);
+            }
+        }
+    }
+
+    #[test]
+    fn linear_and_catmull_rom_curves_differ_between_keys() {
+        let keys = [[0.0, 0.0], [0.5, 1.0], [1.0, 0.0]];
+        let linear = curve(Interpolation::Linear, &keys);
+        assert_close(linear.evaluate(0.25), 0.5);
+        assert_close(linear.evaluate(0.75), 0.5);
+
+        // The mirrored ends put a -1 on either side of the keys, so the spline bulges above
+        // the straight lines.
+        let smooth = curve(Interpolation::CatmullRom, &keys);
+        assert_close(smooth.evaluate(0.25), 0.625);
+        assert_close(smooth.evaluate(0.75), 0.625);
+        // Two keys make a straight line either way.
+        let line = curve(Interpolation::CatmullRom, &[[0.0, 1.0], [1.0, 3.0]]);
+        assert_close(line.evaluate(0.25), 1.5);
+    }
+
+    #[test]
+    fn ranges_include_overshoot() {
+        let keys = [[0.0, 0.0], [0.2, 0.0], [0.4, 1.0], [1.0, 1.0]];
+        assert_eq!(curve(Interpolation::Linear, &keys).range(), (0.0, 1.0));
+
+        let smooth = curve(Interpolation::CatmullRom, &keys);
+        let (min, max) = smooth.range();
+        let sampled = (0..=1000).map(|i| smooth.evaluate(i as f64 / 1000.0));
+        let sampled_min = sampled.clone().fold(f64::INFINITY, f64::min);
+        let sampled_max = sampled.fold(f64::NEG_INFINITY, f64::max);
+        assert!(min < 0.0 && max > 1.0, "{min} {max}");
+        assert!(min <= sampled_min && sampled_min - min < 1e-4);
+        assert!(max >= sampled_max && max - sampled_max < 1e-4);
+    }
+
+    #[test]
+    fn key_edits_keep_the_keys_sorted() {
+        let mut curve = curve(Interpolation::Linear, &[[0.0, 1.0], [0.5, 2.0], [1.0, 3.0]]);
+        assert_eq!(curve.insert_key([0.25, 5.0]), Some(1));
+        assert_eq!(curve.insert_key([2.0, 0.0]), Some(4));
+        assert_eq!(curve.insert_key([0.1, f64::INFINITY]), None);
+
+        // Dragging a key past its neighbors re-sorts it.
+        assert_eq!(curve.move_key(1, [0.75, 5.0]), Some(2));
+        assert_eq!(curve.move_key(2, [-1.0, 5.0]), Some(0));
+        assert_eq!(curve.move_key(9, [0.0, 0.0]), None);
+        assert_eq!(
+            curve.keys(),
+            [[-1.0, 5.0], [0.0, 1.0], [0.5, 2.0], [1.0, 3.0], [2.0, 0.0]]
+        );
+
+        assert!(curve.remove_key(0));
+        assert!(!curve.remove_key(4));
+        assert_eq!(curve.keys(), [[0.0, 1.0], [0.5, 2.0], [1.0, 3.0], [2.0, 0.0]]);
+        while curve.remove_key(0) {}
+        assert_eq!(curve.keys(), [[2.0, 0.0]]);
+    }
+
+    #[test]
+    fn full_curves_take_no_more_keys() {
+        let keys: Vec<[f64; 2]> = (0..MAX_CURVE_KEYS).map(|i| [i as f64, 0.0]).collect();
+        let mut full = curve(Interpolation::Linear, &keys);
+        assert_eq!(full.insert_key([0.5, 0.0]), None);
+        assert_eq!(full.keys().len(), MAX_CURVE_KEYS);
+    }
+
+    #[test]
+    fn deserializing_checks_the_keys() {
+        let json = r#"{"interpolation": "catmull_rom", "keys": [[1, 2], [0, 1]]}"#;
+        let curve: Curve = serde_json::from_str(json).unwrap();
+        assert_eq!(curve.interpolation, Interpolation::CatmullRom);
+        assert_eq!(curve.keys(), [[0.0, 1.0], [1.0, 2.0]]);
+        let round_trip: Curve = serde_json::from_str(&serde_json::to_string(&curve).unwrap()).unwrap();
+        assert_eq!(round_trip, curve);
+
+        let too_many = format!(r#"{{"keys": {:?}}}"#, vec![[0.0, 1.0]; MAX_CURVE_KEYS + 1]);
+        let err = serde_json::from_str::<Curve>(&too_many).unwrap_err();
+        assert!(err.to_string().contains("between 1 and"), "{err}");
+        assert!(serde_json::from_str::<Curve>(r#"{"keys": []}"#).is_err());
+    }
+}
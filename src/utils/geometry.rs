@@ -0,0 +1,39 @@
+use std::f64::consts::TAU;
+
+use crate::skeleton::node::Point;
+
+/// Returns the absolute difference between two angles in radians, from 0 to pi.
+pub fn angle_between(a: f64, b: f64) -> f64 {
+    let difference = (b - a).rem_euclid(TAU);
+    difference.min(TAU - difference)
+}
+
+/// Returns the distance from `point` to the closest point of the segment from `a` to `b`.
+pub fn point_segment_distance(point: Point, a: Point, b: Point) -> f64 {
+    let direction = b - a;
+    let length_squared = direction.norm_squared();
+    if length_squared == 0.0 {
+        return (point - a).norm();
+    }
+    let t = ((point - a).dot(&direction) / length_squared).clamp(0.0, 1.0);
+    (point - (a + direction * t)).norm()
+}
+
+/// Returns whether the segments from `a0` to `a1` and from `b0` to `b1` properly cross.
+/// Segments that only touch are not counted; their distance is zero anyway.
+pub fn segments_intersect(a0: Point, a1: Point, b0: Point, b1: Point) -> bool {
+    let side = |o: Point, p: Point, q: Point| (p - o).perp(&(q - o));
+    side(b0, b1, a0) * side(b0, b1, a1) < 0.0 && side(a0, a1, b0) * side(a0, a1, b1) < 0.0
+}
+
+/// Returns the shortest distance between the segments from `a0` to `a1` and from `b0` to
+/// `b1`, which is zero when they cross.
+pub fn segment_distance(a0: Point, a1: Point, b0: Point, b1: Point) -> f64 {
+    if segments_intersect(a0, a1, b0, b1) {
+        return 0.0;
+    }
+    point_segment_distance(a0, b0, b1)
+        .min(point_segment_distance(a1, b0, b1))
+        .min(point_segment_distance(b0, a0, a1))
+        .min(point_segment_distance(b1, a0, a1))
+}
@@ -2,7 +2,12 @@ use std::ops::Sub;
 
 use nalgebra::Point2;
 
+pub mod curve;
+pub mod geometry;
 pub mod quadtree;
+pub mod region_tree;
+pub mod rng;
+pub mod segment_index;
 
 pub type Line = (Point, Point);
 
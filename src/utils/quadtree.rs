@@ -1,6 +1,10 @@
+use serde::{Deserialize, Serialize};
+
 use crate::skeleton::node::Point;
 
-#[derive(Debug, Clone, Copy)]
+use super::region_tree::RegionTree;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct BoundingBox {
     pub x: f64,
     pub y: f64,
@@ -16,6 +20,44 @@ impl BoundingBox {
             && point.y <= self.y + self.height
     }
 
+    /// Returns the smallest box containing both `self` and `point`.
+    pub fn including(&self, point: &Point) -> BoundingBox {
+        let min_x = self.x.min(point.x);
+        let min_y = self.y.min(point.y);
+        let max_x = (self.x + self.width).max(point.x);
+        let max_y = (self.y + self.height).max(point.y);
+        BoundingBox {
+            x: min_x,
+            y: min_y,
+            width: max_x - min_x,
+            height: max_y - min_y,
+        }
+    }
+
+    /// Returns the distance from `point` to the closest point of the box, zero inside it.
+    pub fn distance_to(&self, point: &Point) -> f64 {
+        let dx = (self.x - point.x).max(point.x - (self.x + self.width)).max(0.0);
+        let dy = (self.y - point.y).max(point.y - (self.y + self.height)).max(0.0);
+        dx.hypot(dy)
+    }
+
+    /// Returns whether `other` lies entirely inside the box, edges included.
+    pub fn contains_box(&self, other: &BoundingBox) -> bool {
+        other.x >= self.x
+            && other.x + other.width <= self.x + self.width
+            && other.y >= self.y
+            && other.y + other.height <= self.y + self.height
+    }
+
+    /// Like [`BoundingBox::intersects`], but boxes that only share an edge or a corner touch
+    /// too, so boxes of zero width or height are never missed.
+    pub fn touches(&self, other: &BoundingBox) -> bool {
+        self.x <= other.x + other.width
+            && self.x + self.width >= other.x
+            && self.y <= other.y + other.height
+            && self.y + self.height >= other.y
+    }
+
     pub fn intersects(&self, other: &BoundingBox) -> bool {
         self.x < other.x + other.width
             && self.x + self.width > other.x
@@ -24,89 +66,86 @@ impl BoundingBox {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Quadtree {
-    capacity: usize,
-    boundary: BoundingBox,
-    points: Vec<(Point, usize)>,
-    divided: bool,
-    north_west: Option<Box<Quadtree>>,
-    north_east: Option<Box<Quadtree>>,
-    south_west: Option<Box<Quadtree>>,
-    south_east: Option<Box<Quadtree>>,
-}
+/// A point quadtree whose points each carry a payload, node indices by default. Points are
+/// stored as boxes of zero size, so every [`RegionTree`] query works on them.
+pub type Quadtree<T = usize> = RegionTree<Point, T>;
 
-impl Quadtree {
-    pub fn new(boundary: BoundingBox, capacity: usize) -> Self {
-        Self {
-            capacity,
-            boundary,
-            points: Vec::new(),
-            divided: false,
-            north_west: None,
-            north_east: None,
-            south_west: None,
-            south_east: None,
-        }
-    }
-
-    pub fn insert(&mut self, point: Point, index: usize) -> bool {
-        if !self.boundary.contains(&point) {
-            return false;
-        }
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
 
-        if self.points.len() < self.capacity {
-            self.points.push((point, index));
-            return true;
-        }
+    use super::*;
+    use crate::utils::rng::rng_from_seed;
 
-        if !self.divided {
-            self.subdivide();
+    fn boundary(size: f64) -> BoundingBox {
+        BoundingBox {
+            x: 0.0,
+            y: 0.0,
+            width: size,
+            height: size,
         }
-
-        self.north_west.as_mut().unwrap().insert(point, index) ||
-        self.north_east.as_mut().unwrap().insert(point, index) ||
-        self.south_west.as_mut().unwrap().insert(point, index) ||
-        self.south_east.as_mut().unwrap().insert(point, index)
     }
 
-    fn subdivide(&mut self) {
-        let x = self.boundary.x;
-        let y = self.boundary.y;
-        let w = self.boundary.width / 2.0;
-        let h = self.boundary.height / 2.0;
-
-        let nw = BoundingBox { x, y, width: w, height: h };
-        let ne = BoundingBox { x: x + w, y, width: w, height: h };
-        let sw = BoundingBox { x, y: y + h, width: w, height: h };
-        let se = BoundingBox { x: x + w, y: y + h, width: w, height: h };
-
-        self.north_west = Some(Box::new(Quadtree::new(nw, self.capacity)));
-        self.north_east = Some(Box::new(Quadtree::new(ne, self.capacity)));
-        self.south_west = Some(Box::new(Quadtree::new(sw, self.capacity)));
-        self.south_east = Some(Box::new(Quadtree::new(se, self.capacity)));
-
-        self.divided = true;
+    /// Points on a coarse grid, so that many of them are equally far from a query.
+    fn grid_points(count: usize, seed: u64) -> Vec<(Point, usize)> {
+        let mut rng = rng_from_seed(seed);
+        (0..count)
+            .map(|index| {
+                let point = Point::new(rng.gen_range(0..20) as f64, rng.gen_range(0..20) as f64);
+                (point, index)
+            })
+            .collect()
     }
 
-    pub fn query(&self, range: BoundingBox, mut found: Vec<(Point, usize)>) -> Vec<(Point, usize)> {
-        if !self.boundary.intersects(&range) {
-            return found;
-        }
+    /// Sorts `points` by their distance to `point` and then by index, like the tree does.
+    fn brute_force(points: &[(Point, usize)], point: &Point) -> Vec<(Point, usize)> {
+        let mut sorted = points.to_vec();
+        sorted.sort_by(|a, b| {
+            (a.0 - point)
+                .norm()
+                .total_cmp(&(b.0 - point).norm())
+                .then(a.1.cmp(&b.1))
+        });
+        sorted
+    }
 
-        for point in &self.points {
-            if range.contains(&point.0) {
-                found.push(*point);
+    #[test]
+    fn nearest_points_match_a_brute_force_scan() {
+        let points = grid_points(300, 5);
+        let quadtree = Quadtree::from_entries(boundary(20.0), 4, &points);
+        let mut rng = rng_from_seed(6);
+        for _ in 0..50 {
+            let point = Point::new(rng.gen_range(-5..25) as f64, rng.gen_range(-5..25) as f64);
+            let expected = brute_force(&points, &point);
+            assert_eq!(quadtree.nearest(&point), expected.first().copied());
+            for k in [1, 5, 40] {
+                assert_eq!(quadtree.k_nearest(&point, k), expected[..k]);
             }
+            let within = expected.first().filter(|&&(nearest, _)| (nearest - point).norm() <= 2.0);
+            assert_eq!(quadtree.nearest_within(&point, 2.0), within.copied());
         }
+    }
 
-        if self.divided {
-            found = self.north_west.as_ref().unwrap().query(range, found.clone());
-            found = self.north_east.as_ref().unwrap().query(range, found.clone());
-            found = self.south_west.as_ref().unwrap().query(range, found.clone());
-            found = self.south_east.as_ref().unwrap().query(range, found.clone());
+    #[test]
+    fn radius_queries_match_a_brute_force_scan() {
+        let points = grid_points(300, 7);
+        let quadtree = Quadtree::from_entries(boundary(20.0), 4, &points);
+        for (center, radius) in [(Point::new(10.0, 10.0), 3.0), (Point::new(0.0, 19.0), 5.5)] {
+            let mut found = quadtree.query_radius(&center, radius);
+            found.sort_by_key(|&(_, index)| index);
+            let expected: Vec<_> = points
+                .iter()
+                .copied()
+                .filter(|&(point, _)| (point - center).norm() <= radius)
+                .collect();
+            assert_eq!(found, expected);
         }
+    }
 
-        found
+    #[test]
+    fn empty_trees_have_no_neighbors() {
+        let quadtree = Quadtree::<usize>::new(boundary(20.0), 4);
+        assert_eq!(quadtree.nearest(&Point::new(1.0, 1.0)), None);
+        assert!(quadtree.k_nearest(&Point::new(1.0, 1.0), 3).is_empty());
     }
 }
@@ -0,0 +1,655 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::skeleton::node::Point;
+
+use super::quadtree::BoundingBox;
+
+/// A shape that can be stored in a [`RegionTree`].
+pub trait Bounded: Copy + PartialEq {
+    /// Returns the smallest box covering the shape.
+    fn bounds(&self) -> BoundingBox;
+
+    /// Returns the distance from `point` to the shape, zero inside it. Never less than the
+    /// distance from `point` to [`Bounded::bounds`].
+    fn distance_to_point(&self, point: &Point) -> f64;
+
+    /// Returns whether the shape overlaps `range`, edges included.
+    fn overlaps_box(&self, range: &BoundingBox) -> bool;
+}
+
+/// A point is a box of zero size.
+impl Bounded for Point {
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox {
+            x: self.x,
+            y: self.y,
+            width: 0.0,
+            height: 0.0,
+        }
+    }
+
+    fn distance_to_point(&self, point: &Point) -> f64 {
+        (self - point).norm()
+    }
+
+    fn overlaps_box(&self, range: &BoundingBox) -> bool {
+        range.contains(self)
+    }
+}
+
+/// A region quadtree of shapes that each carry a payload, node indices by default.
+///
+/// Every shape is stored in the smallest quadrant whose boundary covers its bounds, so shapes
+/// that straddle the edge between quadrants stay higher up in the tree, while points always
+/// sink down to a leaf. The boundary doubles toward shapes inserted outside of it.
+#[derive(Debug, Clone)]
+pub struct RegionTree<S, T = usize> {
+    capacity: usize,
+    boundary: BoundingBox,
+    entries: Vec<(S, T)>,
+    /// North west, north east, south west and south east, in that order.
+    quadrants: Option<Box<[RegionTree<S, T>; 4]>>,
+}
+
+impl<S: Bounded, T: Copy + PartialEq> RegionTree<S, T> {
+    pub fn new(boundary: BoundingBox, capacity: usize) -> Self {
+        Self {
+            capacity,
+            boundary,
+            entries: Vec::new(),
+            quadrants: None,
+        }
+    }
+
+    /// Builds a tree holding every `(shape, payload)` pair. The boundary is grown to cover
+    /// all finite shapes up front, so nothing has to be expanded while inserting.
+    pub fn from_entries(boundary: BoundingBox, capacity: usize, entries: &[(S, T)]) -> Self {
+        let boundary = entries
+            .iter()
+            .map(|(shape, _)| shape.bounds())
+            .filter(is_finite)
+            .fold(boundary, |boundary, bounds| {
+                boundary
+                    .including(&Point::new(bounds.x, bounds.y))
+                    .including(&Point::new(
+                        bounds.x + bounds.width,
+                        bounds.y + bounds.height,
+                    ))
+            });
+        let mut tree = RegionTree::new(boundary, capacity);
+        for &(shape, payload) in entries {
+            tree.insert(shape, payload);
+        }
+        tree
+    }
+
+    /// Inserts `shape`, growing the tree if it lies outside of it. Returns `false` for
+    /// shapes with non-finite coordinates, which are never stored.
+    pub fn insert(&mut self, shape: S, payload: T) -> bool {
+        let bounds = shape.bounds();
+        if !is_finite(&bounds) {
+            return false;
+        }
+        self.expand_to_contain(&bounds);
+        self.insert_within(shape, &bounds, payload)
+    }
+
+    /// Removes the entry of `payload` for `shape`. Returns whether it was found.
+    ///
+    /// Quadrants left holding no more shapes than a single node can are merged back into
+    /// their parent, so a tree that shrinks does not keep its deep subdivisions.
+    pub fn remove(&mut self, shape: &S, payload: T) -> bool {
+        self.remove_within(shape, &shape.bounds(), payload)
+    }
+
+    /// Replaces the entry of `payload` for `old` with `new`. Returns `false` if there was no
+    /// entry for `old` or `new` could not be inserted.
+    pub fn update(&mut self, old: &S, payload: T, new: S) -> bool {
+        self.remove(old, payload) && self.insert(new, payload)
+    }
+
+    /// Renumbers every entry with `map`, dropping the entries it maps to `None`.
+    pub fn remap(&mut self, map: &mut dyn FnMut(T) -> Option<T>) {
+        self.entries = self
+            .entries
+            .drain(..)
+            .filter_map(|(shape, payload)| map(payload).map(|payload| (shape, payload)))
+            .collect();
+        for quadrant in self.quadrants_mut() {
+            quadrant.remap(map);
+        }
+        self.merge_if_sparse();
+    }
+
+    /// Number of shapes in the tree.
+    pub fn len(&self) -> usize {
+        self.entries.len() + self.quadrants().map(RegionTree::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.quadrants().all(RegionTree::is_empty)
+    }
+
+    pub fn boundary(&self) -> BoundingBox {
+        self.boundary
+    }
+
+    /// Calls `visitor` with every shape that overlaps `range`, without allocating.
+    pub fn visit_box(&self, range: &BoundingBox, visitor: &mut dyn FnMut(&S, T)) {
+        if !self.boundary.touches(range) {
+            return;
+        }
+        for (shape, payload) in &self.entries {
+            if shape.overlaps_box(range) {
+                visitor(shape, *payload);
+            }
+        }
+        for quadrant in self.quadrants() {
+            quadrant.visit_box(range, visitor);
+        }
+    }
+
+    /// Returns every shape that overlaps `range`, in no particular order.
+    pub fn query_box(&self, range: &BoundingBox) -> Vec<(S, T)> {
+        let mut found = Vec::new();
+        self.visit_box(range, &mut |shape, payload| found.push((*shape, payload)));
+        found
+    }
+
+    /// Calls `visitor` with every shape at most `radius` away from `center`, and its
+    /// distance, without allocating.
+    pub fn visit_radius(
+        &self,
+        center: &Point,
+        radius: f64,
+        visitor: &mut dyn FnMut(&S, T, f64),
+    ) {
+        if self.boundary.distance_to(center) > radius {
+            return;
+        }
+        for (shape, payload) in &self.entries {
+            let distance = shape.distance_to_point(center);
+            if distance <= radius {
+                visitor(shape, *payload, distance);
+            }
+        }
+        for quadrant in self.quadrants() {
+            quadrant.visit_radius(center, radius, visitor);
+        }
+    }
+
+    /// Returns every shape at most `radius` away from `center`, in no particular order.
+    pub fn query_radius(&self, center: &Point, radius: f64) -> Vec<(S, T)> {
+        let mut found = Vec::new();
+        self.visit_radius(center, radius, &mut |shape, payload, _| {
+            found.push((*shape, payload))
+        });
+        found
+    }
+
+    /// Calls `visitor` with the entries of every node whose boundary touches `range`, so
+    /// callers can run their own exact test.
+    pub(super) fn visit_candidates(
+        &self,
+        range: &BoundingBox,
+        visitor: &mut dyn FnMut(&S, T),
+    ) {
+        if !self.boundary.touches(range) {
+            return;
+        }
+        for (shape, payload) in &self.entries {
+            visitor(shape, *payload);
+        }
+        for quadrant in self.quadrants() {
+            quadrant.visit_candidates(range, visitor);
+        }
+    }
+
+    /// Grows the boundary by doubling it toward `bounds` until they are covered. The
+    /// existing tree becomes one quadrant of the new root, so no shapes are reinserted.
+    fn expand_to_contain(&mut self, bounds: &BoundingBox) {
+        if self.boundary.width <= 0.0 || self.boundary.height <= 0.0 {
+            return;
+        }
+
+        while !self.boundary.contains_box(bounds) {
+            let old = self.boundary;
+            let grow_west = bounds.x < old.x;
+            let grow_north = bounds.y < old.y;
+            let boundary = BoundingBox {
+                x: if grow_west { old.x - old.width } else { old.x },
+                y: if grow_north {
+                    old.y - old.height
+                } else {
+                    old.y
+                },
+                width: old.width * 2.0,
+                height: old.height * 2.0,
+            };
+
+            let mut quadrants =
+                split(&boundary).map(|quadrant| RegionTree::new(quadrant, self.capacity));
+            let slot = match (grow_west, grow_north) {
+                (false, false) => 0,
+                (true, false) => 1,
+                (false, true) => 2,
+                (true, true) => 3,
+            };
+            quadrants[slot] = std::mem::replace(self, RegionTree::new(boundary, self.capacity));
+            self.quadrants = Some(Box::new(quadrants));
+        }
+    }
+
+    fn insert_within(&mut self, shape: S, bounds: &BoundingBox, payload: T) -> bool {
+        if !self.boundary.contains_box(bounds) {
+            return false;
+        }
+        if let Some(quadrants) = &mut self.quadrants {
+            if let Some(quadrant) = quadrants
+                .iter_mut()
+                .find(|quadrant| quadrant.boundary.contains_box(bounds))
+            {
+                return quadrant.insert_within(shape, bounds, payload);
+            }
+            self.entries.push((shape, payload));
+            return true;
+        }
+
+        self.entries.push((shape, payload));
+        if self.entries.len() > self.capacity {
+            self.subdivide();
+        }
+        true
+    }
+
+    /// Splits a full leaf and moves every shape that fits a quadrant down into it. Piles of
+    /// equal shapes are left alone, since no split could ever separate them.
+    fn subdivide(&mut self) {
+        let first = self.entries[0].0;
+        if self.entries.iter().all(|(shape, _)| *shape == first) {
+            return;
+        }
+
+        self.quadrants = Some(Box::new(
+            split(&self.boundary).map(|quadrant| RegionTree::new(quadrant, self.capacity)),
+        ));
+        for (shape, payload) in std::mem::take(&mut self.entries) {
+            self.insert_within(shape, &shape.bounds(), payload);
+        }
+    }
+
+    fn remove_within(&mut self, shape: &S, bounds: &BoundingBox, payload: T) -> bool {
+        if !self.boundary.contains_box(bounds) {
+            return false;
+        }
+        let position = self
+            .entries
+            .iter()
+            .position(|(candidate, candidate_payload)| {
+                *candidate_payload == payload && candidate == shape
+            });
+        if let Some(position) = position {
+            self.entries.remove(position);
+        } else if !self
+            .quadrants_mut()
+            .any(|quadrant| quadrant.remove_within(shape, bounds, payload))
+        {
+            return false;
+        }
+        self.merge_if_sparse();
+        true
+    }
+
+    /// Pulls the shapes of all quadrants up into this node and drops the quadrants if they
+    /// all fit.
+    fn merge_if_sparse(&mut self) {
+        if self.quadrants.is_none() || self.holds_more_than(self.capacity) {
+            return;
+        }
+        let mut entries = std::mem::take(&mut self.entries);
+        if let Some(quadrants) = self.quadrants.take() {
+            for mut quadrant in *quadrants {
+                quadrant.collect_into(&mut entries);
+            }
+        }
+        self.entries = entries;
+    }
+
+    /// Whether the tree holds more than `limit` shapes. Stops counting once it does, so
+    /// checking a large tree is cheap.
+    fn holds_more_than(&self, limit: usize) -> bool {
+        self.remaining_after(limit).is_none()
+    }
+
+    fn remaining_after(&self, limit: usize) -> Option<usize> {
+        let mut remaining = limit.checked_sub(self.entries.len())?;
+        for quadrant in self.quadrants() {
+            remaining = quadrant.remaining_after(remaining)?;
+        }
+        Some(remaining)
+    }
+
+    fn collect_into(&mut self, found: &mut Vec<(S, T)>) {
+        found.append(&mut self.entries);
+        for quadrant in self.quadrants_mut() {
+            quadrant.collect_into(found);
+        }
+    }
+
+    fn quadrants(&self) -> impl Iterator<Item = &RegionTree<S, T>> {
+        self.quadrants.iter().flat_map(|quadrants| quadrants.iter())
+    }
+
+    fn quadrants_mut(&mut self) -> impl Iterator<Item = &mut RegionTree<S, T>> {
+        self.quadrants
+            .iter_mut()
+            .flat_map(|quadrants| quadrants.iter_mut())
+    }
+}
+
+impl<S: Bounded, T: Copy + Ord> RegionTree<S, T> {
+    /// Returns the shape closest to `point`, or `None` for an empty tree. Points inside a
+    /// shape are at distance zero from it, and ties go to the lowest payload.
+    pub fn nearest(&self, point: &Point) -> Option<(S, T)> {
+        self.nearest_within(point, f64::INFINITY)
+    }
+
+    /// Returns the shape closest to `point` if one is at most `max_distance` away. Ties go
+    /// to the lowest payload.
+    pub fn nearest_within(&self, point: &Point, max_distance: f64) -> Option<(S, T)> {
+        let mut nearest = Nearest {
+            best: None,
+            max_distance,
+        };
+        self.nearest_search(point, &mut nearest);
+        nearest.best.map(|best| (best.shape, best.payload))
+    }
+
+    /// Returns the `k` shapes closest to `point`, closest first. Ties go to the lowest
+    /// payload.
+    pub fn k_nearest(&self, point: &Point, k: usize) -> Vec<(S, T)> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut nearest = KNearest {
+            heap: BinaryHeap::with_capacity(k + 1),
+            k,
+        };
+        self.nearest_search(point, &mut nearest);
+        nearest
+            .heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|neighbor| (neighbor.shape, neighbor.payload))
+            .collect()
+    }
+
+    /// Offers every shape that could still beat the candidates of `search`, visiting the
+    /// quadrants closest to `point` first so the bound tightens early.
+    fn nearest_search(&self, point: &Point, search: &mut dyn NeighborSearch<S, T>) {
+        for &(shape, payload) in &self.entries {
+            search.offer(Neighbor {
+                distance: shape.distance_to_point(point),
+                payload,
+                shape,
+            });
+        }
+
+        if let Some(quadrants) = &self.quadrants {
+            let mut quadrants = quadrants
+                .each_ref()
+                .map(|quadrant| (quadrant.boundary.distance_to(point), quadrant));
+            quadrants.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+            for (distance, quadrant) in quadrants {
+                // Quadrants exactly at the bound may still hold a tie with a lower payload.
+                if distance <= search.bound() {
+                    quadrant.nearest_search(point, search);
+                }
+            }
+        }
+    }
+}
+
+/// Collects the candidates of a nearest-neighbor search.
+trait NeighborSearch<S, T> {
+    fn offer(&mut self, neighbor: Neighbor<S, T>);
+    /// Distance beyond which no shape can be a candidate anymore.
+    fn bound(&self) -> f64;
+}
+
+struct Nearest<S, T> {
+    best: Option<Neighbor<S, T>>,
+    max_distance: f64,
+}
+
+impl<S: Copy, T: Copy + Ord> NeighborSearch<S, T> for Nearest<S, T> {
+    fn offer(&mut self, neighbor: Neighbor<S, T>) {
+        let better = match &self.best {
+            None => neighbor.distance <= self.max_distance,
+            Some(best) => neighbor < *best,
+        };
+        if better {
+            self.best = Some(neighbor);
+        }
+    }
+
+    fn bound(&self) -> f64 {
+        self.best.map_or(self.max_distance, |best| best.distance)
+    }
+}
+
+struct KNearest<S, T> {
+    /// A max-heap of the best candidates so far, whose top is the one to drop next.
+    heap: BinaryHeap<Neighbor<S, T>>,
+    k: usize,
+}
+
+impl<S: Copy, T: Copy + Ord> NeighborSearch<S, T> for KNearest<S, T> {
+    fn offer(&mut self, neighbor: Neighbor<S, T>) {
+        if self.heap.len() < self.k {
+            self.heap.push(neighbor);
+        } else if self.heap.peek().is_some_and(|worst| neighbor < *worst) {
+            self.heap.pop();
+            self.heap.push(neighbor);
+        }
+    }
+
+    fn bound(&self) -> f64 {
+        match self.heap.peek() {
+            Some(worst) if self.heap.len() == self.k => worst.distance,
+            _ => f64::INFINITY,
+        }
+    }
+}
+
+/// A candidate of a nearest-neighbor search, ordered by distance and then by payload.
+#[derive(Debug, Clone, Copy)]
+struct Neighbor<S, T> {
+    distance: f64,
+    payload: T,
+    shape: S,
+}
+
+impl<S, T: Ord> PartialEq for Neighbor<S, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<S, T: Ord> Eq for Neighbor<S, T> {}
+
+impl<S, T: Ord> PartialOrd for Neighbor<S, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S, T: Ord> Ord for Neighbor<S, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.payload.cmp(&other.payload))
+    }
+}
+
+/// Splits `boundary` into its north west, north east, south west and south east quarters.
+fn split(boundary: &BoundingBox) -> [BoundingBox; 4] {
+    let BoundingBox { x, y, .. } = *boundary;
+    let w = boundary.width / 2.0;
+    let h = boundary.height / 2.0;
+    [
+        BoundingBox {
+            x,
+            y,
+            width: w,
+            height: h,
+        },
+        BoundingBox {
+            x: x + w,
+            y,
+            width: w,
+            height: h,
+        },
+        BoundingBox {
+            x,
+            y: y + h,
+            width: w,
+            height: h,
+        },
+        BoundingBox {
+            x: x + w,
+            y: y + h,
+            width: w,
+            height: h,
+        },
+    ]
+}
+
+fn is_finite(bounds: &BoundingBox) -> bool {
+    [bounds.x, bounds.y, bounds.width, bounds.height]
+        .iter()
+        .all(|value| value.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::utils::{rng::rng_from_seed, segment_index::Segment};
+
+    fn boundary(size: f64) -> BoundingBox {
+        BoundingBox {
+            x: 0.0,
+            y: 0.0,
+            width: size,
+            height: size,
+        }
+    }
+
+    fn random_points(count: usize, seed: u64) -> Vec<(Point, usize)> {
+        let mut rng = rng_from_seed(seed);
+        (0..count)
+            .map(|index| (Point::new(rng.gen_range(0.0..20.0), rng.gen_range(0.0..20.0)), index))
+            .collect()
+    }
+
+    fn sorted_entries<S: Bounded>(tree: &RegionTree<S>) -> Vec<(S, usize)> {
+        let mut entries = tree.query_box(&tree.boundary());
+        entries.sort_by_key(|&(_, payload)| payload);
+        entries
+    }
+
+    #[test]
+    fn equal_shapes_do_not_subdivide() {
+        let mut tree = RegionTree::new(boundary(10.0), 4);
+        for payload in 0..1000 {
+            assert!(tree.insert(Point::new(2.5, 2.5), payload));
+        }
+        assert!(tree.quadrants.is_none());
+        assert_eq!(tree.len(), 1000);
+    }
+
+    #[test]
+    fn straddling_shapes_stay_in_the_parent() {
+        let mut tree = RegionTree::new(boundary(20.0), 1);
+        let west = Segment::new(Point::new(2.0, 2.0), Point::new(4.0, 4.0), 1.0);
+        let east = Segment::new(Point::new(16.0, 2.0), Point::new(18.0, 4.0), 1.0);
+        let straddling = Segment::new(Point::new(8.0, 3.0), Point::new(12.0, 3.0), 1.0);
+        for (payload, segment) in [west, east, straddling].into_iter().enumerate() {
+            assert!(tree.insert(segment, payload));
+        }
+        assert_eq!(tree.entries, vec![(straddling, 2)]);
+        assert_eq!(tree.quadrants().map(RegionTree::len).sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn removing_keeps_the_rest_and_merges_empty_quadrants() {
+        let points = random_points(200, 1);
+        let mut tree = RegionTree::from_entries(boundary(20.0), 4, &points);
+        assert!(tree.quadrants.is_some());
+        for &(point, payload) in points.iter().filter(|(_, payload)| payload % 2 == 1) {
+            assert!(tree.remove(&point, payload));
+            assert!(!tree.remove(&point, payload));
+        }
+        let kept: Vec<_> = points
+            .iter()
+            .copied()
+            .filter(|(_, payload)| payload % 2 == 0)
+            .collect();
+        assert_eq!(sorted_entries(&tree), kept);
+
+        for (point, payload) in kept {
+            assert!(tree.remove(&point, payload));
+        }
+        assert!(tree.is_empty());
+        assert!(tree.quadrants.is_none());
+    }
+
+    #[test]
+    fn updates_grow_the_boundary() {
+        let points = random_points(50, 3);
+        let mut tree = RegionTree::from_entries(boundary(20.0), 4, &points);
+        let (old, payload) = points[7];
+        let new = Point::new(-35.0, 70.0);
+        assert!(tree.update(&old, payload, new));
+        assert!(tree.boundary().contains(&new));
+        assert!(!tree.remove(&old, payload));
+
+        let mut moved = points.clone();
+        moved[7].0 = new;
+        assert_eq!(sorted_entries(&tree), moved);
+        assert_eq!(tree.nearest(&Point::new(-30.0, 65.0)), Some((new, payload)));
+
+        assert!(tree.remove(&new, payload));
+        moved.remove(7);
+        assert_eq!(sorted_entries(&tree), moved);
+    }
+
+    #[test]
+    fn remap_renumbers_and_drops_entries() {
+        let points = random_points(120, 4);
+        let mut tree = RegionTree::from_entries(boundary(20.0), 4, &points);
+        tree.remap(&mut |payload| (payload % 3 != 0).then_some(payload + 1000));
+
+        let expected: Vec<_> = points
+            .iter()
+            .filter(|(_, payload)| payload % 3 != 0)
+            .map(|&(point, payload)| (point, payload + 1000))
+            .collect();
+        assert_eq!(sorted_entries(&tree), expected);
+        for &(point, payload) in &expected {
+            assert!(tree.remove(&point, payload));
+        }
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn non_finite_shapes_are_not_stored() {
+        let mut tree = RegionTree::new(boundary(20.0), 4);
+        assert!(!tree.insert(Point::new(1.0, f64::NAN), 0));
+        assert!(!tree.insert(Point::new(f64::INFINITY, 1.0), 1));
+        assert!(tree.is_empty());
+        assert_eq!(tree.boundary().width, 20.0);
+    }
+}
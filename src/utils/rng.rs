@@ -0,0 +1,28 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// The RNG used by every generator. ChaCha is portable and its output is stable across
+/// platforms and crate versions, which `StdRng` does not promise.
+pub type TreeRng = ChaCha8Rng;
+
+/// Mixes `key` into `seed` (splitmix64 finalizer) so that related keys produce unrelated seeds.
+pub fn derive_seed(seed: u64, key: u64) -> u64 {
+    let mut z = seed ^ key.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn rng_from_seed(seed: u64) -> TreeRng {
+    TreeRng::seed_from_u64(seed)
+}
+
+/// Returns an independent RNG stream for `index` under `seed`.
+///
+/// Streams only depend on the seed and the index, never on the order in which they are
+/// created, so work split across threads draws the same numbers however it is scheduled.
+pub fn stream_rng(seed: u64, index: u64) -> TreeRng {
+    let mut rng = TreeRng::seed_from_u64(seed);
+    rng.set_stream(index);
+    rng
+}
@@ -0,0 +1,315 @@
+use crate::skeleton::node::Point;
+
+use super::{
+    geometry::{point_segment_distance, segment_distance, segments_intersect},
+    quadtree::BoundingBox,
+    region_tree::{Bounded, RegionTree},
+};
+
+/// A line segment with a thickness, such as a branch: the set of points at most half the
+/// thickness away from the line from `start` to `end`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start: Point,
+    pub end: Point,
+    pub thickness: f64,
+}
+
+impl Segment {
+    pub fn new(start: Point, end: Point, thickness: f64) -> Self {
+        Self {
+            start,
+            end,
+            thickness,
+        }
+    }
+
+    /// Returns the smallest box covering the segment including its thickness.
+    pub fn bounds(&self) -> BoundingBox {
+        let radius = self.radius();
+        BoundingBox {
+            x: self.start.x.min(self.end.x) - radius,
+            y: self.start.y.min(self.end.y) - radius,
+            width: (self.start.x - self.end.x).abs() + 2.0 * radius,
+            height: (self.start.y - self.end.y).abs() + 2.0 * radius,
+        }
+    }
+
+    /// Returns the distance from `point` to the surface of the segment, zero inside it.
+    pub fn distance_to_point(&self, point: &Point) -> f64 {
+        (point_segment_distance(*point, self.start, self.end) - self.radius()).max(0.0)
+    }
+
+    /// Returns the distance from the surface of the segment to the line from `start` to
+    /// `end`, zero where the line runs through the segment.
+    pub fn distance_to_line(&self, start: Point, end: Point) -> f64 {
+        (segment_distance(self.start, self.end, start, end) - self.radius()).max(0.0)
+    }
+
+    /// Returns the distance from the surface of the segment to `range`, zero where they
+    /// overlap.
+    pub fn distance_to_box(&self, range: &BoundingBox) -> f64 {
+        let corners = [
+            Point::new(range.x, range.y),
+            Point::new(range.x + range.width, range.y),
+            Point::new(range.x + range.width, range.y + range.height),
+            Point::new(range.x, range.y + range.height),
+        ];
+        let crosses_edge = (0..4)
+            .any(|i| segments_intersect(self.start, self.end, corners[i], corners[(i + 1) % 4]));
+        let axis_distance = if crosses_edge {
+            0.0
+        } else {
+            // The box is convex, so outside of it the closest points are an end of the
+            // segment or a corner of the box.
+            corners
+                .iter()
+                .map(|&corner| point_segment_distance(corner, self.start, self.end))
+                .fold(range.distance_to(&self.start), f64::min)
+                .min(range.distance_to(&self.end))
+        };
+        (axis_distance - self.radius()).max(0.0)
+    }
+
+    fn radius(&self) -> f64 {
+        self.thickness.max(0.0) / 2.0
+    }
+}
+
+impl Bounded for Segment {
+    fn bounds(&self) -> BoundingBox {
+        Segment::bounds(self)
+    }
+
+    fn distance_to_point(&self, point: &Point) -> f64 {
+        Segment::distance_to_point(self, point)
+    }
+
+    fn overlaps_box(&self, range: &BoundingBox) -> bool {
+        self.distance_to_box(range) <= 0.0
+    }
+}
+
+/// A region quadtree of thick segments that each carry a payload, node indices by default.
+pub type SegmentIndex<T = usize> = RegionTree<Segment, T>;
+
+impl<T: Copy + PartialEq> RegionTree<Segment, T> {
+    /// Calls `visitor` with every segment whose body overlaps the capsule of `radius` around
+    /// the line from `start` to `end`, without allocating.
+    pub fn visit_capsule(
+        &self,
+        start: Point,
+        end: Point,
+        radius: f64,
+        visitor: &mut dyn FnMut(&Segment, T),
+    ) {
+        let capsule = Segment::new(start, end, 2.0 * radius);
+        self.visit_candidates(&capsule.bounds(), &mut |segment, payload| {
+            if segment.distance_to_line(start, end) <= capsule.radius() {
+                visitor(segment, payload);
+            }
+        });
+    }
+
+    /// Returns every segment whose body overlaps the capsule of `radius` around the line
+    /// from `start` to `end`, in no particular order.
+    pub fn query_capsule(&self, start: Point, end: Point, radius: f64) -> Vec<(Segment, T)> {
+        let mut found = Vec::new();
+        self.visit_capsule(start, end, radius, &mut |segment, payload| {
+            found.push((*segment, payload))
+        });
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+    use rand::Rng;
+
+    use super::*;
+    use crate::utils::rng::rng_from_seed;
+
+    /// Returns the distance between the segments from `a0` to `a1` and from `b0` to `b1` by
+    /// minimizing over both segment parameters, independently of [`segment_distance`].
+    fn closest_approach(a0: Point, a1: Point, b0: Point, b1: Point) -> f64 {
+        let (d1, d2, r) = (a1 - a0, b1 - b0, a0 - b0);
+        let (a, e, f) = (d1.norm_squared(), d2.norm_squared(), d2.dot(&r));
+        let (s, t) = if a == 0.0 && e == 0.0 {
+            (0.0, 0.0)
+        } else if a == 0.0 {
+            (0.0, (f / e).clamp(0.0, 1.0))
+        } else {
+            let c = d1.dot(&r);
+            if e == 0.0 {
+                ((-c / a).clamp(0.0, 1.0), 0.0)
+            } else {
+                let b = d1.dot(&d2);
+                let denom = a * e - b * b;
+                let s = if denom > 0.0 {
+                    ((b * f - c * e) / denom).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let t = (b * s + f) / e;
+                if t < 0.0 {
+                    ((-c / a).clamp(0.0, 1.0), 0.0)
+                } else if t > 1.0 {
+                    (((b - c) / a).clamp(0.0, 1.0), 1.0)
+                } else {
+                    (s, t)
+                }
+            }
+        };
+        ((a0 + d1 * s) - (b0 + d2 * t)).norm()
+    }
+
+    /// Whether the line from `start` to `end` runs through `range`, by Liang-Barsky
+    /// clipping.
+    fn clips(start: Point, end: Point, range: &BoundingBox) -> bool {
+        let direction = end - start;
+        let (mut enter, mut exit) = (0.0f64, 1.0f64);
+        for (delta, low, high) in [
+            (direction.x, range.x - start.x, range.x + range.width - start.x),
+            (direction.y, range.y - start.y, range.y + range.height - start.y),
+        ] {
+            if delta == 0.0 {
+                if low > 0.0 || high < 0.0 {
+                    return false;
+                }
+                continue;
+            }
+            let (near, far) = if delta > 0.0 {
+                (low / delta, high / delta)
+            } else {
+                (high / delta, low / delta)
+            };
+            enter = enter.max(near);
+            exit = exit.min(far);
+        }
+        enter <= exit
+    }
+
+    /// Whether the body of `segment` overlaps `range`: either its line runs through the box
+    /// or it comes within its radius of one of the box edges.
+    fn overlaps(segment: &Segment, range: &BoundingBox) -> bool {
+        let corners = [
+            Point::new(range.x, range.y),
+            Point::new(range.x + range.width, range.y),
+            Point::new(range.x + range.width, range.y + range.height),
+            Point::new(range.x, range.y + range.height),
+        ];
+        clips(segment.start, segment.end, range)
+            || (0..4).any(|i| {
+                closest_approach(segment.start, segment.end, corners[i], corners[(i + 1) % 4])
+                    <= segment.thickness / 2.0
+            })
+    }
+
+    /// Short segments with random ends, so no query lands exactly on a segment's surface.
+    fn random_segments(count: usize, seed: u64) -> Vec<(Segment, usize)> {
+        let mut rng = rng_from_seed(seed);
+        (0..count)
+            .map(|payload| {
+                let start = Point::new(rng.gen_range(0.0..20.0), rng.gen_range(0.0..20.0));
+                let end =
+                    start + Vector2::new(rng.gen_range(-3.0..3.0), rng.gen_range(-3.0..3.0));
+                let thickness = rng.gen_range(0.0..2.0);
+                (Segment::new(start, end, thickness), payload)
+            })
+            .collect()
+    }
+
+    fn payloads(entries: impl IntoIterator<Item = (Segment, usize)>) -> Vec<usize> {
+        let mut payloads: Vec<_> = entries.into_iter().map(|(_, payload)| payload).collect();
+        payloads.sort_unstable();
+        payloads
+    }
+
+    fn index_of(segments: &[(Segment, usize)]) -> SegmentIndex {
+        let boundary = BoundingBox {
+            x: 0.0,
+            y: 0.0,
+            width: 20.0,
+            height: 20.0,
+        };
+        SegmentIndex::from_entries(boundary, 4, segments)
+    }
+
+    #[test]
+    fn box_queries_find_the_segments_overlapping_the_box() {
+        let segments = random_segments(300, 1);
+        let index = index_of(&segments);
+        let mut rng = rng_from_seed(2);
+        for _ in 0..100 {
+            let range = BoundingBox {
+                x: rng.gen_range(-5.0..25.0),
+                y: rng.gen_range(-5.0..25.0),
+                width: rng.gen_range(0.0..6.0),
+                height: rng.gen_range(0.0..6.0),
+            };
+            let expected = segments
+                .iter()
+                .copied()
+                .filter(|(segment, _)| overlaps(segment, &range));
+            assert_eq!(payloads(index.query_box(&range)), payloads(expected), "{range:?}");
+        }
+    }
+
+    #[test]
+    fn thin_boxes_catch_the_segments_they_cross() {
+        let crossing = Segment::new(Point::new(0.0, 5.0), Point::new(10.0, 5.0), 0.0);
+        let beside = Segment::new(Point::new(0.0, 7.0), Point::new(10.0, 7.0), 1.0);
+        let index = index_of(&[(crossing, 0), (beside, 1)]);
+        let line = BoundingBox {
+            x: 4.0,
+            y: 0.0,
+            width: 0.0,
+            height: 6.0,
+        };
+        assert_eq!(index.query_box(&line), vec![(crossing, 0)]);
+    }
+
+    #[test]
+    fn capsule_queries_find_the_segments_within_reach() {
+        let segments = random_segments(300, 3);
+        let index = index_of(&segments);
+        let mut rng = rng_from_seed(4);
+        for _ in 0..100 {
+            let start = Point::new(rng.gen_range(-5.0..25.0), rng.gen_range(-5.0..25.0));
+            let end = start + Vector2::new(rng.gen_range(-6.0..6.0), rng.gen_range(-6.0..6.0));
+            let radius = rng.gen_range(0.0..2.0);
+            let expected = segments.iter().copied().filter(|(segment, _)| {
+                closest_approach(segment.start, segment.end, start, end)
+                    <= radius + segment.thickness / 2.0
+            });
+            assert_eq!(
+                payloads(index.query_capsule(start, end, radius)),
+                payloads(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn nearest_segments_are_measured_to_their_surface() {
+        let segments = random_segments(300, 5);
+        let index = index_of(&segments);
+        let mut rng = rng_from_seed(6);
+        for _ in 0..100 {
+            let point = Point::new(rng.gen_range(-5.0..25.0), rng.gen_range(-5.0..25.0));
+            let surface_distance = |segment: &Segment| {
+                (closest_approach(segment.start, segment.end, point, point)
+                    - segment.thickness / 2.0)
+                    .max(0.0)
+            };
+            let nearest = segments
+                .iter()
+                .copied()
+                .min_by(|a, b| surface_distance(&a.0).total_cmp(&surface_distance(&b.0)));
+            let expected = surface_distance(&nearest.unwrap().0);
+            let found = index.nearest(&point).unwrap();
+            assert!((surface_distance(&found.0) - expected).abs() < 1e-9);
+            assert_eq!(index.nearest_within(&point, 0.5).is_some(), expected <= 0.5);
+        }
+    }
+}